@@ -0,0 +1,317 @@
+use std::{borrow::Cow, collections::BTreeMap, io::{stdout, Write}, path::{Path, PathBuf}};
+
+use clap::{CommandFactory, Subcommand, ValueEnum};
+use serde::Serialize;
+use clap_complete::Shell;
+use rayon::prelude::*;
+use smix::{montage::Montage, stats::RegionStats, sweep::Sweep, ExportOptions, GeneratedImage, Mask};
+use smix_runner::summary::{Problem, Summary};
+
+use crate::{config::Config, Cli, Filter};
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Target shell
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page (roff) to stdout
+    Man,
+    /// Export every weight combination summing to 1 and a contact sheet of them
+    Sweep {
+        /// Weight granularity: each weight is a multiple of 1/STEPS
+        #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=20))]
+        steps: u32,
+        /// Directory containing r.png, g.png, b.png
+        #[arg(short, long, value_delimiter = ' ', num_args = 1.., required = true)]
+        mask_directories: Vec<PathBuf>,
+        /// Output directory
+        #[arg(short, long, default_value = "output")]
+        output: PathBuf,
+        /// Width of one contact sheet cell in pixels
+        #[arg(long, default_value_t = 128)]
+        cell: u32,
+        /// Resize filter for the contact sheet
+        #[arg(short, long, value_enum, default_value_t = Filter::Lanczos3)]
+        filter: Filter,
+    },
+    /// Run the jobs of a pipeline file (TOML)
+    Run {
+        /// Pipeline file
+        pipeline: PathBuf,
+    },
+    /// Lay several results out on one labeled contact sheet
+    Montage {
+        /// Directory containing r.png, g.png, b.png
+        #[arg(short, long, value_delimiter = ' ', num_args = 1.., required = true)]
+        mask_directories: Vec<PathBuf>,
+        /// RGB weights of one result, e.g. 1,0.15,0.04; repeatable
+        #[arg(short, long = "weight", value_parser = parse_weight)]
+        weights: Vec<[f32; 3]>,
+        /// Named preset from smix.toml; repeatable
+        #[arg(long = "preset")]
+        presets: Vec<String>,
+        /// Output image
+        #[arg(short, long, default_value = "montage.png")]
+        output: PathBuf,
+        /// Cells per row
+        #[arg(long, default_value_t = 4)]
+        columns: u32,
+        /// Width of one cell in pixels
+        #[arg(long, default_value_t = 128)]
+        cell: u32,
+        /// Label font size multiplier
+        #[arg(long, default_value_t = 1)]
+        label_scale: u32,
+    },
+    /// Report the mean color, spread and coverage of each mask region of a result
+    Stats {
+        /// Directory containing r.png, g.png, b.png
+        #[arg(short, long, value_delimiter = ' ', num_args = 1.., required = true)]
+        mask_directories: Vec<PathBuf>,
+        /// RGB weights, e.g. 1,0.15,0.04
+        #[arg(short, long, value_parser = parse_weight, required_unless_present = "preset", conflicts_with = "preset")]
+        weight: Option<[f32; 3]>,
+        /// Named preset from smix.toml instead of --weight
+        #[arg(long)]
+        preset: Option<String>,
+        /// Report format
+        #[arg(long, value_enum, default_value_t = StatsFormat::Json)]
+        format: StatsFormat,
+        /// Write the report to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check the outputs listed in a manifest.json (see --manifest) against their recorded SHA-256
+    Verify {
+        /// manifest.json in the output directory
+        manifest: PathBuf,
+    },
+    /// Find the weights that bring each mask region's mean color closest to a target
+    Target {
+        /// Directory containing r.png, g.png, b.png
+        #[arg(short, long, value_delimiter = ' ', num_args = 1.., required = true)]
+        mask_directories: Vec<PathBuf>,
+        /// Target color of the red mask's region, e.g. "#c83228"
+        #[arg(long, value_name = "HEX", value_parser = parse_hex, required_unless_present_any = ["green", "blue"])]
+        red: Option<[f32; 3]>,
+        /// Target color of the green mask's region
+        #[arg(long, value_name = "HEX", value_parser = parse_hex)]
+        green: Option<[f32; 3]>,
+        /// Target color of the blue mask's region
+        #[arg(long, value_name = "HEX", value_parser = parse_hex)]
+        blue: Option<[f32; 3]>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum StatsFormat {
+    Json,
+    Csv,
+}
+
+/// One row of the `stats` report.
+#[derive(Serialize)]
+struct RegionRow<'a> {
+    mask: &'a str,
+    region: &'a str,
+    coverage: f64,
+    area: f64,
+    mean: [f32; 3],
+    mean_hex: String,
+    stddev: [f32; 3],
+}
+
+/// Presets of the smix.toml in effect, if any.
+fn config_presets() -> anyhow::Result<BTreeMap<String, [f32; 3]>> {
+    Ok(match Config::path() {
+        Some(path) => Config::load(&path)?.presets,
+        None => BTreeMap::new(),
+    })
+}
+
+/// Parse `R,G,B` weights.
+fn parse_weight(s: &str) -> Result<[f32; 3], String> {
+    let parts: Vec<f32> = s.split(',')
+        .map(|v| v.trim().parse::<f32>().map_err(|e| format!("{v:?}: {e}")))
+        .collect::<Result<_, _>>()?;
+    parts.try_into().map_err(|_| format!("Expected R,G,B, got {s:?}"))
+}
+
+/// Parse `#rrggbb` (the `#` is optional) into 0.0~1.0 components.
+fn parse_hex(s: &str) -> Result<[f32; 3], String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("Expected #rrggbb, got {s:?}"));
+    }
+    let mut rgb = [0.0; 3];
+    for (i, value) in rgb.iter_mut().enumerate() {
+        let byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|e| format!("{s:?}: {e}"))?;
+        *value = byte as f32 / 255.0;
+    }
+    Ok(rgb)
+}
+
+/// Write `bytes` to stdout; a reader that stopped early, like `head`, is no
+/// error.
+fn print_piped(bytes: &[u8]) -> std::io::Result<()> {
+    let mut out = stdout().lock();
+    match out.write_all(bytes).and_then(|()| out.flush()) {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        written => written,
+    }
+}
+
+/// Quote a CSV field if needed.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+impl Command {
+    pub fn run(self) -> anyhow::Result<()> {
+        match self {
+            Command::Completions { shell } => {
+                let mut script = Vec::new();
+                clap_complete::generate(shell, &mut Cli::command(), "smix", &mut script);
+                print_piped(&script)?;
+            }
+            Command::Man => {
+                let mut page = Vec::new();
+                clap_mangen::Man::new(Cli::command()).render(&mut page)?;
+                print_piped(&page)?;
+            }
+            Command::Sweep { steps, mask_directories, output, cell, filter } => {
+                std::fs::create_dir_all(&output)?;
+                let sweep = Sweep { steps };
+                let options = ExportOptions { filter: filter.into(), ..Default::default() };
+                for dir in &mask_directories {
+                    let name = dir.file_name().map_or("result".into(), |name| name.to_string_lossy());
+                    let mask = Mask::new(dir)?;
+                    let (width, height) = mask.dimensions();
+                    let images: Vec<GeneratedImage> = sweep.weights().par_iter()
+                        .map(|weight| {
+                            let image = mask.generate(weight);
+                            let [r, g, b] = weight;
+                            image.export(output.join(format!("{name}_sweep_{r}_{g}_{b}.png")), width, height, &options)?;
+                            Ok(image)
+                        })
+                        .collect::<anyhow::Result<_>>()?;
+                    let cell = (cell, (cell as u64 * height as u64 / width.max(1) as u64).max(1) as u32);
+                    let sheet = sweep.contact_sheet(&images, cell, options.filter)?;
+                    let path = output.join(format!("{name}_sweep.png"));
+                    sheet.save(&path)?;
+                    println!("Generated {} ({} weights)", path.display(), images.len());
+                }
+            }
+            Command::Run { pipeline } => crate::pipeline::run(&pipeline)?,
+            Command::Montage { mask_directories, mut weights, presets, output, columns, cell, label_scale } => {
+                let mut labels: Vec<String> = weights.iter().map(|[r, g, b]| format!("{r} {g} {b}")).collect();
+                if !presets.is_empty() {
+                    let config_presets = config_presets()?;
+                    for preset in presets {
+                        let weight = config_presets.get(&preset)
+                            .ok_or_else(|| anyhow::anyhow!("Unknown preset {preset:?}"))?;
+                        weights.push(*weight);
+                        labels.push(preset);
+                    }
+                }
+                anyhow::ensure!(!weights.is_empty(), "Give at least one --weight or --preset");
+
+                let mut images = Vec::new();
+                for dir in &mask_directories {
+                    let name = dir.file_name().map_or("result".into(), |name| name.to_string_lossy());
+                    let mask = Mask::new(dir)?;
+                    let generated: Vec<_> = weights.par_iter().map(|weight| mask.generate(weight)).collect();
+                    images.extend(generated.into_iter().zip(&labels).map(|(image, label)| (image, format!("{name} {label}"))));
+                }
+                let tiles: Vec<_> = images.iter().map(|(image, label)| (image, label.as_str())).collect();
+                let montage = Montage { columns, cell_width: cell, label_scale, ..Default::default() };
+                montage.render(&tiles)?.save(&output)?;
+                println!("Generated {} ({} images)", output.display(), tiles.len());
+            }
+            Command::Stats { mask_directories, weight, preset, format, output } => {
+                let weight = match (weight, preset) {
+                    (Some(weight), _) => weight,
+                    (None, Some(preset)) => *config_presets()?.get(&preset)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown preset {preset:?}"))?,
+                    (None, None) => unreachable!("clap requires --weight or --preset"),
+                };
+                let mut report = Vec::new();
+                for dir in &mask_directories {
+                    let name = dir.file_name().map_or("result".into(), |name| name.to_string_lossy());
+                    let mask = Mask::new(dir)?;
+                    let stats = mask.region_stats(&mask.generate(&weight))?;
+                    report.extend(stats.iter().zip(["r", "g", "b"]).map(|(stats, region)| (name.clone(), region, *stats)));
+                }
+                let rows: Vec<_> = report.iter().map(|(mask, region, stats)| RegionRow {
+                    mask,
+                    region,
+                    coverage: stats.coverage,
+                    area: stats.area,
+                    mean: stats.mean,
+                    mean_hex: stats.mean_hex(),
+                    stddev: stats.stddev,
+                }).collect();
+                let text = match format {
+                    StatsFormat::Json => serde_json::to_string_pretty(&rows)? + "\n",
+                    StatsFormat::Csv => {
+                        let mut csv = String::from("mask,region,coverage,area,mean_r,mean_g,mean_b,mean_hex,stddev_r,stddev_g,stddev_b\n");
+                        for row in &rows {
+                            let [mr, mg, mb] = row.mean;
+                            let [sr, sg, sb] = row.stddev;
+                            csv += &format!(
+                                "{},{},{},{},{mr},{mg},{mb},{},{sr},{sg},{sb}\n",
+                                csv_field(row.mask), row.region, row.coverage, row.area, row.mean_hex,
+                            );
+                        }
+                        csv
+                    }
+                };
+                match output {
+                    Some(path) => std::fs::write(&path, text)?,
+                    None => stdout().write_all(text.as_bytes())?,
+                }
+            }
+            Command::Verify { manifest } => {
+                let summary = Summary::load(&manifest)?;
+                let dir = manifest.parent().unwrap_or(Path::new("."));
+                let problems = summary.verify(dir);
+                for (entry, problem) in &problems {
+                    match problem {
+                        Problem::Missing => println!("missing  {}", entry.file),
+                        Problem::Changed => println!("changed  {}", entry.file),
+                    }
+                }
+                anyhow::ensure!(problems.is_empty(), "{} of {} outputs failed verification", problems.len(), summary.outputs.len());
+                println!("Verified {} outputs", summary.outputs.len());
+            }
+            Command::Target { mask_directories, red, green, blue } => {
+                let targets = [red, green, blue];
+                for dir in &mask_directories {
+                    let mask = Mask::new(dir)?;
+                    let weight = mask.fit_weights(&targets);
+                    let stats = mask.region_stats(&mask.generate(&weight))?;
+                    let [r, g, b] = weight.map(|w| (w * 1000.0).round() / 1000.0);
+                    println!("{}: smix {r} {g} {b}", dir.display());
+                    for ((region, target), stats) in ["r", "g", "b"].iter().zip(targets).zip(stats) {
+                        let Some(target) = target else { continue };
+                        let error = target.iter().zip(stats.mean)
+                            .map(|(t, m)| (t - m) * 255.0)
+                            .map(|d| d * d)
+                            .sum::<f32>()
+                            .sqrt();
+                        let target = RegionStats { mean: target, ..Default::default() }.mean_hex();
+                        println!("  {region}: target {target}, got {} (error {error:.1})", stats.mean_hex());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
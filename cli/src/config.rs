@@ -0,0 +1,84 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use clap::{parser::ValueSource, ArgMatches};
+use serde::Deserialize;
+
+use crate::{Args, Filter, Format};
+
+/// File name looked up in the working directory, then in the user config dir.
+pub const CONFIG_FILE: &str = "smix.toml";
+
+/// Project defaults read from `smix.toml`. Every key is optional;
+/// flags given on the command line always win.
+///
+/// ```toml
+/// output = "results"
+/// filter = "catmull-rom"
+/// format = "png"
+/// name-template = "{mask}_{width}x{height}"
+/// scale = [2.0, 0.5]
+/// mask-directories = ["masks/attack", "masks/skill"]
+///
+/// [presets]
+/// attack = [1.0, 0.15, 0.04]
+/// skill = [0.1, 0.8, 0.2]
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub output: Option<PathBuf>,
+    pub filter: Option<Filter>,
+    pub format: Option<Format>,
+    pub name_template: Option<String>,
+    pub scale: Option<Vec<f32>>,
+    pub mask_directories: Option<Vec<PathBuf>>,
+    /// Named weights, offered in the preview
+    pub presets: BTreeMap<String, [f32; 3]>,
+}
+
+impl Config {
+    /// `./smix.toml` first, then `<config dir>/smix/smix.toml` (`$XDG_CONFIG_HOME` on Linux).
+    pub fn path() -> Option<PathBuf> {
+        let local = PathBuf::from(CONFIG_FILE);
+        if local.is_file() {
+            return Some(local);
+        }
+        dirs::config_dir()
+            .map(|dir| dir.join("smix").join(CONFIG_FILE))
+            .filter(|path| path.is_file())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid config {}: {e}", path.display()))
+    }
+}
+
+/// Whether `id` was left to its default, i.e. not set explicitly by the user.
+fn is_default(matches: &ArgMatches, id: &str) -> bool {
+    !matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+}
+
+impl Args {
+    pub fn apply_config(&mut self, config: Config, matches: &ArgMatches) {
+        if let Some(output) = config.output.filter(|_| is_default(matches, "output")) {
+            self.output = output;
+        }
+        if let Some(filter) = config.filter.filter(|_| is_default(matches, "filter")) {
+            self.filter = filter;
+        }
+        if let Some(format) = config.format.filter(|_| is_default(matches, "format")) {
+            self.format = vec![format];
+        }
+        if let Some(template) = config.name_template.filter(|_| is_default(matches, "name_template")) {
+            self.name_template = template;
+        }
+        if let Some(scale) = config.scale.filter(|_| is_default(matches, "scale")) {
+            self.scale = scale;
+        }
+        if let Some(dirs) = config.mask_directories.filter(|_| is_default(matches, "mask_directories")) {
+            self.mask_directories = dirs;
+        }
+    }
+}
@@ -1,69 +1,1003 @@
 use core::f32;
-use std::{collections::HashMap, path::PathBuf};
+use std::{borrow::Cow, collections::{BTreeMap, HashMap}, path::{Path, PathBuf}, thread::JoinHandle, time::{Duration, Instant}};
 
+use clap::ValueEnum;
 use eframe::egui::{self, Slider};
-use image::{imageops, RgbaImage};
+use image::RgbaImage;
+use indexmap::{IndexMap, IndexSet};
 use rfd::FileDialog;
-use smix::Mask;
+use smix::{adjust::{self, WhiteBalance}, cancel::{CancelToken, Cancelled}, colorblind::ColorBlindness, naming::{self, NameFields}, post::{Registry, Sharpen}, ExportOptions, GeneratedImage, Levels, Mask, MixSemantics};
+use smix_runner::{Runner, Settings};
+
+use crate::i18n::tr;
+use crate::{Filter, Mix};
+use crate::perf::Timings;
+use crate::project::{self, ExportSettings, LoadSettings, Project, ProjectMask};
+use crate::report;
+use crate::settings::GuiSettings;
+use crate::watch::MaskWatcher;
+
+/// How long a toast notification stays on screen.
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+/// How many exports the recent exports panel lists.
+const RECENT_EXPORTS: usize = 8;
+
+/// Show `path` in the platform's file manager, selected where the file
+/// manager supports it, otherwise by opening its folder.
+fn reveal(path: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = std::process::Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    };
+    #[cfg(windows)]
+    let mut command = {
+        use std::os::windows::process::CommandExt;
+        let mut command = std::process::Command::new("explorer");
+        // explorer parses its own arguments; the path must be quoted after the comma
+        command.raw_arg(format!("/select,\"{}\"", path.display()));
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = {
+        let mut command = std::process::Command::new("xdg-open");
+        command.arg(path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")));
+        command
+    };
+    let mut child = command.spawn()?;
+    // Reap it so it doesn't linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Exponent of the perceptual slider response: weight = position ^ `SLIDER_GAMMA`.
+const SLIDER_GAMMA: f32 = 2.2;
+
+/// Slider for one 0-1 weight, or -1-1 if `negative`. The perceptual
+/// response shows the slider position and the raw weight it maps to.
+fn weight_slider(ui: &mut egui::Ui, weight: &mut f32, label: &str, perceptual: bool, negative: bool) {
+    let range = if negative { -1.0..=1.0 } else { 0.0..=1.0 };
+    if !perceptual {
+        ui.add(Slider::new(weight, range).text(label).step_by(0.01));
+        return;
+    }
+    ui.horizontal(|ui| {
+        let mut position = weight.abs().powf(1.0 / SLIDER_GAMMA).copysign(*weight);
+        if ui.add(Slider::new(&mut position, range).text(label).step_by(0.01)).changed() {
+            *weight = position.abs().powf(SLIDER_GAMMA).copysign(position);
+        }
+        ui.weak(format!("{} {weight:.3}", tr("raw")));
+    });
+}
+
+/// A fresh seed for "Randomize".
+fn time_seed() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos() ^ d.as_secs() as u32)
+}
+
+/// Weights derived from `seed` by splitmix64, rounded to the sliders' 0.01
+/// steps so they can be typed on the command line as shown.
+fn random_weight(seed: u32, normalize: bool) -> [f32; 3] {
+    let mut state = seed as u64;
+    let mut next = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        ((z ^ (z >> 31)) >> 40) as f32 / (1u64 << 24) as f32
+    };
+    let mut weight = [next(), next(), next()];
+    let sum: f32 = weight.iter().sum();
+    if normalize && sum > 0.0 {
+        weight = weight.map(|w| w / sum);
+    }
+    weight.map(|w| (w * 100.0).round() / 100.0)
+}
+
+/// `arg` as one POSIX shell word.
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./,=:+".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Command line spelling of `value`, e.g. `catmull-rom`.
+fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string())
+}
 
 #[derive(Clone, PartialEq)]
 struct Args {
     pub weight: [f32; 3],
+    /// Whether `weight` is used as is or scaled to add up to 1
+    pub mix: Mix,
+    /// Gain and bias of the channel masks
+    pub levels: Levels,
     pub scale: f32,
     pub key: String,
+    /// Preview-only color vision deficiency simulation
+    pub simulate: Option<ColorBlindness>,
+    /// Resize filter of the preview and the export
+    pub filter: Filter,
+    /// Temperature and tint of the preview and the export
+    pub white_balance: WhiteBalance,
+    /// Side of the square preview in physical pixels, following the panel size
+    pub preview_size: u32,
 }
 
 impl Args {
     pub fn new(weight: [f32; 3], default_key: String) -> Self {
         Self {
             weight,
+            mix: Mix::Sum,
+            levels: Levels::default(),
             scale: 1.0,
-            key: default_key
+            key: default_key,
+            simulate: None,
+            filter: Filter::Lanczos3,
+            white_balance: WhiteBalance::default(),
+            preview_size: 256,
         }
     }
 }
 
+/// Most panes pinned next to the live preview, filling a 2×2 grid.
+const MAX_PANES: usize = 3;
+
+/// A view pinned next to the live preview: another mask, or the same mask
+/// with other weights. Every other setting follows the live preview.
+struct Pane {
+    key: String,
+    weight: [f32; 3],
+    tex: Option<egui::TextureHandle>,
+    /// What `tex` shows
+    rendered: Option<Args>,
+}
+
+impl Pane {
+    fn args(&self, current: &Args) -> Args {
+        Args { key: self.key.clone(), weight: self.weight, ..current.clone() }
+    }
+}
+
 pub struct PreView {
-    masks: HashMap<String, Mask>,
+    masks: IndexMap<String, Mask>,
+    paths: IndexMap<String, PathBuf>,
+    /// Full size of the masks that are downscaled proxies; exports load
+    /// these from `paths` again
+    full_sizes: HashMap<String, (u32, u32)>,
+    /// The command line's settings: every mask set is loaded with its channel
+    /// map, missing channel fallback and PSD layers, see [`Runner::load_mask_set`]
+    load_settings: Settings,
+    presets: BTreeMap<String, [f32; 3]>,
+    /// Masks Ctrl+clicked in the list, always including `current.key`;
+    /// weight changes and exports apply to all of them
+    selection: IndexSet<String>,
+    /// Name typed for a new preset
+    preset_name: String,
+    /// Presets blended by "Blend presets" and the position between them
+    blend: (Option<String>, Option<String>, f32),
+    /// Weights given on the command line, restored by "Reset"
+    cli_weight: [f32; 3],
+    /// Weights of each mask that was tuned; the shown mask's live in
+    /// `current.weight` and are stored here when they change
+    weights: HashMap<String, [f32; 3]>,
+    /// Seed of the last randomized weights, editable to reproduce them
+    seed: u32,
+    /// Randomized weights sum to 1
+    normalize_random: bool,
     tex: Option<egui::TextureHandle>,
+    /// Views shown in a grid along with the live preview
+    panes: Vec<Pane>,
     current: Args,
     last: Args,
+    sharpen: Option<Sharpen>,
+    registry: Registry,
+    /// Names of the post-processing steps applied on export
+    post: Vec<String>,
+    /// Export file name template, see [`smix::naming`]
+    name_template: String,
+    /// `--output` of the command line, for "Copy command"
+    output: PathBuf,
+    /// Weight sliders go down to -1, see `--allow-negative`
+    allow_negative: bool,
+    /// Mask sets that failed to load and their errors, until retried successfully
+    load_errors: Vec<(PathBuf, String)>,
+    settings: GuiSettings,
+    settings_open: bool,
+    watcher: Option<MaskWatcher>,
+    ctx: Option<egui::Context>,
+    toast: Option<(String, Instant)>,
+    /// Kept open once used: on X11 the copied image is gone when it's dropped
+    clipboard: Option<arboard::Clipboard>,
+    /// Shown over the preview with the `performance_overlay` setting
+    timings: Timings,
+    /// Export running in the background, until it finishes or is cancelled
+    export: Option<(JoinHandle<anyhow::Result<Vec<PathBuf>>>, CancelToken)>,
+    /// Files exported from this window, newest first
+    recent_exports: Vec<PathBuf>,
+    /// Created when the node editor is first opened
+    #[cfg(feature = "node-editor")]
+    nodes: Option<crate::nodes::NodeEditor>,
+    #[cfg(feature = "node-editor")]
+    nodes_open: bool,
 }
 
 impl PreView {
-    pub fn new(weight: [f32; 3], masks: HashMap<String, Mask>) -> Self {
+    pub fn new(
+        weight: [f32; 3],
+        masks: IndexMap<String, Mask>,
+        paths: IndexMap<String, PathBuf>,
+        presets: BTreeMap<String, [f32; 3]>,
+        name_template: String,
+    ) -> Self {
         let init = Args::new(weight, masks.iter().next().map(|(s, _)| s.clone()).unwrap());
         Self {
             masks,
+            paths,
+            full_sizes: HashMap::new(),
+            load_settings: Settings::default(),
+            selection: IndexSet::from([init.key.clone()]),
+            presets,
+            preset_name: String::new(),
+            blend: (None, None, 0.5),
+            cli_weight: weight,
+            weights: HashMap::new(),
+            seed: 0,
+            normalize_random: false,
             tex: None,
+            panes: Vec::new(),
             current: init,
             last: Args::new([0.0, 0.0, 0.0], "".into()),
+            sharpen: None,
+            registry: Registry::with_builtins(),
+            post: Vec::new(),
+            name_template,
+            output: PathBuf::from("output"),
+            allow_negative: false,
+            load_errors: Vec::new(),
+            settings: GuiSettings::load(),
+            settings_open: false,
+            watcher: None,
+            ctx: None,
+            toast: None,
+            clipboard: None,
+            timings: Timings::default(),
+            export: None,
+            recent_exports: Vec::new(),
+            #[cfg(feature = "node-editor")]
+            nodes: None,
+            #[cfg(feature = "node-editor")]
+            nodes_open: false,
+        }
+    }
+
+    /// Offer the steps of `registry` on export, with `post` checked.
+    pub fn with_post(mut self, registry: Registry, post: Vec<String>) -> Self {
+        self.registry = registry;
+        self.post = post;
+        self
+    }
+
+    /// Resize previews and exports with `filter`.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.current.filter = filter;
+        self
+    }
+
+    /// Mix by weighted sum or average.
+    pub fn with_mix(mut self, mix: Mix) -> Self {
+        self.current.mix = mix;
+        self
+    }
+
+    /// Correct the channel masks by `levels` before mixing.
+    pub fn with_levels(mut self, levels: Levels) -> Self {
+        self.current.levels = levels;
+        self
+    }
+
+    /// Warm or cool the mixes by `white_balance`.
+    pub fn with_white_balance(mut self, white_balance: WhiteBalance) -> Self {
+        self.current.white_balance = white_balance;
+        self
+    }
+
+    /// Load imported, reloaded and exported mask sets with `settings`, as the
+    /// command line loaded the first ones.
+    pub fn with_load_settings(mut self, settings: Settings) -> Self {
+        self.load_settings = settings;
+        self
+    }
+
+    /// Treat the masks listed in `full_sizes` as proxies of masks that size.
+    pub fn with_full_sizes(mut self, full_sizes: HashMap<String, (u32, u32)>) -> Self {
+        self.full_sizes = full_sizes;
+        self
+    }
+
+    /// How long the masks took to load, for the performance overlay.
+    pub fn with_load_times(mut self, load_times: HashMap<String, Duration>) -> Self {
+        self.timings.decode = load_times;
+        self
+    }
+
+    /// Let the weight sliders go down to -1.
+    pub fn with_negative_weights(mut self, allow: bool) -> Self {
+        self.allow_negative = allow;
+        self
+    }
+
+    /// Output directory written into copied commands.
+    pub fn with_output(mut self, output: PathBuf) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// List mask sets that failed to load, each with a retry button.
+    pub fn with_load_errors(mut self, errors: Vec<(PathBuf, String)>) -> Self {
+        self.load_errors = errors;
+        self
+    }
+
+    /// Apply the persisted theme and UI scale to a freshly created context,
+    /// and start watching the mask directories.
+    pub fn setup(&mut self, ctx: &egui::Context) {
+        self.settings.apply(ctx);
+        self.ctx = Some(ctx.clone());
+        self.watch_masks();
+    }
+
+    fn watch_masks(&mut self) {
+        let Some(ctx) = self.ctx.clone() else { return };
+        self.watcher = match MaskWatcher::new(&self.paths, &self.load_settings.map, ctx) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                eprintln!("watching masks failed: {e}");
+                None
+            }
+        };
+    }
+
+    fn show_toast(&mut self, message: String) {
+        self.toast = Some((message, Instant::now()));
+    }
+
+    /// Load the mask set at `path` in full, the way the command line does.
+    fn load_mask(&self, path: &Path) -> anyhow::Result<Mask> {
+        Runner::load_mask_set(&self.load_settings, path, report::Terminal)
+    }
+
+    /// Reload masks whose files changed on disk.
+    fn reload_changed(&mut self) {
+        let Some(watcher) = &self.watcher else { return };
+        for key in watcher.changed() {
+            let start = Instant::now();
+            match self.load_mask(&self.paths[&key]) {
+                Ok(mask) => {
+                    self.timings.decode.insert(key.clone(), start.elapsed());
+                    self.masks.insert(key.clone(), mask);
+                    self.full_sizes.remove(&key);
+                    if key == self.current.key {
+                        // Force a re-render even though the parameters are unchanged
+                        self.last.key.clear();
+                    }
+                    for pane in self.panes.iter_mut().filter(|pane| pane.key == key) {
+                        pane.rendered = None;
+                    }
+                    #[cfg(feature = "node-editor")]
+                    if let Some(nodes) = &mut self.nodes {
+                        nodes.invalidate();
+                    }
+                    self.show_toast(format!("{} {key}", tr("reloaded")));
+                }
+                // Often a half-written file; the next change event retries
+                Err(e) => self.show_toast(format!("{} {key}: {e}", tr("reload-failed"))),
+            }
+        }
+    }
+
+    /// Snapshot of the session for a `.smix` project file.
+    pub fn project(&self) -> Project {
+        let masks = self.paths.iter()
+            .map(|(name, path)| ProjectMask {
+                name: name.clone(),
+                path: path.clone(),
+                weight: self.weight_of(name),
+            })
+            .collect();
+        Project {
+            selected: Some(self.current.key.clone()),
+            masks,
+            presets: self.presets.clone(),
+            export: ExportSettings {
+                scale: self.current.scale,
+                filter: self.current.filter,
+                mix: self.current.mix,
+                gain: self.current.levels.gain,
+                bias: self.current.levels.bias,
+                sharpen: self.sharpen.map(|s| [s.amount, s.radius]),
+                white_balance: [self.current.white_balance.temperature, self.current.white_balance.tint],
+                post: self.post.clone(),
+                name_template: self.name_template.clone(),
+            },
+            load: Some(LoadSettings {
+                map: self.load_settings.map.to_string(),
+                missing_channel: self.load_settings.missing_channel.as_ref().map(ToString::to_string),
+                psd_layers: self.load_settings.psd_layers.clone(),
+            }),
+            #[cfg(feature = "node-editor")]
+            nodes: self.nodes.as_ref().map(crate::nodes::NodeEditor::graph),
+        }
+    }
+
+    /// Replace the session with `project`, reloading its masks from disk.
+    pub fn open_project(&mut self, project: Project) -> anyhow::Result<()> {
+        let mut load_settings = self.load_settings.clone();
+        if let Some(load) = &project.load {
+            load_settings.map = load.map.parse()?;
+            load_settings.missing_channel = load.missing_channel.as_deref().map(str::parse).transpose()?;
+            load_settings.psd_layers = load.psd_layers.clone();
+        }
+        #[cfg(feature = "node-editor")]
+        let nodes = project.nodes.as_ref().map(crate::nodes::NodeEditor::from_graph).transpose()?;
+        let mut masks = IndexMap::new();
+        let mut paths = IndexMap::new();
+        let mut load_times = HashMap::new();
+        for mask in &project.masks {
+            let start = Instant::now();
+            masks.insert(mask.name.clone(), Runner::load_mask_set(&load_settings, &mask.path, report::Terminal)?);
+            load_times.insert(mask.name.clone(), start.elapsed());
+            paths.insert(mask.name.clone(), mask.path.clone());
+        }
+        let selected = project.selected
+            .filter(|key| masks.contains_key(key))
+            .or_else(|| project.masks.first().map(|mask| mask.name.clone()))
+            .ok_or_else(|| anyhow::anyhow!("Project has no masks"))?;
+        let weights: HashMap<_, _> = project.masks.iter().map(|mask| (mask.name.clone(), mask.weight)).collect();
+        let weight = weights.get(&selected).copied().unwrap_or(self.current.weight);
+
+        self.masks = masks;
+        self.paths = paths;
+        self.load_settings = load_settings;
+        self.full_sizes.clear();
+        self.panes.clear();
+        self.weights = weights;
+        self.timings.decode = load_times;
+        self.presets = project.presets;
+        self.selection = IndexSet::from([selected.clone()]);
+        let export = project.export;
+        let [temperature, tint] = export.white_balance;
+        self.current = Args {
+            weight,
+            mix: export.mix,
+            levels: Levels { gain: export.gain, bias: export.bias },
+            scale: export.scale,
+            key: selected,
+            filter: export.filter,
+            white_balance: WhiteBalance { temperature, tint },
+            ..self.current.clone()
+        };
+        self.sharpen = export.sharpen.map(|[amount, radius]| Sharpen { amount, radius });
+        self.post = export.post;
+        self.name_template = export.name_template;
+        #[cfg(feature = "node-editor")]
+        {
+            self.nodes = nodes;
+        }
+        self.watch_masks();
+        Ok(())
+    }
+
+    /// Add the mask sets at `paths`, directories of channel files or packed
+    /// images, and select the last one.
+    fn import_masks(&mut self, paths: Vec<PathBuf>) {
+        let mut imported = Vec::new();
+        for path in paths {
+            let start = Instant::now();
+            match self.load_mask(&path) {
+                Ok(mask) => {
+                    let key = self.add_mask(path, mask);
+                    self.timings.decode.insert(key.clone(), start.elapsed());
+                    imported.push(key);
+                }
+                Err(e) => self.show_toast(format!("{} {}: {e}", tr("import-failed"), path.display())),
+            }
+        }
+        if !imported.is_empty() {
+            self.watch_masks();
+            self.show_toast(format!("{} {}", tr("imported"), imported.join(", ")));
+        }
+    }
+
+    /// Add and select `mask`, named after `path` with a suffix if the name is taken.
+    fn add_mask(&mut self, path: PathBuf, mask: Mask) -> String {
+        let stem = path.file_stem().map_or("mask".into(), |stem| stem.to_string_lossy());
+        let key = (1..)
+            .map(|i| if i == 1 { stem.to_string() } else { format!("{stem}_{i}") })
+            .find(|key| !self.masks.contains_key(key))
+            .expect("some key is free");
+        self.masks.insert(key.clone(), mask);
+        self.paths.insert(key.clone(), path);
+        self.full_sizes.remove(&key);
+        self.store_weight();
+        self.show_mask(key.clone());
+        self.selection = IndexSet::from([key.clone()]);
+        key
+    }
+
+    /// Show the clicked mask `key`; with `toggle` (Ctrl+click) add it to or
+    /// remove it from the selection instead of selecting only it.
+    fn click_mask(&mut self, key: String, toggle: bool) {
+        self.store_weight();
+        if !toggle {
+            self.selection = IndexSet::from([key.clone()]);
+            self.show_mask(key);
+        } else if !self.selection.shift_remove(&key) {
+            self.selection.insert(key.clone());
+            self.show_mask(key);
+        } else if key == self.current.key {
+            match self.selection.last() {
+                Some(last) => self.show_mask(last.clone()),
+                // The shown mask stays selected
+                None => {
+                    self.selection.insert(key);
+                }
+            }
+        }
+    }
+
+    /// Show the mask `key` with its own weights.
+    fn show_mask(&mut self, key: String) {
+        self.current.weight = self.weight_of(&key);
+        self.current.key = key;
+    }
+
+    /// Weights of the mask `key`: as tuned, or as given on the command line.
+    fn weight_of(&self, key: &str) -> [f32; 3] {
+        if key == self.current.key {
+            return self.current.weight;
+        }
+        self.weights.get(key).copied().unwrap_or(self.cli_weight)
+    }
+
+    /// Keep `current.weight` for the shown mask, and when it was changed,
+    /// for every other selected mask too.
+    fn store_weight(&mut self) {
+        match self.weights.get(&self.current.key) {
+            Some(stored) if *stored == self.current.weight => {}
+            Some(_) => {
+                for key in &self.selection {
+                    self.weights.insert(key.clone(), self.current.weight);
+                }
+            }
+            None => {
+                self.weights.insert(self.current.key.clone(), self.current.weight);
+            }
+        }
+    }
+
+    /// Give every mask the shown mask's weights.
+    fn copy_weight_to_all(&mut self) {
+        for key in self.masks.keys() {
+            self.weights.insert(key.clone(), self.current.weight);
+        }
+    }
+
+    /// "Blend presets": weights interpolated live between two presets.
+    fn blend_presets(&mut self, ui: &mut egui::Ui) {
+        ui.label(tr("blend-presets"));
+        let (a, b, _) = &mut self.blend;
+        let mut changed = false;
+        for (id, choice) in [("blend-a", a), ("blend-b", b)] {
+            egui::ComboBox::from_id_salt(id)
+                .selected_text(choice.as_deref().unwrap_or("-"))
+                .show_ui(ui, |ui| {
+                    for name in self.presets.keys() {
+                        changed |= ui.selectable_value(choice, Some(name.clone()), name).changed();
+                    }
+                });
+        }
+        let (Some(a), Some(b)) = (&self.blend.0, &self.blend.1) else { return };
+        let (Some(from), Some(to)) = (self.presets.get(a), self.presets.get(b)) else { return };
+        let t = &mut self.blend.2;
+        changed |= ui.add(Slider::new(t, 0.0..=1.0).text("t").step_by(0.01))
+            .on_hover_text(format!("--weights \"lerp({a}, {b}, {t})\""))
+            .changed();
+        if changed {
+            self.current.weight = std::array::from_fn(|i| from[i] + (to[i] - from[i]) * *t);
+        }
+    }
+
+    /// Export every selected mask into a picked folder, named by the template.
+    fn save_selection(&mut self, ctx: &egui::Context) {
+        let names: anyhow::Result<Vec<_>> = self.selection.iter()
+            .map(|key| Ok((key.clone(), self.export_name(key)?)))
+            .collect();
+        let names = match names {
+            Ok(names) => names,
+            Err(e) => return self.show_toast(e.to_string()),
+        };
+        let Some(dir) = FileDialog::new()
+            .set_title(tr("save-selected"))
+            .set_directory(std::env::current_dir().unwrap_or_default())
+            .pick_folder()
+        else {
+            return;
+        };
+        let targets = names.into_iter().map(|(key, name)| (key, dir.join(name))).collect();
+        self.start_export(targets, ctx);
+    }
+
+    /// Load the failed mask set at `index` of `load_errors` again.
+    fn retry_load(&mut self, index: usize) {
+        let path = self.load_errors[index].0.clone();
+        let start = Instant::now();
+        match self.load_mask(&path) {
+            Ok(mask) => {
+                self.load_errors.remove(index);
+                let key = self.add_mask(path, mask);
+                self.timings.decode.insert(key.clone(), start.elapsed());
+                self.watch_masks();
+                self.show_toast(format!("{} {key}", tr("reloaded")));
+            }
+            Err(e) => self.load_errors[index].1 = format!("{e:#}"),
+        }
+    }
+
+    /// Output size of the mask `key` at the current scale.
+    fn export_size(&self, key: &str) -> (u32, u32) {
+        let (w, h) = self.full_sizes.get(key).copied().unwrap_or_else(|| self.masks[key].dimensions());
+        ((w as f32 * self.current.scale) as u32, (h as f32 * self.current.scale) as u32)
+    }
+
+    /// File name the mask `key` exports to with the current parameters, from the name template.
+    fn export_name(&self, key: &str) -> anyhow::Result<String> {
+        let (width, height) = self.export_size(key);
+        let fields = NameFields {
+            mask: key,
+            width,
+            height,
+            scale: self.current.scale,
+            weight: self.weight_of(key),
+            lod: None,
+        };
+        naming::render(&self.name_template, &fields, image::ImageFormat::Png)
+    }
+
+    /// The headless command line producing the current export.
+    fn command_line(&self) -> String {
+        let [r, g, b] = self.current.weight;
+        let mut args = vec![
+            "smix".to_string(), r.to_string(), g.to_string(), b.to_string(),
+            "-m".into(), self.paths[&self.current.key].display().to_string(),
+            "-s".into(), self.current.scale.to_string(),
+            "-f".into(), value_name(self.current.filter),
+            "-o".into(), self.output.display().to_string(),
+        ];
+        if self.current.weight.iter().any(|&w| w < 0.0) {
+            args.push("--allow-negative".into());
+        }
+        // How the mask set was loaded
+        let (load, default) = (&self.load_settings, Settings::default());
+        if load.map != default.map {
+            args.extend(["--map".into(), load.map.to_string()]);
+        }
+        if let Some(fallback) = &load.missing_channel {
+            args.extend(["--missing-channel".into(), fallback.to_string()]);
+        }
+        if load.psd_layers != default.psd_layers {
+            args.extend(["--psd-layers".into(), load.psd_layers.join(",")]);
+        }
+        for plugin in &load.plugins {
+            args.extend(["--plugin".into(), plugin.display().to_string()]);
+        }
+        let levels = self.current.levels;
+        let default = Levels::default();
+        for (flag, values, default) in [("--gain", levels.gain, default.gain), ("--bias", levels.bias, default.bias)] {
+            let changed: Vec<_> = values.iter().zip(default).zip(["r", "g", "b"])
+                .filter(|((value, default), _)| **value != *default)
+                .map(|((value, _), channel)| format!("{channel}={value}"))
+                .collect();
+            if !changed.is_empty() {
+                args.extend([flag.into(), changed.join(",")]);
+            }
+        }
+        let balance = self.current.white_balance;
+        if balance.temperature != adjust::NEUTRAL_TEMPERATURE {
+            args.extend(["--temperature".into(), balance.temperature.to_string()]);
+        }
+        if balance.tint != 0.0 {
+            args.extend(["--tint".into(), balance.tint.to_string()]);
+        }
+        if self.current.mix != Mix::Sum {
+            args.extend(["--mix".into(), value_name(self.current.mix)]);
+        }
+        if self.name_template != naming::DEFAULT_TEMPLATE {
+            args.extend(["--name-template".into(), self.name_template.clone()]);
+        }
+        if let Some(sharpen) = self.sharpen {
+            args.extend(["--sharpen".into(), sharpen.amount.to_string(), "--sharpen-radius".into(), sharpen.radius.to_string()]);
+        }
+        if !self.post.is_empty() {
+            args.extend(["--post".into(), self.post.join(",")]);
+        }
+        args.extend(["-p".into(), "false".into()]);
+        args.iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" ")
+    }
+
+    fn project_dialog() -> FileDialog {
+        FileDialog::new()
+            .add_filter("smix project", &[project::EXTENSION])
+            .set_directory(std::env::current_dir().unwrap_or_default())
+    }
+
+    /// The weights to mix the mask `key` by, see [`MixSemantics::effective_weight`].
+    fn mix_weight(&self, key: &str) -> [f32; 3] {
+        MixSemantics::from(self.current.mix).effective_weight(self.weight_of(key))
+    }
+
+    /// The mask `key` corrected by the gain and bias.
+    fn leveled_mask(&self, key: &str) -> Cow<'_, Mask> {
+        let mask = &self.masks[key];
+        if self.current.levels.is_identity() {
+            return Cow::Borrowed(mask);
+        }
+        let mut mask = mask.clone();
+        mask.apply_levels(&self.current.levels);
+        Cow::Owned(mask)
+    }
+
+    /// The mix `args` describe, with the color vision simulation.
+    fn preview_mix(&self, args: &Args) -> GeneratedImage {
+        let weight = MixSemantics::from(args.mix).effective_weight(args.weight);
+        let mut img = self.leveled_mask(&args.key).generate(&weight);
+        if !args.white_balance.is_neutral() {
+            img = img.white_balance(args.white_balance);
+        }
+        match args.simulate {
+            Some(kind) => img.simulate(kind),
+            None => img,
+        }
+    }
+
+    /// `img` resized to the export size, then shown at the preview's
+    /// physical size with nearest neighbor so the output's pixels and
+    /// resampling artifacts stay visible, sharp on high-DPI screens too.
+    /// Exports larger than the preview are only resized to its size: their
+    /// pixels can't be told apart there, and resizing a 5× export on every
+    /// slider change would stall the window.
+    fn fit_preview(&self, args: &Args, img: &GeneratedImage) -> RgbaImage {
+        let size = args.preview_size;
+        let (width, height) = self.export_size(&args.key);
+        let options = ExportOptions { filter: args.filter.into(), ..Default::default() };
+        let shown = img.resized_for(width.clamp(1, size), height.clamp(1, size), &options);
+        image::imageops::resize(&*shown, size, size, image::imageops::FilterType::Nearest)
+    }
+
+    /// `img` resized to the export size with the filter of `args`.
+    fn at_export_size<'a>(&self, args: &Args, img: &'a GeneratedImage) -> Cow<'a, RgbaImage> {
+        let (width, height) = self.export_size(&args.key);
+        let options = ExportOptions { filter: args.filter.into(), ..Default::default() };
+        img.resized_for(width.max(1), height.max(1), &options)
+    }
+
+    /// Put the current mix at the export size on the system clipboard.
+    fn copy_image(&mut self) -> Result<(), arboard::Error> {
+        let mix = self.preview_mix(&self.current);
+        let output = self.at_export_size(&self.current, &mix);
+        let data = arboard::ImageData {
+            width: output.width() as usize,
+            height: output.height() as usize,
+            bytes: Cow::Borrowed(output.as_raw()),
+        };
+        let clipboard = match &mut self.clipboard {
+            Some(clipboard) => clipboard,
+            None => self.clipboard.insert(arboard::Clipboard::new()?),
+        };
+        clipboard.set_image(data)
+    }
+
+    /// Write the current mix at the export size to a temporary PNG named
+    /// like its export and start dragging that file out of the window, to
+    /// be dropped on the desktop or into a file manager.
+    #[cfg(all(feature = "drag", any(windows, target_os = "macos")))]
+    fn drag_out(&self, frame: &eframe::Frame) -> anyhow::Result<()> {
+        let mix = self.preview_mix(&self.current);
+        let output = self.at_export_size(&self.current, &mix);
+        let path = std::path::absolute(std::env::temp_dir().join("smix-drag").join(self.export_name(&self.current.key)?))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
         }
+        output.save(&path)?;
+        let icon = drag::Image::File(path.clone());
+        drag::start_drag(frame, drag::DragItem::Files(vec![path]), icon, |_, _| {}, drag::Options::default())?;
+        Ok(())
+    }
+
+    /// The current mix as shown in the preview, see [`PreView::fit_preview`].
+    pub fn preview_image(&self) -> RgbaImage {
+        self.fit_preview(&self.current, &self.preview_mix(&self.current))
+    }
+
+    /// Mix and export each mask of `targets` to its path on a worker
+    /// thread, so the window stays responsive and the export can be cancelled.
+    fn start_export(&mut self, targets: Vec<(String, PathBuf)>, ctx: &egui::Context) {
+        let cancel = CancelToken::new();
+        let options = match self.export_options(&cancel) {
+            Ok(options) => options,
+            Err(e) => return self.show_toast(e.to_string()),
+        };
+        let levels = self.current.levels;
+        // Proxies are only good for the preview; the worker loads those masks in full
+        let jobs: Vec<_> = targets.into_iter()
+            .map(|(key, path)| {
+                let mask = (!self.full_sizes.contains_key(&key)).then(|| self.leveled_mask(&key).into_owned());
+                (mask, self.paths[&key].clone(), self.mix_weight(&key), self.export_size(&key), path)
+            })
+            .collect();
+        let settings = self.load_settings.clone();
+        let (ctx, token) = (ctx.clone(), cancel.clone());
+        let handle = std::thread::spawn(move || {
+            let result = jobs.into_iter()
+                .map(|(mask, source, weight, (nwidth, nheight), path)| {
+                    let mask = match mask {
+                        Some(mask) => mask,
+                        None => {
+                            let mut mask = Runner::load_mask_set(&settings, &source, report::Terminal)?;
+                            mask.apply_levels(&levels);
+                            mask
+                        }
+                    };
+                    mask.generate_cancellable(&weight, &token)?.export(&path, nwidth, nheight, &options)?;
+                    Ok(path)
+                })
+                .collect();
+            ctx.request_repaint();
+            result
+        });
+        self.export = Some((handle, cancel));
+    }
+
+    /// Options shared by every export of the session.
+    fn export_options(&self, cancel: &CancelToken) -> anyhow::Result<ExportOptions> {
+        // Export is disabled while a step is unknown, but a project may have just named one
+        let post = self.registry.resolve(&self.post)?;
+        Ok(ExportOptions {
+            filter: self.current.filter.into(),
+            white_balance: Some(self.current.white_balance),
+            sharpen: self.sharpen,
+            post,
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        })
     }
 
-    pub fn preview_256x(&self) -> RgbaImage {
-        use imageops::FilterType::Nearest;
-        let mask = &self.masks[&self.current.key];
-        let img = mask.generate(&self.current.weight);
-        let img = image::imageops::resize(img.get_rgba(), 256, 256, Nearest);
-        img
+    /// Export the node editor's output to `path` on a worker thread, at its
+    /// full size times the export scale, like [`PreView::start_export`].
+    #[cfg(feature = "node-editor")]
+    fn start_graph_export(&mut self, graph: crate::nodes::Graph, path: PathBuf, ctx: &egui::Context) {
+        let cancel = CancelToken::new();
+        let options = match self.export_options(&cancel) {
+            Ok(options) => options,
+            Err(e) => return self.show_toast(e.to_string()),
+        };
+        // Proxies are loaded in full by the worker, like in `start_export`
+        let sources: Vec<_> = graph.masks()
+            .map(|key| {
+                let mask = self.masks.get(key).filter(|_| !self.full_sizes.contains_key(key)).cloned();
+                (key.to_string(), mask, self.paths.get(key).cloned())
+            })
+            .collect();
+        let (settings, registry, scale) = (self.load_settings.clone(), self.registry.clone(), self.current.scale);
+        let ctx = ctx.clone();
+        let handle = std::thread::spawn(move || {
+            let result = (|| {
+                let mut masks = IndexMap::new();
+                for (key, mask, source) in sources {
+                    let mask = match (mask, source) {
+                        (Some(mask), _) => mask,
+                        (None, Some(source)) => Runner::load_mask_set(&settings, &source, report::Terminal)?,
+                        (None, None) => continue,
+                    };
+                    masks.insert(key, mask);
+                }
+                let img = graph.evaluate(&masks, &registry)?;
+                let (width, height) = img.dimensions();
+                img.export(&path, (width as f32 * scale) as u32, (height as f32 * scale) as u32, &options)?;
+                Ok(vec![path])
+            })();
+            ctx.request_repaint();
+            result
+        });
+        self.export = Some((handle, cancel));
+    }
+
+    fn finish_export(&mut self) {
+        if !self.export.as_ref().is_some_and(|(handle, _)| handle.is_finished()) {
+            return;
+        }
+        let (handle, _) = self.export.take().expect("checked above");
+        // The panic itself is already printed; the window only needs to know
+        let result = handle.join().unwrap_or_else(|_| Err(anyhow::anyhow!("the export thread panicked")));
+        match result {
+            Ok(paths) => {
+                self.show_toast(format!("{} {}", tr("images-saved"), paths.len()));
+                for path in paths {
+                    self.recent_exports.retain(|recent| *recent != path);
+                    self.recent_exports.insert(0, path);
+                }
+                self.recent_exports.truncate(RECENT_EXPORTS);
+            }
+            Err(e) if e.is::<Cancelled>() => self.show_toast(tr("export-cancelled").into()),
+            Err(e) => self.show_toast(format!("{} {e:#}", tr("save-failed"))),
+        }
     }
 
     pub fn update_preview(&mut self, ctx: &egui::Context) {
-        let preview = self.preview_256x();
-        let img = egui::ColorImage::from_rgba_unmultiplied([256, 256], preview.as_raw());
+        let start = Instant::now();
+        let mix = self.preview_mix(&self.current);
+        let mixed = Instant::now();
+        let preview = self.fit_preview(&self.current, &mix);
+        let resized = Instant::now();
+        let size = [preview.width() as usize, preview.height() as usize];
+        let img = egui::ColorImage::from_rgba_unmultiplied(size, preview.as_raw());
         if let Some(handle) = &mut self.tex {
             handle.set(img, egui::TextureOptions::default());
         } else {
             self.tex = Some(ctx.load_texture("preview", img, Default::default()))
         }
+        self.timings.mix = mixed - start;
+        self.timings.resize = resized - mixed;
+        self.timings.upload = resized.elapsed();
 
         self.last.clone_from(&self.current);
     }
+
+    /// Render the pinned panes whose mix changed since they were last shown.
+    fn update_panes(&mut self, ctx: &egui::Context) {
+        for i in 0..self.panes.len() {
+            let args = self.panes[i].args(&self.current);
+            if self.panes[i].rendered.as_ref() == Some(&args) {
+                continue;
+            }
+            let preview = self.fit_preview(&args, &self.preview_mix(&args));
+            let size = [preview.width() as usize, preview.height() as usize];
+            let img = egui::ColorImage::from_rgba_unmultiplied(size, preview.as_raw());
+            let pane = &mut self.panes[i];
+            match &mut pane.tex {
+                Some(handle) => handle.set(img, egui::TextureOptions::default()),
+                None => pane.tex = Some(ctx.load_texture(format!("pane-{i}"), img, Default::default())),
+            }
+            pane.rendered = Some(args);
+        }
+    }
+}
+
+/// Output size, in the top left corner of a preview at `rect`.
+fn paint_badge(painter: &egui::Painter, rect: egui::Rect, text: String) {
+    let galley = painter.layout_no_wrap(text, egui::FontId::monospace(12.0), egui::Color32::WHITE);
+    let badge_rect = egui::Rect::from_min_size(rect.left_top() + egui::vec2(6.0, 6.0), galley.size() + egui::vec2(8.0, 4.0));
+    painter.rect_filled(badge_rect, 3.0, egui::Color32::from_black_alpha(160));
+    painter.galley(badge_rect.min + egui::vec2(4.0, 2.0), galley, egui::Color32::WHITE);
 }
 
 impl eframe::App for PreView {
     fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+        self.timings.frame();
+        self.reload_changed();
+        self.finish_export();
+        let dropped: Vec<_> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
+        if !dropped.is_empty() {
+            self.import_masks(dropped);
+        }
         let changed = self.last != self.current;
         if changed {
             self.update_preview(ctx);
@@ -72,70 +1006,574 @@ impl eframe::App for PreView {
         egui::SidePanel::left("Control")
             .min_width(128.0).resizable(false)
             .show(ctx, |ui| {
+                // Project files
+                ui.horizontal(|ui| {
+                    if ui.button(tr("open-project")).clicked()
+                        && let Some(path) = Self::project_dialog().pick_file()
+                    {
+                        match Project::load(&path).and_then(|p| self.open_project(p)) {
+                            Ok(()) => println!("opened project {}", path.display()),
+                            Err(e) => eprintln!("open project failed: {e}"),
+                        }
+                    }
+                    if ui.button(tr("save-project")).clicked()
+                        && let Some(path) = Self::project_dialog()
+                            .set_file_name(format!("session.{}", project::EXTENSION))
+                            .save_file()
+                    {
+                        match self.project().save(&path) {
+                            Ok(()) => println!("saved project {}", path.display()),
+                            Err(e) => eprintln!("save project failed: {e}"),
+                        }
+                    }
+                    ui.menu_button(tr("import-mask"), |ui| {
+                        let picked = if ui.button(tr("import-folders")).clicked() {
+                            FileDialog::new().pick_folders()
+                        } else if ui.button(tr("import-packed")).clicked() {
+                            FileDialog::new().add_filter("Image", &["png", "tga", "webp", "tif", "tiff"]).pick_files()
+                        } else {
+                            None
+                        };
+                        if let Some(paths) = picked {
+                            self.import_masks(paths);
+                            ui.close();
+                        }
+                    });
+                    if ui.button(tr("settings")).clicked() {
+                        self.settings_open = !self.settings_open;
+                    }
+                    #[cfg(feature = "node-editor")]
+                    if ui.button(tr("node-editor")).clicked() {
+                        self.nodes_open = !self.nodes_open;
+                    }
+                });
+                ui.separator();
+
                 // Masks list
-                ui.label("Masks");
+                ui.label(tr("masks"));
                 egui::ScrollArea::vertical()
                     .show(ui, |ui| {
+                        let mut clicked = None;
                         for key in self.masks.keys() {
-                            let selected = *key == self.current.key;
-                            if ui.selectable_label(selected, key).clicked() {
-                                self.current.key = key.clone();
+                            let selected = self.selection.contains(key);
+                            let label = if *key == self.current.key { egui::RichText::new(key).strong() } else { key.into() };
+                            let response = ui.selectable_label(selected, label);
+                            if response.clicked() {
+                                clicked = Some((key.clone(), ui.input(|i| i.modifiers.command)));
                             }
                         }
+                        if let Some((key, toggle)) = clicked {
+                            self.click_mask(key, toggle);
+                        }
                     }
                 );
             }
         );
 
+        if !self.load_errors.is_empty() {
+            let mut retry = None;
+            egui::TopBottomPanel::bottom("load-errors").show(ctx, |ui| {
+                ui.label(tr("load-errors"));
+                for (i, (path, error)) in self.load_errors.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.button(tr("retry")).clicked() {
+                            retry = Some(i);
+                        }
+                        ui.label(path.display().to_string());
+                        ui.colored_label(ui.visuals().error_fg_color, error);
+                    });
+                }
+            });
+            if let Some(i) = retry {
+                self.retry_load(i);
+            }
+        }
+
+        if !self.recent_exports.is_empty() {
+            let mut failed = None;
+            egui::TopBottomPanel::bottom("recent-exports").show(ctx, |ui| {
+                ui.label(tr("recent-exports"));
+                for path in &self.recent_exports {
+                    ui.horizontal(|ui| {
+                        if ui.button(tr("reveal")).clicked()
+                            && let Err(e) = reveal(path)
+                        {
+                            failed = Some(e);
+                        }
+                        ui.label(path.display().to_string());
+                    });
+                }
+            });
+            if let Some(e) = failed {
+                self.show_toast(format!("{} {e}", tr("reveal-failed")));
+            }
+        }
+
         egui::SidePanel::right("Args")
             .resizable(false)
             .show(ctx, |ui| {
                 // Args setting
                 ui.vertical(|ui| {
-                    ui.label("Weights:");
-                    ui.add(Slider::new(&mut self.current.weight[0], 0.0..=1.0).text("R").step_by(0.01));
-                    ui.add(Slider::new(&mut self.current.weight[1], 0.0..=1.0).text("G").step_by(0.01));
-                    ui.add(Slider::new(&mut self.current.weight[2], 0.0..=1.0).text("B").step_by(0.01));
+                    ui.label(tr("weights"));
+                    for (weight, label) in self.current.weight.iter_mut().zip(["R", "G", "B"]) {
+                        weight_slider(ui, weight, label, self.settings.perceptual_sliders, self.allow_negative);
+                    }
+                    egui::ComboBox::from_label(tr("mix"))
+                        .selected_text(value_name(self.current.mix))
+                        .show_ui(ui, |ui| {
+                            for &mix in Mix::value_variants() {
+                                ui.selectable_value(&mut self.current.mix, mix, value_name(mix));
+                            }
+                        })
+                        .response
+                        .on_hover_text(tr("mix-hint"));
+                    ui.horizontal(|ui| {
+                        if ui.button(tr("randomize")).clicked() {
+                            self.seed = time_seed();
+                            self.current.weight = random_weight(self.seed, self.normalize_random);
+                        }
+                        ui.checkbox(&mut self.normalize_random, tr("sum-to-one"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(tr("seed"));
+                        if ui.add(egui::DragValue::new(&mut self.seed)).changed() {
+                            self.current.weight = random_weight(self.seed, self.normalize_random);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button(tr("reset-weights")).clicked() {
+                            self.current.weight = self.cli_weight;
+                        }
+                        if ui.button(tr("copy-to-all")).on_hover_text(tr("copy-to-all-hint")).clicked() {
+                            self.copy_weight_to_all();
+                        }
+                    });
+                    ui.separator();
+
+                    ui.collapsing(tr("presets"), |ui| {
+                        for (name, weight) in &self.presets {
+                            if ui.button(name).on_hover_text(format!("{weight:?}")).clicked() {
+                                self.current.weight = *weight;
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(&mut self.preset_name);
+                            if ui.button(tr("add")).clicked() && !self.preset_name.is_empty() {
+                                self.presets.insert(std::mem::take(&mut self.preset_name), self.current.weight);
+                            }
+                        });
+                        if self.presets.len() >= 2 {
+                            ui.separator();
+                            self.blend_presets(ui);
+                        }
+                    });
+                    ui.collapsing(tr("advanced"), |ui| {
+                        let levels = &mut self.current.levels;
+                        egui::Grid::new("levels").show(ui, |ui| {
+                            ui.label("");
+                            ui.label(tr("gain"));
+                            ui.label(tr("bias"));
+                            ui.end_row();
+                            for (i, channel) in ["R", "G", "B"].into_iter().enumerate() {
+                                ui.label(channel);
+                                ui.add(egui::DragValue::new(&mut levels.gain[i]).speed(0.01).range(0.0..=4.0));
+                                ui.add(egui::DragValue::new(&mut levels.bias[i]).speed(0.01).range(-1.0..=1.0));
+                                ui.end_row();
+                            }
+                        });
+                        let stats = self.masks.get(&self.current.key).map(Mask::stats);
+                        ui.horizontal(|ui| {
+                            if ui.button(tr("reset-levels")).clicked() {
+                                *levels = Levels::default();
+                            }
+                            if let Some(stats) = stats
+                                && ui.button(tr("auto-levels")).on_hover_text(tr("auto-levels-hint")).clicked()
+                            {
+                                *levels = Levels::auto(stats);
+                            }
+                        });
+                        if let Some(stats) = stats {
+                            ui.label(tr("mask-stats"));
+                            egui::Grid::new("mask-stats").show(ui, |ui| {
+                                ui.label("");
+                                ui.label(tr("range"));
+                                ui.label(tr("mean"));
+                                ui.label(tr("coverage"));
+                                ui.end_row();
+                                for (channel, stats) in ["R", "G", "B"].into_iter().zip(stats) {
+                                    ui.label(channel);
+                                    ui.label(format!("{:.3}~{:.3}", stats.min, stats.max));
+                                    ui.label(format!("{:.3}", stats.mean));
+                                    ui.label(format!("{:.1}%", stats.coverage * 100.0));
+                                    ui.end_row();
+                                }
+                            });
+                        }
+                    });
+                    ui.separator();
+                    ui.add(Slider::new(&mut self.current.scale, 0.1..=5.0).text(tr("scale")).step_by(0.1));
+                    egui::ComboBox::from_label(tr("filter"))
+                        .selected_text(value_name(self.current.filter))
+                        .show_ui(ui, |ui| {
+                            for &filter in Filter::value_variants() {
+                                ui.selectable_value(&mut self.current.filter, filter, value_name(filter));
+                            }
+                        });
+                    ui.separator();
+
+                    ui.collapsing(tr("white-balance"), |ui| {
+                        let balance = &mut self.current.white_balance;
+                        ui.add(Slider::new(&mut balance.temperature, 2000.0..=12000.0).text(tr("temperature")).suffix(" K").step_by(50.0))
+                            .on_hover_text(tr("temperature-hint"));
+                        ui.add(Slider::new(&mut balance.tint, -1.0..=1.0).text(tr("tint")).step_by(0.01))
+                            .on_hover_text(tr("tint-hint"));
+                        if ui.button(tr("reset-white-balance")).clicked() {
+                            *balance = WhiteBalance::default();
+                        }
+                    });
+                    ui.separator();
+
+                    egui::ComboBox::from_label(tr("simulate"))
+                        .selected_text(self.current.simulate.map_or(tr("normal-vision").into(), |kind| kind.to_string()))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.current.simulate, None, tr("normal-vision"));
+                            for kind in ColorBlindness::ALL {
+                                ui.selectable_value(&mut self.current.simulate, Some(kind), kind.to_string());
+                            }
+                        })
+                        .response
+                        .on_hover_text(tr("preview-only"));
                     ui.separator();
-                    ui.add(Slider::new(&mut self.current.scale, 0.1..=5.0).text("Scale").step_by(0.1));
+
+                    let mut sharpen = self.sharpen.is_some();
+                    if ui.checkbox(&mut sharpen, tr("sharpen-on-export")).changed() {
+                        self.sharpen = sharpen.then(Sharpen::default);
+                    }
+                    if let Some(sharpen) = &mut self.sharpen {
+                        ui.add(Slider::new(&mut sharpen.amount, 0.0..=2.0).text(tr("amount")).step_by(0.05));
+                        ui.add(Slider::new(&mut sharpen.radius, 0.3..=3.0).text(tr("radius")).step_by(0.1));
+                    }
+                    ui.collapsing(tr("post-processing"), |ui| {
+                        for name in self.registry.names() {
+                            let mut checked = self.post.iter().any(|step| step == name);
+                            if ui.checkbox(&mut checked, name).changed() {
+                                if checked {
+                                    self.post.push(name.to_string());
+                                } else {
+                                    self.post.retain(|step| step != name);
+                                }
+                            }
+                        }
+                        // Steps a project or --post named that no loaded plugin provides
+                        let unknown: Vec<_> = self.post.iter().filter(|step| self.registry.get(step).is_none()).cloned().collect();
+                        for name in unknown {
+                            ui.horizontal(|ui| {
+                                ui.colored_label(ui.visuals().error_fg_color, &name);
+                                if ui.small_button(tr("remove")).clicked() {
+                                    self.post.retain(|step| *step != name);
+                                }
+                            });
+                        }
+                    });
                     ui.separator();
                     
-                    if ui.button("Save").clicked() {
-                        let img = self.masks[&self.current.key].generate(&self.current.weight);
-                        let (w, h) = img.dimensions();
-                        let nwidth = (w as f32 * self.current.scale) as u32;
-                        let nheight = (h as f32 * self.current.scale) as u32;
-                        if let Some(path) = FileDialog::new()
+                    ui.label(tr("name-template"));
+                    ui.text_edit_singleline(&mut self.name_template)
+                        .on_hover_text("{mask} {width} {height} {scale} {r} {g} {b}");
+                    let export_name = self.export_name(&self.current.key);
+                    match &export_name {
+                        Ok(name) => ui.label(format!("→ {name}")),
+                        Err(e) => ui.colored_label(ui.visuals().error_fg_color, e.to_string()),
+                    };
+                    let post = self.registry.resolve(&self.post);
+                    if let Err(e) = &post {
+                        ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                    }
+
+                    if ui.button(tr("copy-command")).clicked() {
+                        ctx.copy_text(self.command_line());
+                        self.show_toast(tr("command-copied").into());
+                    }
+                    if ui.add_enabled(self.panes.len() < MAX_PANES, egui::Button::new(tr("pin-view")))
+                        .on_hover_text(tr("pin-view-hint"))
+                        .clicked()
+                    {
+                        self.panes.push(Pane { key: self.current.key.clone(), weight: self.current.weight, tex: None, rendered: None });
+                    }
+                    if ui.button(tr("copy-image")).clicked() {
+                        match self.copy_image() {
+                            Ok(()) => self.show_toast(tr("image-copied").into()),
+                            Err(e) => self.show_toast(format!("{} {e}", tr("copy-image-failed"))),
+                        }
+                    }
+                    if let Some((_, cancel)) = &self.export {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            if ui.button(tr("cancel")).clicked() {
+                                cancel.cancel();
+                            }
+                        });
+                    } else if ui.add_enabled(export_name.is_ok() && post.is_ok(), egui::Button::new(tr("save"))).clicked()
+                        && let Some(path) = FileDialog::new()
                             .add_filter("PNG", &["png"])
-                            .set_file_name(img.export_name(&self.current.key, nwidth, nheight))
-                            .set_title("Save the preview image")
-                            .set_directory(std::env::current_dir().unwrap_or(PathBuf::new()))
+                            .set_file_name(export_name.unwrap_or_default())
+                            .set_title(tr("save-dialog-title"))
+                            .set_directory(std::env::current_dir().unwrap_or_default())
                             .save_file()
-                        {
-                            if let Err(e) = img.save_as(path, nwidth, nheight, imageops::FilterType::Lanczos3) {
-                                eprintln!("save failed: {e}");
-                            } else {
-                                println!("saved image.");
-                            }
-                        }
+                    {
+                        self.start_export(vec![(self.current.key.clone(), path)], ctx);
+                    }
+                    if self.selection.len() > 1
+                        && self.export.is_none()
+                        && ui.add_enabled(post.is_ok(), egui::Button::new(format!("{} ({})", tr("save-selected"), self.selection.len()))).clicked()
+                    {
+                        self.save_selection(ctx);
                     }
                 });
             }
         );
 
+        let mut settings_open = self.settings_open;
+        egui::Window::new(tr("settings"))
+            .open(&mut settings_open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if self.settings.ui(ui) {
+                    self.settings.apply(ctx);
+                    if let Err(e) = self.settings.save() {
+                        eprintln!("save settings failed: {e}");
+                    }
+                }
+            });
+        self.settings_open = settings_open;
+
+        #[cfg(feature = "node-editor")]
+        if self.nodes_open {
+            let nodes = self.nodes.get_or_insert_with(|| {
+                crate::nodes::NodeEditor::new(&self.current.key, self.current.weight)
+            });
+            let window = egui::Window::new(tr("node-editor"))
+                .open(&mut self.nodes_open)
+                .default_size([900.0, 500.0])
+                .show(ctx, |ui| nodes.ui(ui, &self.masks, &self.registry));
+            if window.and_then(|response| response.inner).unwrap_or(false)
+                && self.export.is_none()
+                && let Some(path) = FileDialog::new()
+                    .add_filter("PNG", &["png"])
+                    .set_file_name("nodes.png")
+                    .set_title(tr("export-graph"))
+                    .set_directory(std::env::current_dir().unwrap_or_default())
+                    .save_file()
+            {
+                let graph = nodes.graph();
+                self.start_graph_export(graph, path, ctx);
+            }
+        }
+
+        if let Some((message, shown)) = &self.toast {
+            let elapsed = shown.elapsed();
+            if elapsed < TOAST_DURATION {
+                egui::Area::new(egui::Id::new("toast"))
+                    .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -16.0])
+                    .show(ctx, |ui| {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(message.as_str()));
+                    });
+                ctx.request_repaint_after(TOAST_DURATION - elapsed);
+            } else {
+                self.toast = None;
+            }
+        }
+
+        self.update_panes(ctx);
+        let (width, height) = self.export_size(&self.current.key);
+        let badge = format!("{width}×{height}");
+        let mut unpin = None;
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.centered_and_justified(|ui| {
-                if let Some(tex) = &self.tex {
+            if self.panes.is_empty() {
+                ui.centered_and_justified(|ui| {
                     let max_size = ui.available_size().min_elem();
-                    ui.image((tex.id(), egui::vec2(max_size, max_size)));
-                } else {
-                    ui.label("Loading...");
+                    // Re-rendered next frame when the panel or the display scale changes
+                    self.current.preview_size = ((max_size * ctx.pixels_per_point()).round() as u32).clamp(1, 4096);
+                    if let Some(tex) = &self.tex {
+                        let image = egui::Image::new((tex.id(), egui::vec2(max_size, max_size)));
+                        #[cfg(all(feature = "drag", any(windows, target_os = "macos")))]
+                        let image = image.sense(egui::Sense::drag());
+                        let response = ui.add(image);
+                        let rect = response.rect;
+                        #[cfg(all(feature = "drag", any(windows, target_os = "macos")))]
+                        if response.on_hover_text(tr("drag-out-hint")).drag_started()
+                            && let Err(e) = self.drag_out(_frame)
+                        {
+                            self.show_toast(format!("{} {e:#}", tr("drag-out-failed")));
+                        }
+                        // Final output size in the preview's corner
+                        paint_badge(ui.painter(), rect, badge);
+                        if self.settings.performance_overlay {
+                            self.timings.paint(ui.painter(), rect, &self.current.key);
+                        }
+                    } else {
+                        ui.label(tr("loading"));
+                    }
+                });
+                return;
+            }
+            // 2×2 grid: the live preview, then the pinned panes
+            let caption = ui.text_style_height(&egui::TextStyle::Button) + ui.spacing().item_spacing.y * 2.0;
+            let available = ui.available_size();
+            let cell = ((available.x - ui.spacing().item_spacing.x) / 2.0).min((available.y - ui.spacing().item_spacing.y) / 2.0 - caption).max(16.0);
+            self.current.preview_size = ((cell * ctx.pixels_per_point()).round() as u32).clamp(1, 4096);
+            egui::Grid::new("panes").num_columns(2).show(ui, |ui| {
+                ui.vertical(|ui| {
+                    ui.strong(format!("{} {:?}", self.current.key, self.current.weight));
+                    if let Some(tex) = &self.tex {
+                        let rect = ui.image((tex.id(), egui::vec2(cell, cell))).rect;
+                        paint_badge(ui.painter(), rect, badge);
+                        if self.settings.performance_overlay {
+                            self.timings.paint(ui.painter(), rect, &self.current.key);
+                        }
+                    }
+                });
+                for (i, pane) in self.panes.iter().enumerate() {
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            if ui.small_button("✕").on_hover_text(tr("unpin")).clicked() {
+                                unpin = Some(i);
+                            }
+                            ui.label(format!("{} {:?}", pane.key, pane.weight));
+                        });
+                        if let Some(tex) = &pane.tex {
+                            let rect = ui.image((tex.id(), egui::vec2(cell, cell))).rect;
+                            let (width, height) = self.export_size(&pane.key);
+                            paint_badge(ui.painter(), rect, format!("{width}×{height}"));
+                        }
+                    });
+                    if i % 2 == 0 {
+                        ui.end_row();
+                    }
                 }
             });
         });
+        if let Some(i) = unpin {
+            self.panes.remove(i);
+        }
 
-        if changed {
+        self.store_weight();
+        if changed || self.last != self.current {
             ctx.request_repaint();
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use clap::{Args as _, FromArgMatches};
+    use smix::procedural::Pattern;
+
+    use super::*;
+
+    #[test]
+    fn copied_command_reproduces_the_settings() {
+        let mask = Mask::procedural(4, 4, &[Pattern::Solid(1.0), Pattern::Solid(0.5), Pattern::Solid(0.0)]);
+        let load_settings = Settings {
+            map: "r=body.png,b=0.5".parse().unwrap(),
+            missing_channel: Some(smix::Fallback::Black),
+            psd_layers: vec!["Base".into(), "Trim".into(), "Accent".into()],
+            ..Settings::default()
+        };
+        let levels = Levels { gain: [2.0, 1.0, 1.0], bias: [0.0, 0.0, -0.25] };
+        let preview = PreView::new(
+            [0.5, 0.25, 0.0],
+            IndexMap::from([("hero".to_string(), mask)]),
+            IndexMap::from([("hero".to_string(), PathBuf::from("masks/hero"))]),
+            BTreeMap::new(),
+            naming::DEFAULT_TEMPLATE.into(),
+        )
+        .with_load_settings(load_settings.clone())
+        .with_levels(levels);
+        let command = preview.command_line();
+        let matches = crate::Args::augment_args(clap::Command::new("smix")).try_get_matches_from(command.split_whitespace()).unwrap();
+        let args = crate::Args::from_arg_matches(&matches).unwrap();
+        let settings = args.settings();
+        assert_eq!(settings.weight, [0.5, 0.25, 0.0]);
+        assert_eq!(settings.mask_directories, [PathBuf::from("masks/hero")]);
+        assert_eq!(settings.map, load_settings.map);
+        assert_eq!(settings.missing_channel, load_settings.missing_channel);
+        assert_eq!(settings.psd_layers, load_settings.psd_layers);
+        assert_eq!(settings.levels, levels);
+    }
+
+    #[test]
+    fn saved_project_reproduces_the_export() {
+        let dir = std::env::temp_dir().join(format!("smix-gui-project-{}", std::process::id()));
+        let masks = dir.join("hero");
+        std::fs::create_dir_all(&masks).unwrap();
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 255, 255, 255])).save(masks.join("body.png")).unwrap();
+        let load_settings = Settings {
+            map: "r=body.png,b=0.5".parse().unwrap(),
+            missing_channel: Some(smix::Fallback::Black),
+            psd_layers: vec!["Base".into(), "Trim".into(), "Accent".into()],
+            ..Settings::default()
+        };
+        let mut preview = PreView::new(
+            [0.5, 0.25, 0.0],
+            IndexMap::from([("hero".to_string(), Runner::load_mask_set(&load_settings, &masks, |_: smix_runner::Event<'_>| {}).unwrap())]),
+            IndexMap::from([("hero".to_string(), masks.clone())]),
+            BTreeMap::new(),
+            "{name}_{scale}".into(),
+        )
+        .with_load_settings(load_settings)
+        .with_filter(Filter::Mitchell)
+        .with_mix(Mix::Average)
+        .with_levels(Levels { gain: [2.0, 1.0, 1.0], bias: [0.0, 0.0, -0.25] })
+        .with_white_balance(WhiteBalance { temperature: 5000.0, tint: 0.25 });
+        preview.current.scale = 0.5;
+        preview.sharpen = Some(Sharpen { amount: 0.75, radius: 2.0 });
+        let saved = preview.project();
+        let path = dir.join(format!("session.{}", project::EXTENSION));
+        saved.save(&path).unwrap();
+
+        let mut reopened = PreView::new(
+            [0.0; 3],
+            IndexMap::from([("other".to_string(), Mask::procedural(4, 4, &[Pattern::Solid(0.0); 3]))]),
+            IndexMap::from([("other".to_string(), PathBuf::from("other"))]),
+            BTreeMap::new(),
+            naming::DEFAULT_TEMPLATE.into(),
+        );
+        reopened.open_project(Project::load(&path).unwrap()).unwrap();
+        let restored = reopened.project();
+        assert_eq!(restored.export, saved.export);
+        assert_eq!(restored.load, saved.load);
+        assert_eq!(restored.masks[0].weight, [0.5, 0.25, 0.0]);
+        assert!(reopened.current == preview.current, "the export settings differ");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "node-editor")]
+    #[test]
+    fn saved_project_keeps_the_node_graph() {
+        use crate::nodes::{Graph, GraphNode, Node, NodeEditor, Wire};
+
+        let mask = Mask::procedural(6, 3, &[Pattern::Solid(1.0), Pattern::Solid(0.5), Pattern::Solid(0.0)]);
+        let masks = IndexMap::from([("hero".to_string(), mask)]);
+        let mut project = Project::default();
+        let graph = Graph {
+            nodes: vec![
+                GraphNode { node: Node::Mask("hero".into()), pos: [0.0, 0.0] },
+                GraphNode { node: Node::Mix([1.0, 0.0, 0.0]), pos: [200.0, 0.0] },
+                GraphNode { node: Node::Tint([0.5, 1.0, 1.0]), pos: [400.0, 0.0] },
+                GraphNode { node: Node::Output, pos: [600.0, 0.0] },
+            ],
+            wires: vec![Wire { from: 0, to: 1, input: 0 }, Wire { from: 1, to: 2, input: 0 }, Wire { from: 2, to: 3, input: 0 }],
+        };
+        project.nodes = Some(NodeEditor::from_graph(&graph).unwrap().graph());
+        let restored: Project = toml::from_str(&toml::to_string_pretty(&project).unwrap()).unwrap();
+        let restored = restored.nodes.unwrap();
+        assert_eq!(restored, graph);
+        let img = restored.evaluate(&masks, &Registry::default()).unwrap();
+        assert_eq!(img.dimensions(), (6, 3));
+        assert_eq!(img.get_rgba().get_pixel(0, 0).0, [128, 255, 255, 255]);
+
+        let mut unwired = graph;
+        unwired.wires.push(Wire { from: 3, to: 0, input: 0 });
+        assert!(NodeEditor::from_graph(&unwired).is_err(), "the output node has no output pin");
+    }
+}
@@ -0,0 +1,179 @@
+//! Translations of the preview window's labels: a key table with one column
+//! per [`Language`], picked in the settings window.
+
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    /// Simplified Chinese
+    #[serde(rename = "zh")]
+    Chinese,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::Chinese];
+
+    /// Name of the language in itself.
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Chinese => "简体中文",
+        }
+    }
+}
+
+/// Key, English, Chinese
+const STRINGS: &[(&str, &str, &str)] = &[
+    ("open-project", "Open project", "打开项目"),
+    ("save-project", "Save project", "保存项目"),
+    ("settings", "Settings", "设置"),
+    ("import-mask", "Import mask...", "导入遮罩……"),
+    ("import-folders", "Folders of r/g/b files", "含 r/g/b 文件的文件夹"),
+    ("import-packed", "Packed images", "通道打包图像"),
+    ("imported", "Imported masks:", "已导入遮罩："),
+    ("import-failed", "Import failed", "导入失败"),
+    ("load-errors", "Mask sets that failed to load", "加载失败的遮罩组"),
+    ("retry", "Retry", "重试"),
+    ("node-editor", "Node editor", "节点编辑器"),
+    ("masks", "Masks", "遮罩"),
+    ("weights", "Weights:", "权重："),
+    ("mix", "Mix", "混合方式"),
+    ("mix-hint", "sum: weights as given; average: weights scaled to add up to 1", "sum：按原权重相加；average：权重缩放至总和为 1"),
+    ("presets", "Presets", "预设"),
+    ("blend-presets", "Blend presets", "混合预设"),
+    ("randomize", "Randomize", "随机"),
+    ("sum-to-one", "Sum to 1", "总和为 1"),
+    ("seed", "Seed:", "种子："),
+    ("reset-weights", "Reset to CLI args", "重置为命令行参数"),
+    ("copy-to-all", "Copy to all", "应用到全部"),
+    ("copy-to-all-hint", "Give every mask these weights", "将当前权重应用到所有遮罩"),
+    ("add", "Add", "添加"),
+    ("advanced", "Advanced", "高级"),
+    ("gain", "Gain", "增益"),
+    ("bias", "Bias", "偏移"),
+    ("reset-levels", "Reset gain and bias", "重置增益和偏移"),
+    ("auto-levels", "Auto levels", "自动色阶"),
+    ("auto-levels-hint", "Stretch each channel of the shown mask from its darkest to its brightest value", "将当前遮罩每个通道从最暗值到最亮值拉伸到完整范围"),
+    ("mask-stats", "Shown mask", "当前遮罩"),
+    ("range", "Range", "范围"),
+    ("mean", "Mean", "平均值"),
+    ("coverage", "Coverage", "覆盖率"),
+    ("scale", "Scale", "缩放"),
+    ("filter", "Filter", "缩放滤镜"),
+    ("white-balance", "White balance", "白平衡"),
+    ("temperature", "Temperature", "色温"),
+    ("temperature-hint", "Color temperature of the light: lower warms, higher cools; 6500 K is neutral", "光源色温：越低越暖，越高越冷；6500 K 为中性"),
+    ("tint", "Tint", "色调"),
+    ("tint-hint", "Negative toward green, positive toward magenta", "负值偏绿，正值偏品红"),
+    ("reset-white-balance", "Reset white balance", "重置白平衡"),
+    ("simulate", "Simulate", "模拟"),
+    ("normal-vision", "normal vision", "正常视觉"),
+    ("preview-only", "Preview only; exports are unaffected", "仅用于预览，不影响导出"),
+    ("sharpen-on-export", "Sharpen on export", "导出时锐化"),
+    ("amount", "Amount", "强度"),
+    ("radius", "Radius", "半径"),
+    ("post-processing", "Post-processing", "后期处理"),
+    ("name-template", "Name template:", "文件名模板："),
+    ("save", "Save", "保存"),
+    ("save-selected", "Save selected", "保存所选"),
+    ("copy-command", "Copy command", "复制命令"),
+    ("command-copied", "Command copied", "命令已复制"),
+    ("copy-image", "Copy image", "复制图像"),
+    ("pin-view", "Pin view", "固定视图"),
+    ("pin-view-hint", "Keep this mask and weights on screen next to the live preview", "在实时预览旁保留当前遮罩和权重"),
+    ("unpin", "Unpin", "取消固定"),
+    ("image-copied", "Image copied", "图像已复制"),
+    ("copy-image-failed", "Copying the image failed:", "复制图像失败："),
+    ("drag-out-hint", "Drag the image out to save it as a PNG", "拖出图像以保存为 PNG"),
+    ("drag-out-failed", "Dragging the image out failed:", "拖出图像失败："),
+    ("cancel", "Cancel", "取消"),
+    ("save-dialog-title", "Save the preview image", "保存预览图像"),
+    ("export-cancelled", "Export cancelled", "导出已取消"),
+    ("images-saved", "Images saved:", "已保存图像："),
+    ("save-failed", "Saving failed:", "保存失败："),
+    ("recent-exports", "Recent exports", "最近导出"),
+    ("reveal", "Reveal", "在文件管理器中显示"),
+    ("reveal-failed", "Opening the file manager failed:", "打开文件管理器失败："),
+    ("loading", "Loading...", "加载中……"),
+    ("reloaded", "Reloaded", "已重新加载"),
+    ("reload-failed", "Reloading failed", "重新加载失败"),
+    ("theme", "Theme:", "主题："),
+    ("theme-system", "System", "跟随系统"),
+    ("theme-dark", "Dark", "深色"),
+    ("theme-light", "Light", "浅色"),
+    ("ui-scale", "UI scale", "界面缩放"),
+    ("language", "Language:", "语言："),
+    ("perceptual-sliders", "Perceptual weight sliders", "感知式权重滑块"),
+    ("performance-overlay", "Performance overlay", "性能信息叠加层"),
+    ("raw", "raw", "原始值"),
+    ("node-mask", "Mask", "遮罩"),
+    ("node-mix", "Mix", "混合"),
+    ("node-blend", "Blend", "叠加"),
+    ("node-tint", "Tint", "着色"),
+    ("node-post", "Post", "后期"),
+    ("node-output", "Output", "输出"),
+    ("add-node", "Add node", "添加节点"),
+    ("remove", "Remove", "删除"),
+    ("add-node-hint", "Right-click the graph to add nodes.", "在图上右键添加节点。"),
+    ("export-graph", "Save output", "保存输出"),
+];
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(Language::English as u8);
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        1 => Language::Chinese,
+        _ => Language::English,
+    }
+}
+
+/// The label `key` in the current language; unknown keys are shown as is.
+pub fn tr(key: &'static str) -> &'static str {
+    let Some(&(_, english, chinese)) = STRINGS.iter().find(|(k, _, _)| *k == key) else {
+        return key;
+    };
+    match language() {
+        Language::English => english,
+        Language::Chinese => chinese,
+    }
+}
+
+/// System fonts with CJK glyphs, which egui's built-in fonts lack.
+const CJK_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/STHeiti Medium.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+];
+
+/// Add the first CJK system font found as a fallback of `ctx`'s fonts, once.
+pub fn install_cjk_font(ctx: &egui::Context) {
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+    if INSTALLED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let Some(data) = CJK_FONTS.iter().find_map(|path| std::fs::read(path).ok()) else {
+        eprintln!("Warning: no CJK font found, Chinese labels may not render");
+        return;
+    };
+    let mut fonts = egui::FontDefinitions::default();
+    fonts.font_data.insert("cjk".into(), egui::FontData::from_owned(data).into());
+    for family in [egui::FontFamily::Proportional, egui::FontFamily::Monospace] {
+        fonts.families.entry(family).or_default().push("cjk".into());
+    }
+    ctx.set_fonts(fonts);
+}
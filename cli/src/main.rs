@@ -1,175 +1,738 @@
-use std::{collections::HashMap, io::{stdout, Write}, path::{PathBuf}};
+use std::{path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, OnceLock}};
 
-use anyhow::{ensure};
-use clap::{Parser, ValueEnum};
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use eframe::egui;
-use smix::{Mask};
+use smix::{adjust::{self, WhiteBalance}, cancel::{CancelToken, Cancelled}, canvas::{Anchor, Canvas}, colorspace::ColorSpace, layers, naming, post::Sharpen, quantize::Quantize, sprite::SpriteGrid, BitDepth, ChannelMap, ExportOptions, Fallback, Levels, MixSemantics, OutputFormat, ResamplePrecision, ResizeFilter, ResizeSpace};
+use smix_runner::{summary::SummaryFormat, thumbnails::{self, ThumbnailCache}, Loaded, MaskOrder, NameCollision, Runner, Settings};
 
+use serde::{Deserialize, Serialize};
+
+use crate::command::Command;
+use crate::config::Config;
 use crate::gui::PreView;
 
+/// Set when the image itself is written to stdout; status lines then go to stderr.
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// `println!` for progress and status lines, kept off stdout when it carries image data.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::STATUS_TO_STDERR.load(::std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod command;
+pub mod config;
 pub mod gui;
+pub mod i18n;
+#[cfg(feature = "node-editor")]
+pub mod nodes;
+pub mod perf;
+pub mod pipeline;
+pub mod project;
+pub mod report;
+pub mod settings;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod watch;
+pub mod weights;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Image mixer (RGB channels only)", long_about = None)]
+#[command(name = "smix", args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(flatten)]
+    args: Option<Args>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Args, Debug)]
 pub struct Args {
-    /// Red channel weight, 0~1 positive float
+    /// Red channel weight, 0~1 float (-1~1 with --allow-negative)
+    #[arg(required_unless_present = "weights", default_value_t = 0.0, allow_negative_numbers = true)]
     r: f32,
-    /// Green channel weight, 0~1 positive float
+    /// Green channel weight, 0~1 float (-1~1 with --allow-negative)
+    #[arg(required_unless_present = "weights", default_value_t = 0.0, allow_negative_numbers = true)]
     g: f32,
-    /// Blue channel weight, 0~1 positive float
+    /// Blue channel weight, 0~1 float (-1~1 with --allow-negative)
+    #[arg(required_unless_present = "weights", default_value_t = 0.0, allow_negative_numbers = true)]
     b: f32,
 
-    /// Output directory (create if missing)
-    #[arg(short, long, default_value = "output")]
+    /// Weights as an expression over smix.toml presets and numbers instead of R G B, e.g. "lerp(summer, winter, 0.3)"
+    #[arg(long, env = "SMIX_WEIGHTS", allow_hyphen_values = true, conflicts_with_all = ["r", "g", "b"])]
+    weights: Option<String>,
+
+    /// Accept weights down to -1, subtracting their mask; the mix is clamped to 0~1 before resizing
+    #[arg(long, env = "SMIX_ALLOW_NEGATIVE")]
+    allow_negative: bool,
+
+    /// Multiply channel masks before mixing, e.g. "r=1.2,b=0.9" to brighten masks authored too dark
+    #[arg(long, env = "SMIX_GAIN", value_name = "CHANNEL=GAIN,...", value_parser = parse_gain)]
+    gain: Option<[f32; 3]>,
+
+    /// Add to channel masks before mixing (after --gain), e.g. "g=0.05"; results are clamped to 0~1
+    #[arg(long, env = "SMIX_BIAS", value_name = "CHANNEL=BIAS,...", value_parser = parse_bias, allow_hyphen_values = true)]
+    bias: Option<[f32; 3]>,
+
+    /// Mix as the weighted sum of the masks, or their weighted average (weights scaled to add up to 1)
+    #[arg(long, env = "SMIX_MIX", value_enum, default_value_t = Mix::Sum)]
+    mix: Mix,
+
+    /// Output directory (create if missing), or `-` to write a single image to stdout
+    #[arg(short, long, env = "SMIX_OUTPUT", default_value = "output")]
     output: PathBuf,
 
-    /// Directory containing r.png, g.png, b.png
-    #[arg(short, long, required = true, value_delimiter = ' ', num_args = 1..)]
+    /// Directory containing r.png, g.png, b.png (or the files given by --map), a layered .psd, a packed image with one mask per RGB channel, an archive like set.zip#inner/dir, or an http(s) URL
+    #[arg(short, long, env = "SMIX_MASK_DIRECTORIES", value_delimiter = ' ', num_args = 1..)]
     mask_directories: Vec<PathBuf>,
 
     /// Multiple scale factors; one file per factor (>0)
-    #[arg(short, long, value_delimiter = ' ', num_args = 1..)]
+    #[arg(short, long, env = "SMIX_SCALE", value_delimiter = ' ', num_args = 1..)]
     scale: Vec<f32>,
 
+    /// Also export N successively halved levels of detail of every scale as _lod0 to _lodN, resampled from the full precision mix unless --resample-precision says otherwise
+    #[arg(long, env = "SMIX_LODS", value_name = "N")]
+    lods: Option<u32>,
+
     /// Resize filter used when scaling masks.
-    #[arg(short, long, value_enum, default_value_t = Filter::Lanczos3)]
+    #[arg(short, long, env = "SMIX_FILTER", value_enum, default_value_t = Filter::Lanczos3)]
     filter: Filter,
 
-    /// Setup a preview gui
-    #[arg(short, long, default_value = "true")]
-    preview: bool
+    /// Output file name without extension; placeholders {mask} {width} {height} {scale} {r} {g} {b} {lod}
+    #[arg(long, env = "SMIX_NAME_TEMPLATE", default_value = naming::DEFAULT_TEMPLATE)]
+    name_template: String,
+
+    /// Subdirectory of the output directory for each file, with the --name-template placeholders, e.g. "{mask}/{scale}/"
+    #[arg(long, env = "SMIX_OUTPUT_LAYOUT", value_name = "TEMPLATE")]
+    output_layout: Option<String>,
+
+    /// Write into a subdirectory of the output directory for this run, named NAME or by the time with "auto"; recorded in manifest.json
+    #[arg(long, env = "SMIX_RUN_ID", value_name = "auto|NAME")]
+    run_id: Option<String>,
+
+    /// Output image formats, e.g. "png,webp,ktx2"; every format is encoded from the same mix
+    #[arg(long, env = "SMIX_FORMAT", value_enum, value_delimiter = ',', default_values_t = [Format::Png])]
+    format: Vec<Format>,
+
+    /// Lossy quality 1-100 for AVIF
+    #[arg(long, env = "SMIX_QUALITY", value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+
+    /// Encoder speed 1-10 for AVIF and JPEG XL; slower compresses better
+    #[arg(long, env = "SMIX_SPEED", value_parser = clap::value_parser!(u8).range(1..=10))]
+    speed: Option<u8>,
+
+    /// Treat masks as sprite sheets of CxR frames (e.g. 4x2)
+    #[arg(long, env = "SMIX_SPRITE_GRID")]
+    sprite_grid: Option<SpriteGrid>,
+
+    /// Re-pack the sprite frames into this CxR grid
+    #[arg(long, requires = "sprite_grid")]
+    sprite_repack: Option<SpriteGrid>,
+
+    /// Also export every sprite frame as its own file
+    #[arg(long, requires = "sprite_grid")]
+    sprite_frames: bool,
+
+    /// Channel files in each mask directory, e.g. r=body.png,g=trim.png,b=accent.png; a #rrggbb color or 0~1 value fills a channel flat
+    #[arg(long, env = "SMIX_MAP", value_name = "r=FILE,g=FILE,b=FILE", default_value_t)]
+    map: ChannelMap,
+
+    /// Layers used as the R, G, B masks of .psd mask files
+    #[arg(long, env = "SMIX_PSD_LAYERS", value_name = "R,G,B", value_delimiter = ',', default_values = layers::DEFAULT_LAYERS)]
+    psd_layers: Vec<String>,
+
+    /// Substitute missing r/g/b.png with `black` or the given image instead of failing
+    #[arg(long, env = "SMIX_MISSING_CHANNEL", value_name = "black|PATH")]
+    missing_channel: Option<Fallback>,
+
+    /// Skip mask sets that fail to load, listing them at the end, instead of aborting
+    #[arg(long, env = "SMIX_SKIP_INVALID")]
+    skip_invalid: bool,
+
+    /// Refuse to load mask sets estimated to need more memory than this, e.g. 8G
+    #[arg(long, env = "SMIX_MAX_MEMORY", value_name = "SIZE", value_parser = parse_size)]
+    max_memory: Option<u64>,
+
+    /// Fail instead of warning when the weights are all 0 or add up far from 1, e.g. in CI
+    #[arg(long, env = "SMIX_STRICT")]
+    strict: bool,
+
+    /// When two mask sets have the same name: fail, suffix later ones with _2, _3..., or name all by their path below the directory they share
+    #[arg(long, env = "SMIX_ON_NAME_COLLISION", value_enum, default_value_t = Collision::Error)]
+    on_name_collision: Collision,
+
+    /// Order to list and generate mask sets in: as given, by name, or by pixel count
+    #[arg(long, env = "SMIX_SORT", value_enum, default_value_t = Sort::None)]
+    sort: Sort,
+
+    /// Per-pixel mix expression over the mask values r, g, b and the weights wr, wg, wb, e.g. "r*0.8 + g*max(0.1, b)" (needs the `script` feature)
+    #[arg(long, env = "SMIX_EXPR")]
+    expr: Option<String>,
+
+    /// Skip outputs whose inputs are unchanged since the last run
+    #[arg(long, env = "SMIX_INCREMENTAL")]
+    incremental: bool,
+
+    /// Also write manifest.json and/or manifest.csv listing every output with its parameters and SHA-256
+    #[arg(long, env = "SMIX_MANIFEST", value_enum, value_delimiter = ',')]
+    manifest: Vec<ManifestFormat>,
+
+    /// Equalize the histogram of each mix before resizing, to rescue low-contrast mixes; per-channel also shifts hues
+    #[arg(long, env = "SMIX_EQUALIZE", value_enum, num_args = 0..=1, default_missing_value = "luminance")]
+    equalize: Option<Equalize>,
+
+    /// Warm or cool each mix as if lit at this color temperature in Kelvin (1000~40000; 6500 is neutral)
+    #[arg(long, env = "SMIX_TEMPERATURE")]
+    temperature: Option<f32>,
+
+    /// Tint each mix toward green (-1) or magenta (1)
+    #[arg(long, env = "SMIX_TINT", allow_negative_numbers = true)]
+    tint: Option<f32>,
+
+    /// Boost the saturation of unsaturated pixels by this much (-1~1), leaving saturated ones nearly as they are
+    #[arg(long, env = "SMIX_VIBRANCE", allow_negative_numbers = true)]
+    vibrance: Option<f32>,
+
+    /// Lower the saturation of pixels above this (0~1), e.g. to keep within a saturation budget
+    #[arg(long, env = "SMIX_MAX_SATURATION")]
+    max_saturation: Option<f32>,
+
+    /// Convert each mix from sRGB into this color space last; PNG and half-float EXR outputs are tagged with it
+    #[arg(long, env = "SMIX_OUTPUT_COLORSPACE", value_enum, default_value_t = OutputColorSpace::Srgb)]
+    output_colorspace: OutputColorSpace,
+
+    /// Unsharp mask strength applied after resizing (e.g. 0.5)
+    #[arg(long, env = "SMIX_SHARPEN")]
+    sharpen: Option<f32>,
+
+    /// Unsharp mask blur radius in output pixels
+    #[arg(long, env = "SMIX_SHARPEN_RADIUS", default_value_t = 1.0, requires = "sharpen")]
+    sharpen_radius: f32,
+
+    /// Re-compress PNG output losslessly: oxipng level 0-6, 7 adds zopfli (needs the `optimize` feature)
+    #[arg(long, env = "SMIX_OPTIMIZE", value_parser = clap::value_parser!(u8).range(0..=7))]
+    optimize: Option<u8>,
+
+    /// Quantize the output to N colors (2-256); PNG is written as indexed color
+    #[arg(long, env = "SMIX_PALETTE", value_parser = clap::value_parser!(u16).range(2..=256))]
+    palette: Option<u16>,
+
+    /// Dither when quantizing with --palette
+    #[arg(long, env = "SMIX_DITHER", requires = "palette")]
+    dither: bool,
+
+    /// Post-processing steps applied after resizing, by name (built-in: sharpen, grayscale, invert)
+    #[arg(long, env = "SMIX_POST", value_delimiter = ',')]
+    post: Vec<String>,
+
+    /// Dynamic library adding post-processing steps (needs the `plugins` feature)
+    #[arg(long = "plugin", env = "SMIX_PLUGINS", value_delimiter = ' ')]
+    plugins: Vec<PathBuf>,
+
+    /// Burn a caption (weights, matching preset, date) into a corner of every output
+    #[arg(long, env = "SMIX_ANNOTATE")]
+    annotate: bool,
+
+    /// Crop outputs to their visible pixels plus an optional margin; offsets, also on the --canvas, are written to trim.json
+    #[arg(long, env = "SMIX_TRIM", value_name = "MARGIN", num_args = 0..=1, default_missing_value = "0")]
+    trim: Option<u32>,
+
+    /// Place outputs on a WxH canvas without scaling (or `pot` for the next power-of-two size)
+    #[arg(long, env = "SMIX_CANVAS", value_name = "WxH|pot")]
+    canvas: Option<Canvas>,
+
+    /// Pad outputs to power-of-two dimensions; same as --canvas pot
+    #[arg(long, env = "SMIX_POT", conflicts_with = "canvas")]
+    pot: bool,
+
+    /// Position on the --canvas/--pot canvas: top-left, top, top-right, left, center, right, bottom-left, bottom, bottom-right
+    #[arg(long, env = "SMIX_ANCHOR", default_value_t)]
+    anchor: Anchor,
+
+    /// Resize in linear light (auto: when downscaling) or on the sRGB values; linear keeps fine bright detail from darkening
+    #[arg(long, env = "SMIX_RESIZE_SPACE", value_enum, default_value_t = Space::Auto)]
+    resize_space: Space,
+
+    /// Resize the 8-bit image, or the full precision mix and quantize afterwards (smoother gradients); linear resizing always uses f32 [default: u8, f32 with --lods]
+    #[arg(long, env = "SMIX_RESAMPLE_PRECISION", value_enum)]
+    resample_precision: Option<Precision>,
+
+    /// Bits per channel of PNG and TIFF outputs; auto writes 16 when the masks are 16-bit images
+    #[arg(long, env = "SMIX_BIT_DEPTH", value_enum, default_value_t = Depth::Auto)]
+    bit_depth: Depth,
+
+    /// Bleed edge colors N pixels into transparent areas to avoid dark halos when mipmapped
+    #[arg(long, env = "SMIX_PADDING", value_name = "N")]
+    padding: Option<u32>,
+
+    /// Masks are tileable textures; resize with wrapped edges
+    #[arg(long, env = "SMIX_TILEABLE")]
+    tileable: bool,
+
+    /// Pin encoder settings, encode AVIF on one thread, resize exactly and strip metadata for byte-identical outputs
+    #[arg(long, env = "SMIX_DETERMINISTIC")]
+    deterministic: bool,
+
+    /// Worker threads for batch generation and resizing (0 = one per CPU)
+    #[arg(short, long, env = "SMIX_JOBS", default_value_t = 0)]
+    jobs: usize,
+
+    /// Setup a preview gui; pass `false` to generate headlessly
+    #[arg(short, long, env = "SMIX_PREVIEW", default_value_t = true, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
+    preview: bool,
+
+    /// Pick masks, adjust weights and generate in the terminal instead of the preview window, e.g. over SSH
+    #[cfg(feature = "tui")]
+    #[arg(long, env = "SMIX_TUI", conflicts_with = "preview")]
+    tui: bool,
 }
 
+/// Exit code of a batch interrupted by SIGINT or SIGTERM, as shells report Ctrl+C.
+const INTERRUPTED: i32 = 130;
+
 fn main() -> anyhow::Result<()> {
-    let mut env = Env::new();
+    match run() {
+        Err(e) if e.downcast_ref::<Cancelled>().is_some() => {
+            eprintln!("Interrupted; finished outputs and reports were kept");
+            std::process::exit(INTERRUPTED);
+        }
+        result => result,
+    }
+}
+
+/// Cancelled on the first SIGINT or SIGTERM, so batches stop between outputs;
+/// a second signal exits right away.
+pub fn interrupt_token() -> anyhow::Result<CancelToken> {
+    static TOKEN: OnceLock<CancelToken> = OnceLock::new();
+    if let Some(token) = TOKEN.get() {
+        return Ok(token.clone());
+    }
+    let token = TOKEN.get_or_init(CancelToken::new).clone();
+    let handler = token.clone();
+    ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            std::process::exit(INTERRUPTED);
+        }
+        eprintln!("Stopping after the outputs in progress (interrupt again to quit now)");
+        handler.cancel();
+    })?;
+    Ok(token)
+}
+
+fn run() -> anyhow::Result<()> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches)?;
+    if let Some(command) = cli.command {
+        return command.run();
+    }
+    let mut args = cli.args.expect("generation arguments are required without a subcommand");
+    let config_path = Config::path();
+    let mut config = match &config_path {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let presets = std::mem::take(&mut config.presets);
+    if let Some(expr) = &args.weights {
+        [args.r, args.g, args.b] = weights::eval(expr, &presets)?;
+    }
+    args.apply_config(config, &matches);
+    STATUS_TO_STDERR.store(args.writes_stdout(), Ordering::Relaxed);
+    if let Some(path) = &config_path {
+        status!("Config: {}", path.display());
+    }
 
-    env.ensure_args()?;
+    let mut runner = Runner::new(args.settings(), presets, report::Terminal);
 
-    env.load_mask()?;
+    runner.prepare()?;
 
-    if env.args.preview {
-        return env.preview();
+    runner.load_plugins()?;
+
+    #[cfg(feature = "tui")]
+    if args.tui {
+        runner.load_masks_lenient();
+        return tui::run(runner);
+    }
+    if args.preview && !args.writes_stdout() {
+        // Proxies are cached per working directory, the project smix.toml is read from
+        if let Some(cache_root) = dirs::cache_dir() {
+            let cache = ThumbnailCache::for_project(&cache_root, Path::new("."), thumbnails::DEFAULT_SIZE);
+            runner = runner.with_thumbnails(cache);
+        }
+        // Broken sets are listed in the window instead of failing the launch
+        runner.load_masks_lenient();
+        return preview(runner, args.filter, args.mix);
     } else {
-        env.generate()?;
+        let mut runner = runner.with_cancel(interrupt_token()?);
+        runner.load_masks()?;
+        runner.generate()?;
     }
 
     Ok(())
 }
 
-#[derive(ValueEnum, Clone, Copy, Debug)]
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
 pub enum Filter {
     /// Nearest-neighbor
     Nearest,
+    /// Area average
+    Box,
     /// Linear interpolation
     Bilinear,
+    /// Short windowed sinc, between bilinear and Lanczos in sharpness
+    Hamming,
     /// Cubic interpolation
     CatmullRom,
+    /// Cubic with less ringing than Catmull-Rom
+    Mitchell,
     /// Gaussian blur
     Gaussian,
     /// Lanczos with window 3
     Lanczos3,
 }
 
-impl Into<image::imageops::FilterType> for Filter {
-    fn into(self) -> image::imageops::FilterType {
-        use image::imageops::FilterType::*;
-        match self {
-            Filter::Nearest => Nearest,
-            Filter::Bilinear => Triangle,
-            Filter::CatmullRom => CatmullRom,
-            Filter::Gaussian => Gaussian,
-            Filter::Lanczos3 => Lanczos3
+impl From<Filter> for ResizeFilter {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => Self::Nearest,
+            Filter::Box => Self::Box,
+            Filter::Bilinear => Self::Triangle,
+            Filter::Hamming => Self::Hamming,
+            Filter::CatmullRom => Self::CatmullRom,
+            Filter::Mitchell => Self::Mitchell,
+            Filter::Gaussian => Self::Gaussian,
+            Filter::Lanczos3 => Self::Lanczos3,
+        }
+    }
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mix {
+    Sum,
+    Average,
+}
+
+impl From<Mix> for MixSemantics {
+    fn from(mix: Mix) -> Self {
+        match mix {
+            Mix::Sum => MixSemantics::Sum,
+            Mix::Average => MixSemantics::Average,
         }
     }
 }
 
-pub struct Env {
-    args: Args,
-    masks: HashMap<String, Mask>,
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Space {
+    Auto,
+    Linear,
+    Srgb,
 }
 
-impl Env {
-    pub fn new() -> Self {
-        Self {
-            args: Args::parse(),
-            masks: HashMap::new(),
+impl From<Space> for ResizeSpace {
+    fn from(space: Space) -> Self {
+        match space {
+            Space::Auto => ResizeSpace::Auto,
+            Space::Linear => ResizeSpace::Linear,
+            Space::Srgb => ResizeSpace::Srgb,
         }
     }
+}
 
-    pub fn preview(self) -> anyhow::Result<()> {
-        let options = eframe::NativeOptions {
-            viewport: egui::ViewportBuilder::default()
-                .with_min_inner_size([768.0, 512.0]).into(),
-            ..Default::default()
-        };
-        let _ = eframe::run_native(
-            "smix preview",
-            options,
-            Box::new(|_cc| Ok(
-                Box::new(
-                    PreView::new([self.args.r, self.args.g, self.args.b], self.masks)
-                )
-            )
-        ));
-        Ok(())
-    }
-
-    pub fn generate(self) -> anyhow::Result<()> {
-        let weight = &[self.args.r, self.args.g, self.args.b];
-        for (i, &s) in self.args.scale.iter().enumerate() {
-            for (name, mask) in &self.masks {
-                let img = mask.generate(weight);
-                if s < 0.0 {
-                    println!("Scale factor should be positive, but {s} at {i} is negative");
-                    continue;
-                }
-                let (width, height) = img.dimensions();
-                let nwidth = (width as f32 * s) as u32;
-                let nheight = (height as f32 * s) as u32;
-                let output_name = img.export_name(&name, nwidth, nheight);
-
-                print!("Generating {output_name}...");
-                stdout().flush()?;
-                if s == 1.0 {
-                    img.save(self.args.output.join(output_name))?;
-                } else {
-                    img.save_as(self.args.output.join(output_name), nwidth, nheight, self.args.filter.into())?;
-                }
-                println!("done");
-            }
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Equalize {
+    Luminance,
+    PerChannel,
+}
+
+impl From<Equalize> for adjust::Equalize {
+    fn from(equalize: Equalize) -> Self {
+        match equalize {
+            Equalize::Luminance => adjust::Equalize::Luminance,
+            Equalize::PerChannel => adjust::Equalize::PerChannel,
         }
-        Ok(())
     }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum OutputColorSpace {
+    Srgb,
+    DisplayP3,
+    Rec2020,
+}
 
-    pub fn load_mask(&mut self) -> anyhow::Result<()> {
-        for path in &self.args.mask_directories {
-            let mask = Mask::new(&path)?;
-            let name = format!("{}", path.display());
-            let name = name.split("/").last().unwrap_or("result");
-            self.masks.insert(name.into(), mask);
+impl From<OutputColorSpace> for ColorSpace {
+    fn from(space: OutputColorSpace) -> Self {
+        match space {
+            OutputColorSpace::Srgb => ColorSpace::Srgb,
+            OutputColorSpace::DisplayP3 => ColorSpace::DisplayP3,
+            OutputColorSpace::Rec2020 => ColorSpace::Rec2020,
         }
-        Ok(())
     }
+}
 
-    pub fn ensure_args(&mut self) -> anyhow::Result<()> {
-        ensure!(self.args.r >= 0.0 && self.args.r <= 1.0, "Red weight must be in [0, 1]");
-        ensure!(self.args.g >= 0.0 && self.args.g <= 1.0, "Green weight must be in [0, 1]");
-        ensure!(self.args.b >= 0.0 && self.args.b <= 1.0, "Blue weight must be in [0, 1]");
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Precision {
+    U8,
+    F32,
+}
 
-        println!("RGB weights: ({}, {}, {})", self.args.r, self.args.g, self.args.b);
+impl From<Precision> for ResamplePrecision {
+    fn from(precision: Precision) -> Self {
+        match precision {
+            Precision::U8 => ResamplePrecision::U8,
+            Precision::F32 => ResamplePrecision::F32,
+        }
+    }
+}
 
-        self.args.scale.push(1.0);
-        self.args.scale.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-        self.args.scale.dedup();
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Depth {
+    Auto,
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
 
-        if !self.args.output.exists() {
-            println!("Output directory does not exists");
-            std::fs::create_dir_all(&self.args.output)?;
-            println!("Create directory: {}", self.args.output.display());
-        } else  {
-            println!("Output directory: {}", self.args.output.display());
+impl From<Depth> for BitDepth {
+    fn from(depth: Depth) -> Self {
+        match depth {
+            Depth::Auto => BitDepth::Auto,
+            Depth::Eight => BitDepth::Eight,
+            Depth::Sixteen => BitDepth::Sixteen,
         }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ManifestFormat {
+    Json,
+    Csv,
+}
 
-        Ok(())
+impl From<ManifestFormat> for SummaryFormat {
+    fn from(format: ManifestFormat) -> Self {
+        match format {
+            ManifestFormat::Json => SummaryFormat::Json,
+            ManifestFormat::Csv => SummaryFormat::Csv,
+        }
     }
-}
\ No newline at end of file
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Collision {
+    Error,
+    Suffix,
+    Path,
+}
+
+impl From<Collision> for NameCollision {
+    fn from(collision: Collision) -> Self {
+        match collision {
+            Collision::Error => NameCollision::Error,
+            Collision::Suffix => NameCollision::Suffix,
+            Collision::Path => NameCollision::Path,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Sort {
+    Name,
+    Size,
+    None,
+}
+
+impl From<Sort> for MaskOrder {
+    fn from(sort: Sort) -> Self {
+        match sort {
+            Sort::Name => MaskOrder::Name,
+            Sort::Size => MaskOrder::Size,
+            Sort::None => MaskOrder::None,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    Png,
+    Webp,
+    Tga,
+    Bmp,
+    Tiff,
+    Qoi,
+    #[cfg(feature = "avif")]
+    Avif,
+    /// Lossless JPEG XL
+    #[cfg(feature = "jxl")]
+    Jxl,
+    /// Half-float OpenEXR in linear light
+    #[cfg(feature = "f16")]
+    #[value(name = "exr16")]
+    Exr16,
+    /// Uncompressed 8-bit KTX2
+    Ktx2,
+    /// Uncompressed half-float KTX2 in linear light
+    #[cfg(feature = "f16")]
+    Ktx2F16,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        use image::ImageFormat::*;
+        OutputFormat::Image(match format {
+            Format::Png => Png,
+            Format::Webp => WebP,
+            Format::Tga => Tga,
+            Format::Bmp => Bmp,
+            Format::Tiff => Tiff,
+            Format::Qoi => Qoi,
+            #[cfg(feature = "avif")]
+            Format::Avif => Avif,
+            #[cfg(feature = "jxl")]
+            Format::Jxl => return OutputFormat::Jxl,
+            #[cfg(feature = "f16")]
+            Format::Exr16 => return OutputFormat::ExrF16,
+            Format::Ktx2 => return OutputFormat::Ktx2,
+            #[cfg(feature = "f16")]
+            Format::Ktx2F16 => return OutputFormat::Ktx2F16,
+        })
+    }
+}
+
+impl Args {
+    /// `--output -`: the encoded image goes to stdout instead of a directory.
+    pub fn writes_stdout(&self) -> bool {
+        self.output.as_os_str() == "-"
+    }
+
+    pub fn levels(&self) -> Levels {
+        let default = Levels::default();
+        Levels { gain: self.gain.unwrap_or(default.gain), bias: self.bias.unwrap_or(default.bias) }
+    }
+
+    /// The batch these arguments describe.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            weight: [self.r, self.g, self.b],
+            mix: self.mix.into(),
+            allow_negative: self.allow_negative,
+            levels: self.levels(),
+            output: self.output.clone(),
+            mask_directories: self.mask_directories.clone(),
+            scale: self.scale.clone(),
+            lods: self.lods,
+            name_template: self.name_template.clone(),
+            output_layout: self.output_layout.clone(),
+            run_id: self.run_id.clone(),
+            sprite_grid: self.sprite_grid,
+            sprite_repack: self.sprite_repack,
+            sprite_frames: self.sprite_frames,
+            map: self.map.clone(),
+            psd_layers: self.psd_layers.clone(),
+            missing_channel: self.missing_channel.clone(),
+            skip_invalid: self.skip_invalid,
+            max_memory: self.max_memory,
+            strict: self.strict,
+            name_collision: self.on_name_collision.into(),
+            sort: self.sort.into(),
+            expr: self.expr.clone(),
+            incremental: self.incremental,
+            summary: self.manifest.iter().map(|&format| format.into()).collect(),
+            plugins: self.plugins.clone(),
+            post: self.post.clone(),
+            annotate: self.annotate,
+            jobs: self.jobs,
+            export: ExportOptions {
+                filter: self.filter.into(),
+                format: self.format[0].into(),
+                quality: self.quality,
+                speed: self.speed,
+                deterministic: self.deterministic,
+                tileable: self.tileable,
+                resize_space: self.resize_space.into(),
+                // Levels of detail are resampled from the full precision mix unless asked otherwise
+                resample_precision: match (self.resample_precision, self.lods) {
+                    (Some(precision), _) => precision.into(),
+                    (None, Some(_)) => ResamplePrecision::F32,
+                    (None, None) => ResamplePrecision::U8,
+                },
+                bit_depth: self.bit_depth.into(),
+                equalize: self.equalize.map(Into::into),
+                white_balance: (self.temperature.is_some() || self.tint.is_some()).then(|| WhiteBalance {
+                    temperature: self.temperature.unwrap_or(adjust::NEUTRAL_TEMPERATURE),
+                    tint: self.tint.unwrap_or(0.0),
+                }),
+                vibrance: self.vibrance,
+                max_saturation: self.max_saturation,
+                color_space: self.output_colorspace.into(),
+                sharpen: self.sharpen.map(|amount| Sharpen { amount, radius: self.sharpen_radius }),
+                optimize: self.optimize,
+                palette: self.palette.map(|colors| Quantize { colors, dither: self.dither }),
+                padding: self.padding,
+                trim: self.trim,
+                canvas: if self.pot { Some(Canvas::PowerOfTwo) } else { self.canvas },
+                anchor: self.anchor,
+                ..ExportOptions::default()
+            },
+            extra_formats: self.format[1..].iter().map(|&format| format.into()).collect(),
+        }
+    }
+}
+
+fn parse_gain(s: &str) -> Result<[f32; 3], String> {
+    Levels::parse_channels(s, 1.0).map_err(|e| e.to_string())
+}
+
+fn parse_bias(s: &str) -> Result<[f32; 3], String> {
+    Levels::parse_channels(s, 0.0).map_err(|e| e.to_string())
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    smix_runner::memory::parse_size(s).map_err(|e| e.to_string())
+}
+
+/// Open the preview window on the masks loaded by `runner`.
+fn preview(runner: Runner, filter: Filter, mix: Mix) -> anyhow::Result<()> {
+    let loaded = runner.into_loaded();
+    if loaded.masks.is_empty() {
+        let errors: Vec<_> = loaded.failures.iter().map(|failure| failure.error.as_str()).collect();
+        anyhow::bail!("No mask set could be loaded:\n{}", errors.join("\n"));
+    }
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_min_inner_size([768.0, 512.0]),
+        ..Default::default()
+    };
+    let _ = eframe::run_native(
+        "smix preview",
+        options,
+        Box::new(|cc| {
+            let Loaded { settings, masks, paths, full_sizes, load_times, presets, registry, failures } = loaded;
+            let mut preview = PreView::new(settings.weight, masks, paths, presets, settings.name_template.clone())
+                .with_load_settings(settings.clone())
+                .with_full_sizes(full_sizes)
+                .with_load_times(load_times)
+                .with_post(registry, settings.post)
+                .with_filter(filter)
+                .with_mix(mix)
+                .with_negative_weights(settings.allow_negative)
+                .with_levels(settings.levels)
+                .with_white_balance(settings.export.white_balance.unwrap_or_default())
+                .with_output(settings.output)
+                .with_load_errors(failures.into_iter().map(|failure| (failure.source, failure.error)).collect());
+            preview.setup(&cc.egui_ctx);
+            Ok(Box::new(preview))
+        }),
+    );
+    Ok(())
+}
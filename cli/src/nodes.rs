@@ -0,0 +1,481 @@
+//! Node-based mixing graph for the preview window.
+//!
+//! Masks feed `Mix` nodes, whose images can be blended, tinted and run
+//! through post-processing steps before reaching the single `Output` node.
+//! The graph is evaluated again whenever it changes, saved with the project
+//! as a [`Graph`] and exported like a single mask.
+
+use indexmap::IndexMap;
+
+use eframe::egui::{self, Color32, DragValue, Slider};
+use egui_snarl::{
+    ui::{PinInfo, SnarlPin, SnarlStyle, SnarlViewer},
+    InPin, NodeId, OutPin, OutPinId, Snarl,
+};
+use image::{imageops, Rgba32FImage};
+use serde::{Deserialize, Serialize};
+use smix::{post::Registry, GeneratedImage, Mask};
+
+use crate::i18n::tr;
+
+const MASK_COLOR: Color32 = Color32::from_rgb(0xb0, 0x60, 0xe0);
+const IMAGE_COLOR: Color32 = Color32::from_rgb(0x60, 0xb0, 0xe0);
+/// Longest side of the output preview
+const PREVIEW_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlendMode {
+    Multiply,
+    Add,
+    Screen,
+    /// Linear interpolation from the first input to the second
+    Lerp,
+}
+
+impl BlendMode {
+    const ALL: [BlendMode; 4] = [BlendMode::Multiply, BlendMode::Add, BlendMode::Screen, BlendMode::Lerp];
+
+    fn apply(self, a: f32, b: f32, amount: f32) -> f32 {
+        match self {
+            BlendMode::Multiply => a * b,
+            BlendMode::Add => a + b,
+            BlendMode::Screen => 1.0 - (1.0 - a) * (1.0 - b),
+            BlendMode::Lerp => a + (b - a) * amount,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Node {
+    /// A loaded mask set, by key
+    Mask(String),
+    /// Weighted mix of a mask's channels
+    Mix([f32; 3]),
+    Blend { mode: BlendMode, amount: f32 },
+    /// Multiply the RGB of an image by a color
+    Tint([f32; 3]),
+    /// A registered post-processing step, by name
+    Post(String),
+    Output,
+}
+
+impl Node {
+    fn title(&self) -> String {
+        match self {
+            Node::Mask(key) => format!("{} {key}", tr("node-mask")),
+            Node::Mix(_) => tr("node-mix").into(),
+            Node::Blend { mode, .. } => format!("{} ({mode:?})", tr("node-blend")),
+            Node::Tint(_) => tr("node-tint").into(),
+            Node::Post(name) => format!("{} {name}", tr("node-post")),
+            Node::Output => tr("node-output").into(),
+        }
+    }
+
+    fn inputs(&self) -> usize {
+        match self {
+            Node::Mask(_) => 0,
+            Node::Blend { .. } => 2,
+            Node::Mix(_) | Node::Tint(_) | Node::Post(_) | Node::Output => 1,
+        }
+    }
+
+    fn outputs(&self) -> usize {
+        match self {
+            Node::Output => 0,
+            _ => 1,
+        }
+    }
+
+    /// Whether the output of this node is a mask rather than an image.
+    fn outputs_mask(&self) -> bool {
+        matches!(self, Node::Mask(_))
+    }
+
+    /// Whether the inputs of this node take a mask rather than an image.
+    fn takes_mask(&self) -> bool {
+        matches!(self, Node::Mix(_))
+    }
+}
+
+/// A graph as saved in a project: its nodes and the wires between them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub wires: Vec<Wire>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct GraphNode {
+    pub node: Node,
+    /// Position in the editor
+    pub pos: [f32; 2],
+}
+
+/// The output of node `from` connected to input `input` of node `to`,
+/// both indices into [`Graph::nodes`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Wire {
+    pub from: usize,
+    pub to: usize,
+    pub input: usize,
+}
+
+impl Graph {
+    /// Keys of the masks the graph reads.
+    pub fn masks(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().filter_map(|node| match &node.node {
+            Node::Mask(key) => Some(key.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Evaluate the graph up to its output node, e.g. on an export thread.
+    pub fn evaluate(&self, masks: &IndexMap<String, Mask>, registry: &Registry) -> anyhow::Result<GeneratedImage> {
+        NodeEditor::from_graph(self)?.evaluate(masks, registry)
+    }
+}
+
+/// The result of evaluating one node.
+enum Value<'a> {
+    Mask(&'a Mask),
+    Image(Rgba32FImage),
+}
+
+/// Evaluates a graph against the loaded masks and post-processing steps.
+struct Evaluator<'a> {
+    snarl: &'a Snarl<Node>,
+    masks: &'a IndexMap<String, Mask>,
+    registry: &'a Registry,
+}
+
+impl<'a> Evaluator<'a> {
+    fn input(&self, node: NodeId, input: usize, depth: usize) -> anyhow::Result<Value<'a>> {
+        let pin = self.snarl.in_pin(egui_snarl::InPinId { node, input });
+        let Some(remote) = pin.remotes.first() else {
+            anyhow::bail!("{} input {} is not connected", self.snarl[node].title(), input + 1);
+        };
+        self.eval(remote.node, depth + 1)
+    }
+
+    fn image(&self, node: NodeId, input: usize, depth: usize) -> anyhow::Result<Rgba32FImage> {
+        match self.input(node, input, depth)? {
+            Value::Image(img) => Ok(img),
+            Value::Mask(_) => anyhow::bail!("{} needs an image, not a mask", self.snarl[node].title()),
+        }
+    }
+
+    fn eval(&self, node: NodeId, depth: usize) -> anyhow::Result<Value<'a>> {
+        anyhow::ensure!(depth <= self.snarl.nodes().count(), "The graph contains a cycle");
+        Ok(match &self.snarl[node] {
+            Node::Mask(key) => Value::Mask(self.masks.get(key)
+                .ok_or_else(|| anyhow::anyhow!("Mask {key:?} is not loaded"))?),
+            Node::Mix(weight) => match self.input(node, 0, depth)? {
+                Value::Mask(mask) => Value::Image(mask.generate(weight).get_rgba32f().clone()),
+                Value::Image(_) => anyhow::bail!("Mix needs a mask, not an image"),
+            },
+            Node::Blend { mode, amount } => {
+                let mut a = self.image(node, 0, depth)?;
+                let mut b = self.image(node, 1, depth)?;
+                if b.dimensions() != a.dimensions() {
+                    b = imageops::resize(&b, a.width(), a.height(), imageops::FilterType::Triangle);
+                }
+                for (pa, pb) in a.pixels_mut().zip(b.pixels()) {
+                    for c in 0..3 {
+                        pa[c] = mode.apply(pa[c], pb[c], *amount).clamp(0.0, 1.0);
+                    }
+                }
+                Value::Image(a)
+            }
+            Node::Tint(color) => {
+                let mut img = self.image(node, 0, depth)?;
+                for pixel in img.pixels_mut() {
+                    for c in 0..3 {
+                        pixel[c] *= color[c];
+                    }
+                }
+                Value::Image(img)
+            }
+            Node::Post(name) => {
+                let step = self.registry.get(name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown post-processing step {name:?}"))?;
+                let mut img = GeneratedImage::from(self.image(node, 0, depth)?).get_rgba().clone();
+                step.step.apply(&mut img)?;
+                Value::Image(GeneratedImage::from(img).get_rgba32f().clone())
+            }
+            Node::Output => Value::Image(self.image(node, 0, depth)?),
+        })
+    }
+}
+
+/// Draws the nodes and records whether anything affecting the result changed.
+struct Viewer<'a> {
+    masks: &'a IndexMap<String, Mask>,
+    registry: &'a Registry,
+    changed: bool,
+}
+
+impl Viewer<'_> {
+    fn pin(mask: bool) -> PinInfo {
+        let color = if mask { MASK_COLOR } else { IMAGE_COLOR };
+        PinInfo::circle().with_fill(color).with_wire_color(color)
+    }
+
+    fn add_menu(&mut self, pos: egui::Pos2, ui: &mut egui::Ui, snarl: &mut Snarl<Node>) {
+        let mut picked = None;
+        ui.menu_button(tr("node-mask"), |ui| {
+            for key in self.masks.keys() {
+                if ui.button(key).clicked() {
+                    picked = Some(Node::Mask(key.clone()));
+                }
+            }
+        });
+        if ui.button(tr("node-mix")).clicked() {
+            picked = Some(Node::Mix([1.0, 1.0, 1.0]));
+        }
+        if ui.button(tr("node-blend")).clicked() {
+            picked = Some(Node::Blend { mode: BlendMode::Multiply, amount: 0.5 });
+        }
+        if ui.button(tr("node-tint")).clicked() {
+            picked = Some(Node::Tint([1.0, 1.0, 1.0]));
+        }
+        ui.menu_button(tr("node-post"), |ui| {
+            for name in self.registry.names() {
+                if ui.button(name).clicked() {
+                    picked = Some(Node::Post(name.to_string()));
+                }
+            }
+        });
+        if let Some(node) = picked {
+            snarl.insert_node(pos, node);
+            ui.close();
+        }
+    }
+}
+
+impl SnarlViewer<Node> for Viewer<'_> {
+    fn title(&mut self, node: &Node) -> String {
+        node.title()
+    }
+
+    fn inputs(&mut self, node: &Node) -> usize {
+        node.inputs()
+    }
+
+    fn outputs(&mut self, node: &Node) -> usize {
+        node.outputs()
+    }
+
+    fn show_input(&mut self, pin: &InPin, ui: &mut egui::Ui, snarl: &mut Snarl<Node>) -> impl SnarlPin + 'static {
+        let node = &snarl[pin.id.node];
+        match node {
+            Node::Blend { .. } => ui.label(["a", "b"][pin.id.input]),
+            Node::Mix(_) => ui.label("mask"),
+            _ => ui.label("image"),
+        };
+        Self::pin(node.takes_mask())
+    }
+
+    fn show_output(&mut self, pin: &OutPin, ui: &mut egui::Ui, snarl: &mut Snarl<Node>) -> impl SnarlPin + 'static {
+        let mask = snarl[pin.id.node].outputs_mask();
+        ui.label(if mask { "mask" } else { "image" });
+        Self::pin(mask)
+    }
+
+    fn has_body(&mut self, node: &Node) -> bool {
+        matches!(node, Node::Mix(_) | Node::Blend { .. } | Node::Tint(_))
+    }
+
+    fn show_body(&mut self, node: NodeId, _inputs: &[InPin], _outputs: &[OutPin], ui: &mut egui::Ui, snarl: &mut Snarl<Node>) {
+        ui.vertical(|ui| {
+            match &mut snarl[node] {
+                Node::Mix(weight) => for (value, label) in weight.iter_mut().zip(["R", "G", "B"]) {
+                    self.changed |= ui.add(Slider::new(value, 0.0..=1.0).text(label).step_by(0.01)).changed();
+                },
+                Node::Blend { mode, amount } => {
+                    egui::ComboBox::from_id_salt(node)
+                        .selected_text(format!("{mode:?}"))
+                        .show_ui(ui, |ui| {
+                            for option in BlendMode::ALL {
+                                self.changed |= ui.selectable_value(mode, option, format!("{option:?}")).changed();
+                            }
+                        });
+                    if *mode == BlendMode::Lerp {
+                        self.changed |= ui.add(Slider::new(amount, 0.0..=1.0).text(tr("amount"))).changed();
+                    }
+                }
+                Node::Tint(color) => ui.horizontal(|ui| {
+                    self.changed |= ui.color_edit_button_rgb(color).changed();
+                    for value in color.iter_mut() {
+                        self.changed |= ui.add(DragValue::new(value).range(0.0..=4.0).speed(0.01)).changed();
+                    }
+                }).inner,
+                _ => {}
+            }
+        });
+    }
+
+    fn connect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
+        if snarl[from.id.node].outputs_mask() != snarl[to.id.node].takes_mask() {
+            return;
+        }
+        snarl.drop_inputs(to.id);
+        snarl.connect(from.id, to.id);
+        self.changed = true;
+    }
+
+    fn disconnect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
+        snarl.disconnect(from.id, to.id);
+        self.changed = true;
+    }
+
+    fn drop_inputs(&mut self, pin: &InPin, snarl: &mut Snarl<Node>) {
+        snarl.drop_inputs(pin.id);
+        self.changed = true;
+    }
+
+    fn drop_outputs(&mut self, pin: &OutPin, snarl: &mut Snarl<Node>) {
+        snarl.drop_outputs(pin.id);
+        self.changed = true;
+    }
+
+    fn has_graph_menu(&mut self, _pos: egui::Pos2, _snarl: &mut Snarl<Node>) -> bool {
+        true
+    }
+
+    fn show_graph_menu(&mut self, pos: egui::Pos2, ui: &mut egui::Ui, snarl: &mut Snarl<Node>) {
+        ui.label(tr("add-node"));
+        self.add_menu(pos, ui, snarl);
+    }
+
+    fn has_node_menu(&mut self, node: &Node) -> bool {
+        *node != Node::Output
+    }
+
+    fn show_node_menu(&mut self, node: NodeId, _inputs: &[InPin], _outputs: &[OutPin], ui: &mut egui::Ui, snarl: &mut Snarl<Node>) {
+        if ui.button(tr("remove")).clicked() {
+            snarl.remove_node(node);
+            self.changed = true;
+            ui.close();
+        }
+    }
+}
+
+/// The node editor window: the graph and a preview of its output.
+pub struct NodeEditor {
+    snarl: Snarl<Node>,
+    output: NodeId,
+    tex: Option<egui::TextureHandle>,
+    error: Option<String>,
+    dirty: bool,
+}
+
+impl NodeEditor {
+    /// Start with `mask` mixed by `weight` into the output.
+    pub fn new(mask: &str, weight: [f32; 3]) -> Self {
+        let mut snarl = Snarl::new();
+        let source = snarl.insert_node(egui::pos2(0.0, 0.0), Node::Mask(mask.to_string()));
+        let mix = snarl.insert_node(egui::pos2(200.0, 0.0), Node::Mix(weight));
+        let output = snarl.insert_node(egui::pos2(450.0, 0.0), Node::Output);
+        snarl.connect(OutPinId { node: source, output: 0 }, egui_snarl::InPinId { node: mix, input: 0 });
+        snarl.connect(OutPinId { node: mix, output: 0 }, egui_snarl::InPinId { node: output, input: 0 });
+        Self { snarl, output, tex: None, error: None, dirty: true }
+    }
+
+    /// Rebuild the editor from a saved graph, which needs exactly one output node.
+    pub fn from_graph(graph: &Graph) -> anyhow::Result<Self> {
+        let mut snarl = Snarl::new();
+        let ids: Vec<_> = graph.nodes.iter()
+            .map(|node| snarl.insert_node(egui::pos2(node.pos[0], node.pos[1]), node.node.clone()))
+            .collect();
+        let mut outputs = ids.iter().copied().filter(|&id| snarl[id] == Node::Output);
+        let (Some(output), None) = (outputs.next(), outputs.next()) else {
+            anyhow::bail!("A node graph needs exactly one output node");
+        };
+        for wire in &graph.wires {
+            let (Some(&from), Some(&to)) = (ids.get(wire.from), ids.get(wire.to)) else {
+                anyhow::bail!("Wire {} -> {} connects a node that doesn't exist", wire.from, wire.to);
+            };
+            anyhow::ensure!(snarl[from].outputs() > 0 && wire.input < snarl[to].inputs(),
+                "Wire {} -> {} connects a pin that doesn't exist", wire.from, wire.to);
+            snarl.connect(OutPinId { node: from, output: 0 }, egui_snarl::InPinId { node: to, input: wire.input });
+        }
+        Ok(Self { snarl, output, tex: None, error: None, dirty: true })
+    }
+
+    /// The graph for saving in a project.
+    pub fn graph(&self) -> Graph {
+        let (ids, nodes): (Vec<_>, Vec<_>) = self.snarl.nodes_pos_ids()
+            .map(|(id, pos, node)| (id, GraphNode { node: node.clone(), pos: [pos.x, pos.y] }))
+            .unzip();
+        let index = |id: NodeId| ids.iter().position(|&other| other == id).expect("wires connect existing nodes");
+        let mut wires: Vec<_> = self.snarl.wires()
+            .map(|(from, to)| Wire { from: index(from.node), to: index(to.node), input: to.input })
+            .collect();
+        // Snarl keeps its wires unordered; sorting keeps saved projects stable
+        wires.sort_by_key(|wire| (wire.to, wire.input, wire.from));
+        Graph { nodes, wires }
+    }
+
+    /// Evaluate the graph up to the output node.
+    pub fn evaluate(&self, masks: &IndexMap<String, Mask>, registry: &Registry) -> anyhow::Result<GeneratedImage> {
+        let evaluator = Evaluator { snarl: &self.snarl, masks, registry };
+        match evaluator.eval(self.output, 0)? {
+            Value::Image(img) => Ok(img.into()),
+            Value::Mask(_) => unreachable!("the output node produces an image"),
+        }
+    }
+
+    /// Re-evaluate the graph on the next frame, e.g. after masks were reloaded.
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    fn update_preview(&mut self, ctx: &egui::Context, masks: &IndexMap<String, Mask>, registry: &Registry) {
+        self.dirty = false;
+        match self.evaluate(masks, registry) {
+            Ok(img) => {
+                let (width, height) = img.dimensions();
+                let fit = PREVIEW_SIZE as f32 / width.max(height) as f32;
+                let (width, height) = ((width as f32 * fit).round().max(1.0) as u32, (height as f32 * fit).round().max(1.0) as u32);
+                let preview = imageops::resize(img.get_rgba(), width, height, imageops::FilterType::Triangle);
+                let img = egui::ColorImage::from_rgba_unmultiplied([width as usize, height as usize], preview.as_raw());
+                match &mut self.tex {
+                    Some(handle) => handle.set(img, egui::TextureOptions::default()),
+                    None => self.tex = Some(ctx.load_texture("node-preview", img, Default::default())),
+                }
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    /// Draw the editor; returns whether the output should be exported.
+    pub fn ui(&mut self, ui: &mut egui::Ui, masks: &IndexMap<String, Mask>, registry: &Registry) -> bool {
+        let mut export = false;
+        if self.dirty {
+            self.update_preview(ui.ctx(), masks, registry);
+        }
+        egui::SidePanel::right("node-preview")
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                match (&self.error, &self.tex) {
+                    (Some(error), _) => { ui.colored_label(ui.visuals().error_fg_color, error); }
+                    (None, Some(tex)) => { ui.image((tex.id(), tex.size_vec2())); }
+                    (None, None) => { ui.label(tr("loading")); }
+                }
+                ui.label(tr("add-node-hint"));
+                export = ui.add_enabled(self.error.is_none(), egui::Button::new(tr("export-graph"))).clicked();
+            });
+        let mut viewer = Viewer { masks, registry, changed: false };
+        self.snarl.show(&mut viewer, &SnarlStyle::new(), "node-graph", ui);
+        if viewer.changed {
+            self.dirty = true;
+            ui.ctx().request_repaint();
+        }
+        export
+    }
+}
@@ -0,0 +1,60 @@
+//! Timings for the preview's performance overlay: how long the selected mask
+//! set took to load, how long each stage of the last preview update took,
+//! and the frame rate, to tell which one makes updates slow.
+
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use eframe::egui;
+
+/// Weight of the newest frame in the smoothed frame time.
+const SMOOTHING: f32 = 0.1;
+
+#[derive(Default)]
+pub struct Timings {
+    /// Loading of each mask set by key, as far as it was timed
+    pub decode: HashMap<String, Duration>,
+    /// Mixing the masks, including the color vision simulation
+    pub mix: Duration,
+    /// Resizing the mix to the export size and the preview
+    pub resize: Duration,
+    /// Copying the preview into the texture
+    pub upload: Duration,
+    last_frame: Option<Instant>,
+    /// Smoothed seconds between frames
+    frame_time: f32,
+}
+
+impl Timings {
+    /// Count a frame towards the frame rate; call once per update.
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame.replace(now) {
+            // Idle gaps between repaints would drag the average down for seconds
+            let elapsed = (now - last).as_secs_f32().min(1.0);
+            self.frame_time = if self.frame_time == 0.0 { elapsed } else { self.frame_time + (elapsed - self.frame_time) * SMOOTHING };
+        }
+    }
+
+    pub fn fps(&self) -> f32 {
+        if self.frame_time > 0.0 { 1.0 / self.frame_time } else { 0.0 }
+    }
+
+    /// The overlay text for the mask set `key`.
+    fn text(&self, key: &str) -> String {
+        let ms = |duration: Duration| format!("{:7.1} ms", duration.as_secs_f64() * 1000.0);
+        let decode = self.decode.get(key).map_or_else(|| format!("{:>10}", "-"), |&duration| ms(duration));
+        format!(
+            "decode {decode}\nmix    {}\nresize {}\nupload {}\nfps    {:7.1}",
+            ms(self.mix), ms(self.resize), ms(self.upload), self.fps(),
+        )
+    }
+
+    /// Paint the overlay for `key` into the top right corner of `rect`.
+    pub fn paint(&self, painter: &egui::Painter, rect: egui::Rect, key: &str) {
+        let galley = painter.layout_no_wrap(self.text(key), egui::FontId::monospace(12.0), egui::Color32::WHITE);
+        let size = galley.size() + egui::vec2(8.0, 4.0);
+        let overlay = egui::Rect::from_min_size(rect.right_top() + egui::vec2(-6.0 - size.x, 6.0), size);
+        painter.rect_filled(overlay, 3.0, egui::Color32::from_black_alpha(160));
+        painter.galley(overlay.min + egui::vec2(4.0, 2.0), galley, egui::Color32::WHITE);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use clap::Parser;
+use serde::Deserialize;
+
+use smix_runner::Runner;
+
+use crate::{config::Config, report, Cli, Filter, Format};
+
+/// Several generation jobs run by `smix run`, written in TOML. Relative paths
+/// are resolved against the pipeline file.
+///
+/// ```toml
+/// [presets]
+/// attack = [1.0, 0.15, 0.04]
+///
+/// [[job]]
+/// name = "attack backs"
+/// masks = ["masks/attack"]
+/// preset = "attack"
+/// scale = [2.0, 0.5]
+/// output = "build/attack"
+///
+/// [[job]]
+/// masks = ["masks/skill", "masks/power"]
+/// weight = [0.1, 0.8, 0.2]
+/// format = "webp"
+/// sharpen = 0.4
+/// post = ["grayscale"]
+/// name-template = "{mask}_skill_{width}"
+/// output = "build/skill"
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Pipeline {
+    /// Named weights for `preset = "..."`, added to those of smix.toml
+    #[serde(default)]
+    pub presets: BTreeMap<String, [f32; 3]>,
+    /// Plugin libraries loaded for every job
+    #[serde(default)]
+    pub plugins: Vec<PathBuf>,
+    #[serde(rename = "job")]
+    pub jobs: Vec<Job>,
+}
+
+/// One job; every key mirrors the command line flag of the same name.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Job {
+    /// Shown in the log only
+    pub name: Option<String>,
+    pub masks: Vec<PathBuf>,
+    /// Either `weight` or `preset` is required
+    pub weight: Option<[f32; 3]>,
+    pub allow_negative: bool,
+    pub preset: Option<String>,
+    pub output: Option<PathBuf>,
+    /// `auto` or a name, see `--run-id`
+    pub run_id: Option<String>,
+    pub filter: Option<Filter>,
+    pub format: Option<Format>,
+    pub name_template: Option<String>,
+    pub output_layout: Option<String>,
+    pub scale: Option<Vec<f32>>,
+    pub lods: Option<u32>,
+    pub sharpen: Option<f32>,
+    pub sharpen_radius: Option<f32>,
+    /// Post-processing steps by name
+    pub post: Vec<String>,
+    pub optimize: Option<u8>,
+    pub palette: Option<u16>,
+    pub dither: bool,
+    pub tileable: bool,
+    pub annotate: bool,
+    pub padding: Option<u32>,
+    /// Trim margin, see `--trim`
+    pub trim: Option<u32>,
+    /// Canvas size `WxH` or `pot`, see `--canvas`
+    pub canvas: Option<String>,
+    pub anchor: Option<String>,
+    pub deterministic: bool,
+    pub incremental: bool,
+    pub skip_invalid: bool,
+}
+
+impl Pipeline {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid pipeline {}: {e}", path.display()))
+    }
+}
+
+/// Run every job of the pipeline at `path`, in order.
+pub fn run(path: &Path) -> anyhow::Result<()> {
+    let pipeline = Pipeline::load(path)?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut presets = match Config::path() {
+        Some(config) => Config::load(&config)?.presets,
+        None => BTreeMap::new(),
+    };
+    presets.extend(pipeline.presets);
+
+    let count = pipeline.jobs.len();
+    for (i, job) in pipeline.jobs.into_iter().enumerate() {
+        let label = job.name.clone().unwrap_or_else(|| format!("#{}", i + 1));
+        status!("Job {label} ({}/{count})", i + 1);
+        let weight = match (&job.weight, &job.preset) {
+            (Some(weight), None) => *weight,
+            (None, Some(preset)) => *presets.get(preset)
+                .ok_or_else(|| anyhow::anyhow!("Job {label}: unknown preset {preset:?}"))?,
+            _ => anyhow::bail!("Job {label}: give exactly one of `weight` and `preset`"),
+        };
+        let mut args = job.into_args(weight, base)?;
+        args.plugins = pipeline.plugins.iter().map(|plugin| base.join(plugin)).collect();
+        let mut runner = Runner::new(args.settings(), presets.clone(), report::Terminal)
+            .with_cancel(crate::interrupt_token()?);
+        runner.prepare()?;
+        runner.load_plugins()?;
+        runner.load_masks()?;
+        runner.generate()?;
+    }
+    Ok(())
+}
+
+impl Job {
+    /// Command line arguments equivalent to this job.
+    fn into_args(self, [r, g, b]: [f32; 3], base: &Path) -> anyhow::Result<crate::Args> {
+        let cli = Cli::try_parse_from(["smix", &r.to_string(), &g.to_string(), &b.to_string(), "--preview", "false"])?;
+        let mut args = cli.args.expect("weights were given");
+        args.allow_negative = self.allow_negative;
+        args.mask_directories = self.masks.iter().map(|mask| base.join(mask)).collect();
+        if let Some(output) = self.output {
+            args.output = base.join(output);
+        }
+        args.run_id = self.run_id;
+        if let Some(filter) = self.filter {
+            args.filter = filter;
+        }
+        if let Some(format) = self.format {
+            args.format = vec![format];
+        }
+        if let Some(template) = self.name_template {
+            args.name_template = template;
+        }
+        args.output_layout = self.output_layout;
+        if let Some(scale) = self.scale {
+            args.scale = scale;
+        }
+        args.lods = self.lods;
+        args.sharpen = self.sharpen;
+        if let Some(radius) = self.sharpen_radius {
+            args.sharpen_radius = radius;
+        }
+        args.post = self.post;
+        args.optimize = self.optimize;
+        args.palette = self.palette;
+        args.dither = self.dither;
+        args.tileable = self.tileable;
+        args.annotate = self.annotate;
+        args.padding = self.padding;
+        args.trim = self.trim;
+        args.canvas = self.canvas.map(|canvas| canvas.parse()).transpose()?;
+        if let Some(anchor) = self.anchor {
+            args.anchor = anchor.parse()?;
+        }
+        args.deterministic = self.deterministic;
+        args.incremental = self.incremental;
+        args.skip_invalid = self.skip_invalid;
+        Ok(args)
+    }
+}
@@ -0,0 +1,109 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Filter, Mix};
+
+/// File extension of GUI session files.
+pub const EXTENSION: &str = "smix";
+
+/// A GUI review session, saved as TOML in a `.smix` file.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Project {
+    /// Key of the mask shown when the project is opened
+    pub selected: Option<String>,
+    pub masks: Vec<ProjectMask>,
+    pub presets: BTreeMap<String, [f32; 3]>,
+    pub export: ExportSettings,
+    /// How the mask sets are loaded; projects without it keep the session's
+    pub load: Option<LoadSettings>,
+    /// The node editor's graph, if it was opened
+    #[cfg(feature = "node-editor")]
+    pub nodes: Option<crate::nodes::Graph>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProjectMask {
+    pub name: String,
+    /// Mask directory, relative to the project file when it lives below it
+    pub path: PathBuf,
+    pub weight: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ExportSettings {
+    pub scale: f32,
+    pub filter: Filter,
+    pub mix: Mix,
+    /// Levels of the channel masks
+    pub gain: [f32; 3],
+    pub bias: [f32; 3],
+    pub sharpen: Option<[f32; 2]>,
+    /// Temperature in Kelvin and tint
+    pub white_balance: [f32; 2],
+    /// Post-processing steps by name
+    pub post: Vec<String>,
+    /// File name template, see [`smix::naming`]
+    pub name_template: String,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        let levels = smix::Levels::default();
+        Self {
+            scale: 1.0,
+            filter: Filter::Lanczos3,
+            mix: Mix::Sum,
+            gain: levels.gain,
+            bias: levels.bias,
+            sharpen: None,
+            white_balance: [smix::adjust::NEUTRAL_TEMPERATURE, 0.0],
+            post: Vec::new(),
+            name_template: smix::naming::DEFAULT_TEMPLATE.into(),
+        }
+    }
+}
+
+/// The command line's `--map`, `--missing-channel` and `--psd-layers`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct LoadSettings {
+    pub map: String,
+    pub missing_channel: Option<String>,
+    pub psd_layers: Vec<String>,
+}
+
+impl Project {
+    /// Read a project, resolving mask paths against the project's directory.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut project: Self = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid project {}: {e}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for mask in &mut project.masks {
+            mask.path = base.join(&mask.path);
+        }
+        Ok(project)
+    }
+
+    /// Write the project, storing mask paths relative to it where possible so
+    /// the project and its masks can be handed over together.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let base = path.parent()
+            .and_then(|dir| std::path::absolute(dir).ok())
+            .unwrap_or_default();
+        let mut project = self.clone();
+        for mask in &mut project.masks {
+            if let Ok(absolute) = std::path::absolute(&mask.path)
+                && let Ok(relative) = absolute.strip_prefix(&base)
+            {
+                mask.path = relative.to_path_buf();
+            }
+        }
+        std::fs::write(path, toml::to_string_pretty(&project)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+//! Terminal output of [`smix_runner`] events.
+
+use smix_runner::{Event, Reporter};
+
+/// Prints events as status lines, warnings to stderr.
+pub struct Terminal;
+
+impl Reporter for Terminal {
+    fn report(&self, event: Event<'_>) {
+        match event {
+            Event::Weights([r, g, b]) => status!("RGB weights: ({r}, {g}, {b})"),
+            Event::Expression(expr) => status!("Mix expression: {expr}"),
+            Event::RunId(run_id) => status!("Run ID: {run_id}"),
+            Event::OutputDirectory { path, created: true } => {
+                status!("Output directory does not exists");
+                status!("Create directory: {}", path.display());
+            }
+            Event::OutputDirectory { path, created: false } => status!("Output directory: {}", path.display()),
+            Event::Stdout => status!("Output: stdout"),
+            Event::AnimatedMask { name, frames } => status!("Animated mask {name}: {frames} frames"),
+            Event::UvIslands { name, islands } => status!("UV islands {name}: {islands}"),
+            Event::NegativeScale { index, scale } => {
+                status!("Scale factor should be positive, but {scale} at {index} is negative");
+            }
+            Event::UpToDate { file } => status!("Up to date {file}"),
+            Event::Generated { file } => status!("Generated {file}"),
+            Event::Warning(warning) => eprintln!("Warning: {warning}"),
+            Event::Skipped(failures) => {
+                eprintln!("Skipped {} invalid mask set(s):", failures.len());
+                for failure in failures {
+                    eprintln!("  {}: {}", failure.source.display(), failure.error);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::{self, tr, Language};
+
+/// Preview window preferences, persisted in `<config dir>/smix/gui.toml`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GuiSettings {
+    pub theme: Theme,
+    /// egui zoom factor; raise it on high-DPI laptops where sliders get tiny
+    pub ui_scale: f32,
+    pub language: Language,
+    /// Weight sliders move along a gamma curve, giving low weights more travel
+    pub perceptual_sliders: bool,
+    /// Decode, mix, resize and upload times and the frame rate over the preview
+    pub performance_overlay: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl Default for GuiSettings {
+    fn default() -> Self {
+        Self { theme: Theme::System, ui_scale: 1.0, language: Language::English, perceptual_sliders: false, performance_overlay: false }
+    }
+}
+
+impl From<Theme> for egui::ThemePreference {
+    fn from(theme: Theme) -> Self {
+        match theme {
+            Theme::System => egui::ThemePreference::System,
+            Theme::Dark => egui::ThemePreference::Dark,
+            Theme::Light => egui::ThemePreference::Light,
+        }
+    }
+}
+
+impl GuiSettings {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("smix").join("gui.toml"))
+    }
+
+    /// Missing or broken settings fall back to the defaults.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow::anyhow!("No config directory"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn apply(&self, ctx: &egui::Context) {
+        ctx.set_theme(self.theme);
+        ctx.set_zoom_factor(self.ui_scale);
+        i18n::set_language(self.language);
+        if self.language == Language::Chinese {
+            i18n::install_cjk_font(ctx);
+        }
+    }
+
+    /// Settings window contents; returns whether anything changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = self.clone();
+        ui.horizontal(|ui| {
+            ui.label(tr("theme"));
+            ui.selectable_value(&mut self.theme, Theme::System, tr("theme-system"));
+            ui.selectable_value(&mut self.theme, Theme::Dark, tr("theme-dark"));
+            ui.selectable_value(&mut self.theme, Theme::Light, tr("theme-light"));
+        });
+        ui.add(egui::Slider::new(&mut self.ui_scale, 0.5..=3.0).text(tr("ui-scale")).step_by(0.05));
+        ui.horizontal(|ui| {
+            ui.label(tr("language"));
+            for language in Language::ALL {
+                ui.selectable_value(&mut self.language, language, language.native_name());
+            }
+        });
+        ui.checkbox(&mut self.perceptual_sliders, tr("perceptual-sliders"));
+        ui.checkbox(&mut self.performance_overlay, tr("performance-overlay"));
+        *self != before
+    }
+}
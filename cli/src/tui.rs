@@ -0,0 +1,255 @@
+//! Terminal frontend for hosts the preview window can't open on, e.g. over
+//! SSH: pick a mask set, adjust the weights against a coarse truecolor
+//! preview and generate outputs with the usual settings, with progress.
+
+use std::{collections::{BTreeMap, HashMap}, path::PathBuf, sync::mpsc, thread::JoinHandle, time::Duration};
+
+use indexmap::IndexMap;
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{self, Event as Input, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Gauge, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+use smix::{cancel::CancelToken, Mask};
+use smix_runner::{Event, Loaded, Runner, Settings};
+
+/// Longest side of the masks the preview mixes; terminals show far fewer cells.
+const PREVIEW_SIZE: u32 = 256;
+
+/// How long to wait for input before checking on a running generation.
+const POLL: Duration = Duration::from_millis(100);
+
+const CHANNELS: [&str; 3] = ["R", "G", "B"];
+
+/// A generation running on a worker thread, reporting the files it writes.
+struct Job {
+    handle: JoinHandle<anyhow::Result<()>>,
+    events: mpsc::Receiver<String>,
+    cancel: CancelToken,
+    /// Files written so far
+    written: usize,
+    /// Files the job should write, as far as it can be told up front
+    expected: usize,
+}
+
+struct App {
+    settings: Settings,
+    presets: BTreeMap<String, [f32; 3]>,
+    masks: IndexMap<String, Mask>,
+    paths: IndexMap<String, PathBuf>,
+    /// Downscaled masks for the preview, made when first shown
+    proxies: HashMap<String, Mask>,
+    selected: ListState,
+    weight: [f32; 3],
+    /// Weight the arrow keys change
+    channel: usize,
+    job: Option<Job>,
+    /// Generated files and warnings, newest last
+    log: Vec<String>,
+}
+
+/// Run the terminal frontend on the masks loaded by `runner`.
+pub fn run(runner: Runner) -> anyhow::Result<()> {
+    let Loaded { settings, masks, paths, presets, failures, .. } = runner.into_loaded();
+    if masks.is_empty() {
+        let errors: Vec<_> = failures.iter().map(|failure| failure.error.as_str()).collect();
+        anyhow::bail!("No mask set could be loaded:\n{}", errors.join("\n"));
+    }
+    let mut app = App {
+        weight: settings.weight,
+        settings,
+        presets,
+        masks,
+        paths,
+        proxies: HashMap::new(),
+        selected: ListState::default().with_selected(Some(0)),
+        channel: 0,
+        job: None,
+        log: failures.into_iter().map(|failure| format!("skipped {}: {}", failure.source.display(), failure.error)).collect(),
+    };
+    ratatui::run(|terminal| app.run(terminal))
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            self.poll_job();
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(POLL)? {
+                continue;
+            }
+            let Input::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let step = if key.modifiers.contains(KeyModifiers::SHIFT) { 0.1 } else { 0.01 };
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => match &self.job {
+                    Some(job) => job.cancel.cancel(),
+                    None => return Ok(()),
+                },
+                KeyCode::Up | KeyCode::Char('k') => self.selected.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.selected.select_next(),
+                KeyCode::Tab => self.channel = (self.channel + 1) % 3,
+                KeyCode::BackTab => self.channel = (self.channel + 2) % 3,
+                KeyCode::Left | KeyCode::Char('h') => self.nudge(-step),
+                KeyCode::Right | KeyCode::Char('l') => self.nudge(step),
+                KeyCode::Char('0') => self.weight = self.settings.weight,
+                KeyCode::Char(digit @ '1'..='9') => self.apply_preset(digit as usize - '1' as usize),
+                KeyCode::Enter if self.job.is_none() => self.generate(false),
+                KeyCode::Char('a') if self.job.is_none() => self.generate(true),
+                _ => {}
+            }
+        }
+    }
+
+    fn key(&self) -> &str {
+        let index = self.selected.selected().unwrap_or(0).min(self.masks.len() - 1);
+        self.masks.get_index(index).expect("masks is not empty").0
+    }
+
+    fn nudge(&mut self, step: f32) {
+        let min = if self.settings.allow_negative { -1.0 } else { 0.0 };
+        let weight = &mut self.weight[self.channel];
+        *weight = ((*weight + step) * 100.0).round().clamp(min * 100.0, 100.0) / 100.0;
+    }
+
+    fn apply_preset(&mut self, index: usize) {
+        if let Some(weight) = self.presets.values().nth(index) {
+            self.weight = *weight;
+        }
+    }
+
+    /// Export the selected mask set, or all of them, with the current weights
+    /// and every other setting from the command line.
+    fn generate(&mut self, all: bool) {
+        let mask_directories: Vec<_> = if all {
+            self.paths.values().cloned().collect()
+        } else {
+            vec![self.paths[self.key()].clone()]
+        };
+        let expected = mask_directories.len() * self.settings.scale.len().max(1) * (1 + self.settings.extra_formats.len());
+        let settings = Settings { weight: self.weight, mask_directories, ..self.settings.clone() };
+        let presets = self.presets.clone();
+        let (sender, events) = mpsc::channel();
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        let handle = std::thread::spawn(move || {
+            let reporter = move |event: Event<'_>| {
+                let line = match event {
+                    Event::Generated { file } => file.to_string(),
+                    Event::UpToDate { file } => format!("{file} is up to date"),
+                    Event::Warning(warning) => format!("warning: {warning}"),
+                    _ => return,
+                };
+                let _ = sender.send(line);
+            };
+            let mut runner = Runner::new(settings, presets, reporter).with_cancel(token);
+            runner.prepare()?;
+            runner.load_plugins()?;
+            runner.load_masks()?;
+            runner.generate()
+        });
+        self.job = Some(Job { handle, events, cancel, written: 0, expected });
+    }
+
+    /// Log what the running job reported and collect it once it finished.
+    fn poll_job(&mut self) {
+        let Some(job) = &mut self.job else { return };
+        let finished = job.handle.is_finished();
+        for line in job.events.try_iter() {
+            if !line.starts_with("warning: ") {
+                job.written += 1;
+            }
+            self.log.push(line);
+        }
+        if !finished {
+            return;
+        }
+        let job = self.job.take().expect("checked above");
+        match job.handle.join().expect("generation thread panicked") {
+            Ok(()) => self.log.push(format!("done, {} files", job.written)),
+            Err(e) => self.log.push(format!("failed: {e:#}")),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [body, progress, help] = Layout::vertical([Constraint::Min(0), Constraint::Length(3), Constraint::Length(1)]).areas(frame.area());
+        let [list, preview, side] = Layout::horizontal([Constraint::Length(24), Constraint::Min(0), Constraint::Length(32)]).areas(body);
+
+        let items: Vec<_> = self.masks.keys().map(|key| ListItem::new(key.as_str())).collect();
+        let masks = List::new(items)
+            .block(Block::bordered().title(" Masks "))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(masks, list, &mut self.selected);
+
+        let block = Block::bordered().title(format!(" {} ", self.key()));
+        let inner = block.inner(preview);
+        frame.render_widget(block, preview);
+        self.draw_preview(inner, frame.buffer_mut());
+
+        let [weights, log] = Layout::vertical([Constraint::Length(9), Constraint::Min(0)]).areas(side);
+        let rows = Layout::vertical([Constraint::Length(3); 3]).split(weights);
+        let min = if self.settings.allow_negative { -1.0 } else { 0.0 };
+        for (i, (&weight, label)) in self.weight.iter().zip(CHANNELS).enumerate() {
+            let style = if i == self.channel { Style::new().add_modifier(Modifier::BOLD) } else { Style::new() };
+            let gauge = Gauge::default()
+                .block(Block::bordered().title(format!(" {label} ")).border_style(style))
+                .ratio(((weight - min) / (1.0 - min)) as f64)
+                .label(format!("{weight:.2}"));
+            frame.render_widget(gauge, rows[i]);
+        }
+        let lines: Vec<_> = self.log.iter().rev().take(log.height.saturating_sub(2) as usize).rev().map(|line| Line::from(line.as_str())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Log ")), log);
+
+        let (ratio, label) = match &self.job {
+            Some(job) => ((job.written as f64 / job.expected.max(1) as f64).min(1.0), format!("{} written", job.written)),
+            None => (0.0, "idle".to_string()),
+        };
+        let output = format!(" Output {} ", self.settings.output.display());
+        frame.render_widget(Gauge::default().block(Block::bordered().title(output)).ratio(ratio).label(label), progress);
+
+        frame.render_widget(
+            Line::from(" ↑↓ mask  Tab channel  ←→ weight (Shift ×10)  1-9 preset  0 reset  Enter generate  a all  q cancel/quit").style(Style::new().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+
+    /// The current mix of the selected mask, two pixels per cell with upper
+    /// half blocks, fit into `area` keeping the aspect ratio.
+    fn draw_preview(&mut self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() {
+            return;
+        }
+        let key = self.key().to_string();
+        let proxy = self.proxies.entry(key.clone()).or_insert_with(|| {
+            let mut mask = self.masks[&key].downscaled(PREVIEW_SIZE).into_owned();
+            mask.apply_levels(&self.settings.levels);
+            mask
+        });
+        let mix = proxy.generate(&self.settings.mix.effective_weight(self.weight));
+        let (width, height) = mix.dimensions();
+        let (columns, rows) = (area.width as u32, area.height as u32 * 2);
+        let fit = (columns as f32 / width as f32).min(rows as f32 / height as f32);
+        let (columns, rows) = (((width as f32 * fit) as u32).max(1), ((height as f32 * fit) as u32).max(1));
+        let image = image::imageops::resize(mix.get_rgba(), columns, rows, image::imageops::FilterType::Triangle);
+        let color = |x: u32, y: u32| {
+            let p = image.get_pixel(x, y).0;
+            // Terminals have no alpha; composite over black
+            let c = |i: usize| (p[i] as u16 * p[3] as u16 / 255) as u8;
+            Color::Rgb(c(0), c(1), c(2))
+        };
+        for row in 0..rows.div_ceil(2) {
+            for x in 0..columns {
+                let Some(cell) = buf.cell_mut((area.x + x as u16, area.y + row as u16)) else { continue };
+                let top = color(x, row * 2);
+                let bottom = if row * 2 + 1 < rows { color(x, row * 2 + 1) } else { Color::Reset };
+                cell.set_char('▀').set_fg(top).set_bg(bottom);
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::{mpsc, Arc}};
+
+use eframe::egui;
+use notify::{EventKind, RecursiveMode, Watcher};
+use smix::{animation::AnimatedMask, uv, ChannelMap};
+
+/// Watches mask sets so the preview can reload masks edited externally.
+pub struct MaskWatcher {
+    _watcher: notify::RecommendedWatcher,
+    rx: mpsc::Receiver<String>,
+}
+
+impl MaskWatcher {
+    /// Watch every `(key, path)` mask set, the files `map` names in a
+    /// directory or a packed image itself; `ctx` is woken up when one changes.
+    pub fn new<'a>(masks: impl IntoIterator<Item = (&'a String, &'a PathBuf)>, map: &ChannelMap, ctx: egui::Context) -> notify::Result<Self> {
+        let mut keys = HashMap::new();
+        let mut dirs = HashSet::new();
+        for (key, path) in masks {
+            let path = std::path::absolute(path)?;
+            for file in watched_files(&path, map) {
+                keys.insert(file, key.clone());
+            }
+            // Editors often save by renaming over the file, so watch the directory
+            dirs.insert(if path.is_dir() { path } else { path.parent().map_or_else(PathBuf::new, Path::to_path_buf) });
+        }
+        let keys = Arc::new(keys);
+
+        let (tx, rx) = mpsc::channel();
+        let watched = Arc::clone(&keys);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else { return };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+            for key in event.paths.iter().filter_map(|path| watched.get(path)) {
+                let _ = tx.send(key.clone());
+                ctx.request_repaint();
+            }
+        })?;
+        for dir in dirs {
+            watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        }
+        Ok(Self { _watcher: watcher, rx })
+    }
+
+    /// Keys of the masks whose files changed since the last call.
+    pub fn changed(&self) -> HashSet<String> {
+        self.rx.try_iter().collect()
+    }
+}
+
+/// The files the mask set at `path` is loaded from: the channel files of
+/// `map`, under either extension they may have, and the UV islands; every
+/// frame of an animated set; or the packed image itself.
+fn watched_files(path: &Path, map: &ChannelMap) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    if AnimatedMask::detect(path).is_some() {
+        let frames = std::fs::read_dir(path).into_iter().flatten().flatten().map(|entry| entry.path());
+        return frames.filter(|file| file.is_file()).collect();
+    }
+    let mut files = vec![path.join(uv::FILE)];
+    for (file, constant) in map.paths(path).into_iter().zip(map.constants) {
+        if constant.is_some() {
+            continue;
+        }
+        let alternates = ChannelMap::ALTERNATE_EXTENSIONS.iter().map(|ext| file.with_extension(ext));
+        files.extend(alternates.chain(std::iter::once(file.with_extension("png"))));
+        files.push(file);
+    }
+    files
+}
@@ -0,0 +1,307 @@
+//! `--weights` expressions over presets and scalars, e.g. `lerp(summer, winter, 0.3)`.
+//!
+//! Values are either RGB weights or scalars; arithmetic (`+ - * /`) works
+//! component-wise and broadcasts scalars; dividing by zero is an error.
+//! Weights are written as a preset name, `[r, g, b]` or `rgb(r, g, b)`.
+//! Functions:
+//!
+//! - `lerp(a, b, t)`: `a + (b - a) * t`
+//! - `min(a, b)`, `max(a, b)`
+//! - `clamp(a)`: each component into `[0, 1]`
+//! - `normalize(a)`: scale so the components sum to 1
+
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug)]
+enum Value {
+    Scalar(f32),
+    Weight([f32; 3]),
+}
+
+impl Value {
+    fn rgb(self) -> [f32; 3] {
+        match self {
+            Value::Scalar(v) => [v; 3],
+            Value::Weight(w) => w,
+        }
+    }
+
+    fn zip(self, other: Value, f: impl Fn(f32, f32) -> f32) -> Value {
+        match (self, other) {
+            (Value::Scalar(a), Value::Scalar(b)) => Value::Scalar(f(a, b)),
+            _ => {
+                let (a, b) = (self.rgb(), other.rgb());
+                Value::Weight([f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2])])
+            }
+        }
+    }
+
+    fn map(self, f: impl Fn(f32) -> f32) -> Value {
+        match self {
+            Value::Scalar(v) => Value::Scalar(f(v)),
+            Value::Weight(w) => Value::Weight(w.map(f)),
+        }
+    }
+}
+
+/// Evaluate `expr` to RGB weights, looking names up in `presets`.
+pub fn eval(expr: &str, presets: &BTreeMap<String, [f32; 3]>) -> anyhow::Result<[f32; 3]> {
+    let mut parser = Parser { src: expr, pos: 0, presets };
+    let value = parser.expr()
+        .and_then(|value| match parser.peek() {
+            None => Ok(value),
+            Some(c) => Err(parser.error(&format!("unexpected {c:?}"))),
+        })
+        .map_err(|e| anyhow::anyhow!("Invalid --weights {expr:?}: {e}"))?;
+    match value {
+        Value::Weight(weight) => Ok(weight),
+        Value::Scalar(_) => anyhow::bail!("--weights {expr:?} gives a single number, not R,G,B weights"),
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    presets: &'a BTreeMap<String, [f32; 3]>,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow::anyhow!("{message} at column {}", self.pos + 1)
+    }
+
+    /// Next non-space character, without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.src[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += c.len_utf8();
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> anyhow::Result<()> {
+        if self.eat(c) { Ok(()) } else { Err(self.error(&format!("expected {c:?}"))) }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        let len = self.src[start..].find(|c| !f(c)).unwrap_or(self.src.len() - start);
+        self.pos += len;
+        &self.src[start..start + len]
+    }
+
+    fn expr(&mut self) -> anyhow::Result<Value> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value = value.zip(self.term()?, |a, b| a + b);
+            } else if self.eat('-') {
+                value = value.zip(self.term()?, |a, b| a - b);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> anyhow::Result<Value> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value = value.zip(self.unary()?, |a, b| a * b);
+            } else if self.eat('/') {
+                let start = self.pos;
+                let divisor = self.unary()?;
+                if divisor.rgb().contains(&0.0) {
+                    self.pos = start;
+                    return Err(self.error("division by zero"));
+                }
+                value = value.zip(divisor, |a, b| a / b);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Value> {
+        if self.eat('-') {
+            return Ok(self.unary()?.map(|v| -v));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> anyhow::Result<Value> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let value = self.expr()?;
+                self.expect(')')?;
+                Ok(value)
+            }
+            Some('[') => {
+                self.pos += 1;
+                let weight = self.components()?;
+                self.expect(']')?;
+                Ok(Value::Weight(weight))
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.').to_string();
+                number.parse().map(Value::Scalar).map_err(|_| self.error(&format!("bad number {number:?}")))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.pos;
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_').to_string();
+                if self.eat('(') {
+                    return self.call(&name, start);
+                }
+                self.presets.get(&name).copied().map(Value::Weight).ok_or_else(|| {
+                    self.pos = start;
+                    let known: Vec<_> = self.presets.keys().map(String::as_str).collect();
+                    self.error(&format!("unknown preset {name:?} (presets: {})", known.join(", ")))
+                })
+            }
+            Some(c) => Err(self.error(&format!("unexpected {c:?}"))),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn components(&mut self) -> anyhow::Result<[f32; 3]> {
+        let mut weight = [0.0; 3];
+        for (i, component) in weight.iter_mut().enumerate() {
+            if i > 0 {
+                self.expect(',')?;
+            }
+            *component = match self.expr()? {
+                Value::Scalar(v) => v,
+                Value::Weight(_) => return Err(self.error("expected a number")),
+            };
+        }
+        Ok(weight)
+    }
+
+    fn call(&mut self, name: &str, start: usize) -> anyhow::Result<Value> {
+        if name == "rgb" {
+            let weight = self.components()?;
+            self.expect(')')?;
+            return Ok(Value::Weight(weight));
+        }
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(')') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        let value = match (name, &args[..]) {
+            ("lerp", &[a, b, t]) => a.zip(b.zip(a, |b, a| b - a).zip(t, |d, t| d * t), |a, d| a + d),
+            ("min", &[a, b]) => a.zip(b, f32::min),
+            ("max", &[a, b]) => a.zip(b, f32::max),
+            ("clamp", &[a]) => a.map(|v| v.clamp(0.0, 1.0)),
+            ("normalize", &[a]) => {
+                let w = a.rgb();
+                let sum: f32 = w.iter().sum();
+                if sum == 0.0 {
+                    return Err(self.error("normalize of zero weights"));
+                }
+                Value::Weight(w.map(|v| v / sum))
+            }
+            ("lerp" | "min" | "max" | "clamp" | "normalize", _) => {
+                self.pos = start;
+                return Err(self.error(&format!("wrong number of arguments to {name}()")));
+            }
+            _ => {
+                self.pos = start;
+                return Err(self.error(&format!("unknown function {name}()")));
+            }
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presets() -> BTreeMap<String, [f32; 3]> {
+        BTreeMap::from([("summer".into(), [1.0, 0.5, 0.0]), ("winter".into(), [0.0, 0.5, 1.0])])
+    }
+
+    fn weights(expr: &str) -> [f32; 3] {
+        eval(expr, &presets()).unwrap()
+    }
+
+    fn error(expr: &str) -> String {
+        eval(expr, &presets()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(weights("[1, 2, 3] + [1, 1, 1] * 2"), [3.0, 4.0, 5.0]);
+        assert_eq!(weights("([1, 2, 3] + [1, 1, 1]) * 2"), [4.0, 6.0, 8.0]);
+        assert_eq!(weights("[4, 4, 4] - [1, 1, 1] - [1, 2, 3]"), [2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn unary_minus_negates() {
+        assert_eq!(weights("-summer"), [-1.0, -0.5, -0.0]);
+        assert_eq!(weights("winter - -summer"), [1.0, 1.0, 1.0]);
+        assert_eq!(weights("rgb(-0.5, 1, 0)"), [-0.5, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn scalars_broadcast() {
+        assert_eq!(weights("summer * 0.5"), [0.5, 0.25, 0.0]);
+        assert_eq!(weights("1 - winter"), [1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn lerp_interpolates() {
+        assert_eq!(weights("lerp(summer, winter, 0)"), [1.0, 0.5, 0.0]);
+        assert_eq!(weights("lerp(summer, winter, 1)"), [0.0, 0.5, 1.0]);
+        assert_eq!(weights("lerp(summer, winter, 0.25)"), [0.75, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn min_max_and_clamp() {
+        assert_eq!(weights("min(summer, winter)"), [0.0, 0.5, 0.0]);
+        assert_eq!(weights("max(summer, winter)"), [1.0, 0.5, 1.0]);
+        assert_eq!(weights("clamp([-1, 0.5, 2])"), [0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn normalize_sums_to_one() {
+        assert_eq!(weights("normalize([2, 1, 1])"), [0.5, 0.25, 0.25]);
+        assert_eq!(error("normalize([1, -1, 0])"), "Invalid --weights \"normalize([1, -1, 0])\": normalize of zero weights at column 22");
+    }
+
+    #[test]
+    fn division_by_zero_fails() {
+        assert_eq!(weights("summer / 2"), [0.5, 0.25, 0.0]);
+        assert_eq!(error("summer / 0"), "Invalid --weights \"summer / 0\": division by zero at column 9");
+        assert!(error("summer / [1, 0, 1]").contains("division by zero"));
+    }
+
+    #[test]
+    fn parse_errors_point_at_column() {
+        assert_eq!(error("summer +"), "Invalid --weights \"summer +\": unexpected end at column 9");
+        assert_eq!(error("summer )"), "Invalid --weights \"summer )\": unexpected ')' at column 8");
+        assert_eq!(error("[1, 2"), "Invalid --weights \"[1, 2\": expected ',' at column 6");
+        assert_eq!(error("autumn"), "Invalid --weights \"autumn\": unknown preset \"autumn\" (presets: summer, winter) at column 1");
+        assert_eq!(error("mix(summer)"), "Invalid --weights \"mix(summer)\": unknown function mix() at column 1");
+        assert_eq!(error("lerp(summer, winter)"), "Invalid --weights \"lerp(summer, winter)\": wrong number of arguments to lerp() at column 1");
+        assert_eq!(error("1.2.3 * summer"), "Invalid --weights \"1.2.3 * summer\": bad number \"1.2.3\" at column 6");
+    }
+
+    #[test]
+    fn single_number_is_not_weights() {
+        assert_eq!(error("0.5"), "--weights \"0.5\" gives a single number, not R,G,B weights");
+    }
+}
@@ -0,0 +1,93 @@
+//! The mixing kernel of smix: per-pixel weighting and sRGB conversions,
+//! without `image` or the filesystem, so it builds for embedded and WASM
+//! targets. Disable the default `std` feature for `no_std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod math;
+
+/// RGBA color stored as `[R, G, B, A]` in **0.0~1.0**
+pub type Color = [f32; 4];
+
+/// [`math::dot`] of three weights and values.
+pub fn apply_weight(weight: &[f32; 3], value: &[f32; 3]) -> f32 {
+    math::dot(weight, value)
+}
+
+/// Mix a single RGBA pixel by 3-channel weight and 3 mask pixels.
+/// 
+/// Alpha channel is **preserved**; only RGB components are modified.
+/// Each of `pixel`'s RGB components becomes the weighted sum of that
+/// component of the 3 masks, see [`math::weighted_sum_n`].
+/// 
+/// # Arguments
+/// * `pixel` - In-out RGBA pixel (alpha untouched)
+/// * `weight` - Per-channel weights `[Rw, Gw, Bw]` (sum != 0)
+/// * `mask` - Exactly 3 RGBA samples (alpha ignored) corresponding to R, G, B masks
+/// 
+/// # Exmaples
+/// ```
+/// use smix_core::mix_pixel;
+/// 
+/// let mut px = [0.0, 0.0, 0.0, 1.0];
+/// let w = [0.8, 0.15, 0.05];
+/// let m = [
+///     [1.0, 0.0, 0.0, 1.0], // red
+///     [0.0, 1.0, 0.0, 1.0], // red
+///     [0.0, 0.0, 1.0, 1.0], // red
+/// ];
+/// mix_pixel(&mut px, &w, &m);
+/// assert_eq!(px, [0.8, 0.15, 0.05, 1.0]);
+/// ```
+pub fn mix_pixel(pixel: &mut Color, weight: &[f32; 3], mask: &[Color; 3]) {
+    let mixed = math::weighted_sum_n(weight, mask);
+    pixel[..3].copy_from_slice(&mixed[..3]);
+}
+
+/// How [`mix_pixel`]'s weights combine the masks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MixSemantics {
+    /// Weighted sum; weights adding up to more than 1 can overexpose
+    #[default]
+    Sum,
+    /// Weighted average: the weights are scaled so their magnitudes add up to 1
+    Average,
+}
+
+impl MixSemantics {
+    /// The weights to mix by as a plain weighted sum. All-zero weights are
+    /// left as they are.
+    pub fn effective_weight(self, weight: [f32; 3]) -> [f32; 3] {
+        let sum: f32 = weight.iter().map(|w| w.abs()).sum();
+        match self {
+            Self::Average if sum != 0.0 => weight.map(|w| w / sum),
+            _ => weight,
+        }
+    }
+}
+
+/// Decode an sRGB component to linear light, clamped to 0~1 first.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.04045 { v / 12.92 } else { powf((v + 0.055) / 1.055, 2.4) }
+}
+
+/// Encode a linear light component as sRGB.
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 { v * 12.92 } else { 1.055 * powf(v, 1.0 / 2.4) - 0.055 }
+}
+
+/// A 0~1 component as 8 bits, rounded; out of range values are clamped.
+pub fn quantize(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+#[cfg(feature = "std")]
+fn powf(x: f32, y: f32) -> f32 {
+    x.powf(y)
+}
+
+#[cfg(not(feature = "std"))]
+fn powf(x: f32, y: f32) -> f32 {
+    libm::powf(x, y)
+}
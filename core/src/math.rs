@@ -0,0 +1,80 @@
+//! Weighting of any number of channel masks. [`mix_pixel`](crate::mix_pixel)
+//! mixes three; these take slices, so mixes of more channels share them.
+
+/// Sum of the products of `weights` and `values`, `Σ weights[i] * values[i]`.
+///
+/// Surplus elements of the longer slice are ignored.
+///
+/// # Examples
+/// ```
+/// use smix_core::math::dot;
+///
+/// assert_eq!(dot(&[0.5, 0.25, 0.25], &[1.0, 0.0, 0.5]), 0.625);
+/// ```
+pub fn dot(weights: &[f32], values: &[f32]) -> f32 {
+    debug_assert_eq!(weights.len(), values.len(), "one weight per value");
+    weights.iter().zip(values).map(|(w, v)| w * v).sum()
+}
+
+/// Weighted sum of `samples` per component: component `c` of the result is
+/// `Σ weights[i] * samples[i][c]`, i.e. [`dot`] of the weights with the
+/// `c`th component of every sample.
+///
+/// Surplus weights or samples are ignored.
+///
+/// # Examples
+/// ```
+/// use smix_core::math::weighted_sum_n;
+///
+/// let samples = [[1.0, 0.0], [0.0, 1.0], [1.0, 1.0], [0.0, 0.0]];
+/// assert_eq!(weighted_sum_n(&[0.5, 0.25, 0.25, 1.0], &samples), [0.75, 0.5]);
+/// ```
+pub fn weighted_sum_n<const C: usize>(weights: &[f32], samples: &[[f32; C]]) -> [f32; C] {
+    debug_assert_eq!(weights.len(), samples.len(), "one weight per sample");
+    let mut sum = [0.0; C];
+    for (w, sample) in weights.iter().zip(samples) {
+        for (s, v) in sum.iter_mut().zip(sample) {
+            *s += w * v;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_of_empty_slices_is_zero() {
+        assert_eq!(dot(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn dot_matches_three_channel_weighting() {
+        let (weight, value) = ([0.2, -0.5, 1.5], [0.3, 0.8, 0.1]);
+        let expected = weight[0] * value[0] + weight[1] * value[1] + weight[2] * value[2];
+        assert!((dot(&weight, &value) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn dot_takes_any_number_of_channels() {
+        assert_eq!(dot(&[1.0; 5], &[0.5, 0.25, 0.125, 0.0625, 0.0625]), 1.0);
+    }
+
+    #[test]
+    fn weighted_sum_n_is_dot_per_component() {
+        let weights = [0.1, 0.2, 0.3, 0.4];
+        let samples = [[1.0, 0.5, 0.0, 1.0], [0.0, 1.0, 0.5, 1.0], [0.5, 0.5, 0.5, 1.0], [1.0, 0.0, 1.0, 1.0]];
+        let sum = weighted_sum_n(&weights, &samples);
+        for c in 0..4 {
+            let component = samples.map(|sample| sample[c]);
+            assert!((sum[c] - dot(&weights, &component)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn weighted_sum_n_with_unit_weight_picks_sample() {
+        let samples = [[0.25, 0.5, 0.75], [0.1, 0.2, 0.3]];
+        assert_eq!(weighted_sum_n(&[0.0, 1.0], &samples), samples[1]);
+    }
+}
@@ -0,0 +1,19 @@
+//! Untrusted bytes as a packed mask and as three channel files: decoding must
+//! fail with an error, never panic or run out of memory.
+//!
+//! `cargo fuzz run decode_mask` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smix::Mask;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mask) = Mask::from_packed_bytes(data) {
+        mask.generate(&[0.5, 0.3, 0.2]);
+    }
+    let third = data.len() / 3;
+    let (r, rest) = data.split_at(third);
+    let (g, b) = rest.split_at(third);
+    let _ = Mask::from_bytes([r, g, b]);
+});
@@ -0,0 +1,197 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use smix::{animation::AnimatedMask, archive::ArchivePath, uv, BitDepth, ChannelMap, ExportOptions, Fallback, OutputFormat, ResamplePrecision, ResizeFilter, ResizeSpace};
+
+use crate::Settings;
+
+/// Written into the output directory by `--incremental` runs.
+pub const MANIFEST: &str = ".smix-incremental.toml";
+
+/// Output file name -> hash of the inputs it was generated from.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Manifest {
+    #[serde(default)]
+    outputs: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// A missing or unreadable manifest just means everything gets rebuilt.
+    pub fn load(dir: &Path) -> Self {
+        std::fs::read_to_string(dir.join(MANIFEST))
+            .ok()
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        smix::write_atomic(dir.join(MANIFEST), toml::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn is_fresh(&self, dir: &Path, name: &str, hash: &str) -> bool {
+        self.outputs.get(name).is_some_and(|h| h == hash) && dir.join(name).is_file()
+    }
+
+    pub fn record(&mut self, name: String, hash: String) {
+        self.outputs.insert(name, hash);
+    }
+}
+
+pub fn hash(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Layout of [`InputKey`]s; bump it when a field changes meaning, so every
+/// output is rebuilt once rather than kept by a hash that no longer fits.
+const INPUT_KEY_VERSION: &str = "smix input key 1";
+
+/// The inputs of one output file, added field by field with a text form
+/// written out here, so renaming a type or changing its derives never
+/// changes the hash, and two different settings never print the same.
+pub(crate) struct InputKey {
+    parts: Vec<Vec<u8>>,
+}
+
+impl InputKey {
+    pub(crate) fn new() -> Self {
+        Self { parts: vec![INPUT_KEY_VERSION.into()] }
+    }
+
+    /// Add `name` with the text of `value`.
+    pub(crate) fn field(&mut self, name: &str, value: impl fmt::Display) -> &mut Self {
+        self.parts.push(format!("{name}={value}").into_bytes());
+        self
+    }
+
+    /// Add `name` with raw `bytes`, such as another hash.
+    pub(crate) fn bytes(&mut self, name: &str, bytes: &[u8]) -> &mut Self {
+        self.parts.push(name.as_bytes().to_vec());
+        self.parts.push(bytes.to_vec());
+        self
+    }
+
+    /// Add everything in `options` that changes the encoded file. The UV
+    /// islands come from the hashed sources; cancelling and atomic writes
+    /// don't change the bytes.
+    pub(crate) fn export(&mut self, options: &ExportOptions) -> &mut Self {
+        let format = match options.format {
+            OutputFormat::Image(format) => format.extensions_str().first().copied().unwrap_or("image"),
+            OutputFormat::Jxl => "jxl",
+            OutputFormat::ExrF16 => "exr-f16",
+            OutputFormat::Ktx2 => "ktx2",
+            OutputFormat::Ktx2F16 => "ktx2-f16",
+        };
+        let filter = match options.filter {
+            ResizeFilter::Nearest => "nearest",
+            ResizeFilter::Box => "box",
+            ResizeFilter::Triangle => "triangle",
+            ResizeFilter::Hamming => "hamming",
+            ResizeFilter::CatmullRom => "catmull-rom",
+            ResizeFilter::Mitchell => "mitchell",
+            ResizeFilter::Gaussian => "gaussian",
+            ResizeFilter::Lanczos3 => "lanczos3",
+        };
+        let resize_space = match options.resize_space {
+            ResizeSpace::Srgb => "srgb",
+            ResizeSpace::Linear => "linear",
+            ResizeSpace::Auto => "auto",
+        };
+        let resample_precision = match options.resample_precision {
+            ResamplePrecision::U8 => "u8",
+            ResamplePrecision::F32 => "f32",
+        };
+        let bit_depth = match options.bit_depth {
+            BitDepth::Auto => "auto",
+            BitDepth::Eight => "8",
+            BitDepth::Sixteen => "16",
+        };
+        self.field("format", format)
+            .field("filter", filter)
+            .field("quality", optional(options.quality))
+            .field("speed", optional(options.speed))
+            .field("deterministic", options.deterministic)
+            .field("tileable", options.tileable)
+            .field("resize_space", resize_space)
+            .field("resample_precision", resample_precision)
+            .field("equalize", optional(options.equalize))
+            .field("white_balance", optional(options.white_balance.map(|b| format!("{} {}", b.temperature, b.tint))))
+            .field("vibrance", optional(options.vibrance))
+            .field("max_saturation", optional(options.max_saturation))
+            .field("color_space", options.color_space)
+            .field("sharpen", optional(options.sharpen.map(|s| format!("{} {}", s.amount, s.radius))))
+            .field("optimize", optional(options.optimize))
+            .field("palette", optional(options.palette.map(|p| format!("{} {}", p.colors, p.dither))))
+            .field("post", options.post.len());
+        for step in &options.post {
+            self.field("post_step", &step.name);
+        }
+        self.field("annotate", optional(options.annotate.as_deref()))
+            .field("padding", optional(options.padding))
+            .field("trim", optional(options.trim))
+            .field("canvas", optional(options.canvas))
+            .field("anchor", options.anchor)
+            .field("bit_depth", bit_depth)
+    }
+
+    pub(crate) fn finish(&self) -> String {
+        hash(&self.parts.iter().map(Vec::as_slice).collect::<Vec<_>>())
+    }
+}
+
+/// The text of `value`, or `none`.
+pub(crate) fn optional<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "none".into(), |value| value.to_string())
+}
+
+/// Hash of the settings besides the channel map that change how a mask set
+/// loads: the PSD layers and the `missing_channel` fallback, an image one by
+/// its contents.
+pub fn hash_load_settings(settings: &Settings) -> anyhow::Result<String> {
+    let mut parts: Vec<Vec<u8>> = settings.psd_layers.iter().map(|layer| layer.as_bytes().to_vec()).collect();
+    match &settings.missing_channel {
+        None => parts.push(b"none".to_vec()),
+        Some(Fallback::Black) => parts.push(b"black".to_vec()),
+        Some(Fallback::Image(path)) => {
+            parts.push(b"image".to_vec());
+            parts.push(std::fs::read(path)?);
+        }
+    }
+    Ok(hash(&parts.iter().map(Vec::as_slice).collect::<Vec<_>>()))
+}
+
+/// Hash of the raw channel files (and `uv.png`, if any) in a mask directory.
+pub fn hash_mask_sources(dir: &Path, map: &ChannelMap) -> anyhow::Result<String> {
+    // Constant channels have no file to hash
+    let constants = format!("{:?}", map.constants);
+    let constants = map.constants.iter().any(Option::is_some).then_some(constants.as_bytes());
+    if let Some(archive) = ArchivePath::parse(dir) {
+        let data = std::fs::read(&archive.archive)?;
+        let mut parts = vec![&data[..], archive.prefix.as_bytes()];
+        parts.extend(constants);
+        return Ok(hash(&parts));
+    }
+    // Layered .psd or packed image
+    if dir.is_file() {
+        return Ok(hash(&[&std::fs::read(dir)?]));
+    }
+    let files = AnimatedMask::detect(dir)
+        .unwrap_or_else(|| map.paths(dir));
+    // A missing channel hashes as empty; --missing-channel decides whether that's an error
+    let [r, g, b] = files.map(|file| match std::fs::read(file) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        read => read,
+    });
+    let (r, g, b) = (r?, g?, b?);
+    let mut parts = vec![&r[..], &g[..], &b[..]];
+    let uv = std::fs::read(dir.join(uv::FILE)).ok();
+    parts.extend(uv.as_deref());
+    parts.extend(constants);
+    Ok(hash(&parts))
+}
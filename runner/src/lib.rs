@@ -0,0 +1,1096 @@
+//! Batch generation shared by smix frontends: load the mask sets given in
+//! [`Settings`], then export every scale of every mix. Progress and results
+//! are reported as [`Event`]s to a [`Reporter`] instead of being printed, so
+//! the command line, the preview window and other frontends can present them
+//! their own way.
+
+use std::{borrow::Cow, collections::{BTreeMap, HashMap}, io::{stdout, Write}, path::{Component, Path, PathBuf}, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use anyhow::{ensure, Context};
+use image::ImageFormat;
+use indexmap::IndexMap;
+use rayon::prelude::*;
+use smix::{animation::AnimatedMask, archive::ArchivePath, cancel::CancelToken, colorspace::{self, ColorSpace}, layers, naming::{self, NameFields}, plugin, post::{PostStep, Registry}, script::Expression, sprite::SpriteGrid, uv::{self, UvIslands}, ChannelMap, ExportOptions, Fallback, GeneratedImage, Levels, Mask, MixSemantics, OutputFormat};
+
+use crate::incremental::Manifest;
+use crate::summary::{Summary, SummaryEntry, SummaryFormat};
+use crate::thumbnails::ThumbnailCache;
+use crate::trim::TrimReport;
+
+pub mod incremental;
+pub mod memory;
+pub mod remote;
+pub mod summary;
+pub mod thumbnails;
+pub mod trim;
+
+/// Everything a batch run depends on; mirrors the command line flags.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub weight: [f32; 3],
+    /// Whether `weight` is used as is or scaled to add up to 1
+    pub mix: MixSemantics,
+    /// Accept weights down to -1, subtracting their mask from the mix
+    pub allow_negative: bool,
+    /// Per-channel gain and bias applied to the masks before mixing
+    pub levels: Levels,
+    /// Output directory, or `-` for a single image on stdout
+    pub output: PathBuf,
+    /// Subdirectory of `output` for this run, [`AUTO_RUN_ID`] for a new one
+    /// named by the time; [`Runner::prepare`] moves `output` into it
+    pub run_id: Option<String>,
+    /// Mask directories, `.psd` files, packed images, archives or URLs
+    pub mask_directories: Vec<PathBuf>,
+    pub scale: Vec<f32>,
+    /// Also export this many successively halved levels of detail of every
+    /// scale, named `_lod0` to `_lodN` unless the template has `{lod}`
+    pub lods: Option<u32>,
+    pub name_template: String,
+    /// Subdirectory template for outputs, e.g. `{mask}/{scale}/`
+    pub output_layout: Option<String>,
+    pub sprite_grid: Option<SpriteGrid>,
+    pub sprite_repack: Option<SpriteGrid>,
+    pub sprite_frames: bool,
+    pub map: ChannelMap,
+    pub psd_layers: Vec<String>,
+    pub missing_channel: Option<Fallback>,
+    /// Skip mask sets that fail to load instead of failing the run
+    pub skip_invalid: bool,
+    /// Refuse to load mask sets estimated to need more bytes than this
+    pub max_memory: Option<u64>,
+    /// Fail on suspicious weights instead of warning, e.g. in CI
+    pub strict: bool,
+    /// What to do when two mask sets get the same name
+    pub name_collision: NameCollision,
+    /// Order mask sets are listed and generated in
+    pub sort: MaskOrder,
+    /// Per-pixel mix expression
+    pub expr: Option<String>,
+    pub incremental: bool,
+    /// Write `manifest.json` / `manifest.csv` listing every output, see [`summary`]
+    pub summary: Vec<SummaryFormat>,
+    pub plugins: Vec<PathBuf>,
+    /// Post-processing steps by name
+    pub post: Vec<String>,
+    /// Burn a caption into every output
+    pub annotate: bool,
+    /// Worker threads for generation and resizing, 0 for one per CPU
+    pub jobs: usize,
+    /// How outputs are encoded; `post`, `annotate` and `uv` are filled in by the runner
+    pub export: ExportOptions,
+    /// Formats written besides `export.format`, encoded from the same mix
+    pub extra_formats: Vec<OutputFormat>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            weight: [0.0; 3],
+            mix: MixSemantics::Sum,
+            allow_negative: false,
+            levels: Levels::default(),
+            output: PathBuf::from("output"),
+            run_id: None,
+            mask_directories: Vec::new(),
+            scale: Vec::new(),
+            lods: None,
+            name_template: naming::DEFAULT_TEMPLATE.into(),
+            output_layout: None,
+            sprite_grid: None,
+            sprite_repack: None,
+            sprite_frames: false,
+            map: ChannelMap::default(),
+            psd_layers: layers::DEFAULT_LAYERS.iter().map(|layer| layer.to_string()).collect(),
+            missing_channel: None,
+            skip_invalid: false,
+            max_memory: None,
+            strict: false,
+            name_collision: NameCollision::Error,
+            sort: MaskOrder::None,
+            expr: None,
+            incremental: false,
+            summary: Vec::new(),
+            plugins: Vec::new(),
+            post: Vec::new(),
+            annotate: false,
+            jobs: 0,
+            export: ExportOptions::default(),
+            extra_formats: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// `output` is `-`: the encoded image goes to stdout instead of a directory.
+    pub fn writes_stdout(&self) -> bool {
+        self.output.as_os_str() == "-"
+    }
+}
+
+/// Most levels of detail below the base size; enough to halve 65536 pixels to 1.
+pub const MAX_LODS: u32 = 16;
+
+/// The `run_id` that picks a new run directory, named by the UTC time.
+pub const AUTO_RUN_ID: &str = "auto";
+
+/// How far from 1 the weights may add up before [`weight_warning`] warns.
+const WEIGHT_SUM_TOLERANCE: f32 = 0.5;
+
+/// Why mixing by `weight` likely isn't what was meant: all weights are 0,
+/// or they add up far from 1, so fully covered pixels come out dark or clip.
+/// Subtractive weights aren't checked against the sum.
+pub fn weight_warning(weight: [f32; 3]) -> Option<String> {
+    if weight.iter().all(|&w| w == 0.0) {
+        return Some("All weights are 0, every output will be black".into());
+    }
+    let sum: f32 = weight.iter().sum();
+    if weight.iter().any(|&w| w < 0.0) || (sum - 1.0).abs() <= WEIGHT_SUM_TOLERANCE {
+        return None;
+    }
+    let effect = if sum < 1.0 { "come out dark" } else { "clip to white" };
+    Some(format!("Weights add up to {sum}, far from 1; pixels covered by all masks will {effect}"))
+}
+
+/// How mask sets whose names collide are told apart, e.g. `a/hero` and `b/hero`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NameCollision {
+    /// Fail
+    #[default]
+    Error,
+    /// Append `_2`, `_3`, ... to later sets
+    Suffix,
+    /// Name every set by its path below the directory all sets share, e.g.
+    /// `a/hero`; outputs go to subdirectories of the output directory
+    Path,
+}
+
+/// Order of the loaded mask sets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MaskOrder {
+    /// As given in `mask_directories`
+    #[default]
+    None,
+    /// By name
+    Name,
+    /// By pixel count, smallest first
+    Size,
+}
+
+/// Progress of a [`Runner`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// The checked mix weights, after applying the `mix` semantics
+    Weights([f32; 3]),
+    /// The mix expression compiled
+    Expression(&'a str),
+    /// Outputs go to the directory of this run, see [`Settings::run_id`]
+    RunId(&'a str),
+    /// Outputs go to `path`, which was `created` if missing
+    OutputDirectory { path: &'a Path, created: bool },
+    /// The single output goes to stdout
+    Stdout,
+    /// An animated mask set was loaded
+    AnimatedMask { name: &'a str, frames: usize },
+    /// A mask set has a `uv.png`
+    UvIslands { name: &'a str, islands: u32 },
+    /// The scale factor at `index` is negative and was skipped
+    NegativeScale { index: usize, scale: f32 },
+    /// An `incremental` output is unchanged and was skipped
+    UpToDate { file: &'a str },
+    /// An output file was written
+    Generated { file: &'a str },
+    /// Something was worked around rather than failing the run
+    Warning(String),
+    /// The run is done, but these mask sets were skipped as invalid
+    Skipped(&'a [LoadFailure]),
+}
+
+/// Receives the [`Event`]s of a run; called from worker threads.
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: Event<'_>);
+}
+
+impl<F: Fn(Event<'_>) + Send + Sync> Reporter for F {
+    fn report(&self, event: Event<'_>) {
+        self(event)
+    }
+}
+
+/// The masks and plugins of a run, for frontends that mix interactively
+/// instead of generating the batch.
+pub struct Loaded {
+    pub settings: Settings,
+    /// Animated sets are represented by their first frame; in `sort` order.
+    /// `settings.levels` is not applied yet.
+    pub masks: IndexMap<String, Mask>,
+    /// Directory each mask was loaded from
+    pub paths: IndexMap<String, PathBuf>,
+    /// Full size of the masks loaded as downscaled proxies, see [`Runner::with_thumbnails`]
+    pub full_sizes: HashMap<String, (u32, u32)>,
+    /// How long loading each mask set took, downloads and proxies included
+    pub load_times: HashMap<String, Duration>,
+    pub presets: BTreeMap<String, [f32; 3]>,
+    /// Built-in and plugin post-processing steps
+    pub registry: Registry,
+    /// Mask sets that failed to load
+    pub failures: Vec<LoadFailure>,
+}
+
+/// A mask set that failed to load.
+#[derive(Clone, Debug)]
+pub struct LoadFailure {
+    pub source: PathBuf,
+    pub error: String,
+}
+
+pub struct Runner {
+    settings: Settings,
+    reporter: Box<dyn Reporter>,
+    masks: IndexMap<String, Mask>,
+    animations: IndexMap<String, AnimatedMask>,
+    /// Directory each mask was loaded from, in `sort` order of all sets
+    paths: IndexMap<String, PathBuf>,
+    presets: BTreeMap<String, [f32; 3]>,
+    /// Content hash of each mask's source files, only filled with `incremental`
+    sources: HashMap<String, String>,
+    /// Built-in and plugin post-processing steps
+    registry: Registry,
+    /// The `post` steps, resolved by [`Runner::load_plugins`]
+    post: Vec<PostStep>,
+    /// The `expr` mix, compiled by [`Runner::prepare`]
+    expr: Option<Expression>,
+    /// Crops of the trimmed outputs
+    trims: Mutex<TrimReport>,
+    /// Every output, for the `summary` files
+    summary: Mutex<Summary>,
+    /// Stops [`Runner::generate`] between outputs, see [`Runner::with_cancel`]
+    cancel: CancelToken,
+    /// UV islands of the mask sets with a `uv.png`
+    uvs: HashMap<String, Arc<UvIslands>>,
+    /// Mask sets that failed to load
+    failures: Vec<LoadFailure>,
+    /// Load masks as proxies from here, see [`Runner::with_thumbnails`]
+    thumbnails: Option<ThumbnailCache>,
+    /// Full size of the masks loaded as proxies
+    full_sizes: HashMap<String, (u32, u32)>,
+    load_times: HashMap<String, Duration>,
+}
+
+impl Runner {
+    pub fn new(settings: Settings, presets: BTreeMap<String, [f32; 3]>, reporter: impl Reporter + 'static) -> Self {
+        Self {
+            settings,
+            reporter: Box::new(reporter),
+            masks: IndexMap::new(),
+            animations: IndexMap::new(),
+            paths: IndexMap::new(),
+            presets,
+            sources: HashMap::new(),
+            registry: Registry::with_builtins(),
+            post: Vec::new(),
+            expr: None,
+            trims: Mutex::default(),
+            summary: Mutex::new(Summary::new()),
+            cancel: CancelToken::new(),
+            uvs: HashMap::new(),
+            failures: Vec::new(),
+            thumbnails: None,
+            full_sizes: HashMap::new(),
+            load_times: HashMap::new(),
+        }
+    }
+
+    /// Stop generating when `cancel` is cancelled, e.g. on Ctrl+C. Outputs
+    /// being encoded at that moment are finished or not written at all, and
+    /// the reports list what was written; `generate` then fails with
+    /// [`Cancelled`](smix::cancel::Cancelled).
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Load mask sets as downscaled proxies through `cache`, for previews:
+    /// only sets whose files changed since the last run are decoded. Archives
+    /// and animated sets are loaded in full.
+    pub fn with_thumbnails(mut self, cache: ThumbnailCache) -> Self {
+        self.thumbnails = Some(cache);
+        self
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// The weights to mix by, see [`MixSemantics::effective_weight`].
+    fn weight(&self) -> [f32; 3] {
+        self.settings.mix.effective_weight(self.settings.weight)
+    }
+
+    fn report(&self, event: Event<'_>) {
+        self.reporter.report(event);
+    }
+
+    /// Check the settings, compile the mix expression and create the output directory.
+    pub fn prepare(&mut self) -> anyhow::Result<()> {
+        let (range, allowed) = if self.settings.allow_negative {
+            (-1.0..=1.0, "[-1, 1]")
+        } else {
+            (0.0..=1.0, "[0, 1] (see --allow-negative)")
+        };
+        for (weight, channel) in self.settings.weight.iter().zip(["Red", "Green", "Blue"]) {
+            ensure!(range.contains(weight), "{channel} weight must be in {allowed}");
+        }
+        let export = &self.settings.export;
+        if let Some(balance) = export.white_balance {
+            ensure!((1000.0..=40000.0).contains(&balance.temperature), "Temperature must be in 1000~40000 K");
+            ensure!((-1.0..=1.0).contains(&balance.tint), "Tint must be in -1~1");
+        }
+        ensure!(export.vibrance.is_none_or(|amount| (-1.0..=1.0).contains(&amount)), "Vibrance must be in -1~1");
+        ensure!(export.max_saturation.is_none_or(|max| (0.0..=1.0).contains(&max)), "Maximum saturation must be in 0~1");
+        if export.color_space != ColorSpace::Srgb {
+            for format in self.formats().into_iter().filter(|&format| !colorspace::can_tag(format)) {
+                self.warn(format!(
+                    ".{} files can't be tagged as {}; viewers will take their colors for sRGB",
+                    format.extension(), self.settings.export.color_space,
+                ))?;
+            }
+        }
+        let formats = self.formats();
+        for (i, format) in formats.iter().enumerate() {
+            let ext = format.extension();
+            ensure!(formats[..i].iter().all(|other| other.extension() != ext), "Two output formats write .{ext} files; pick one of them");
+        }
+        ensure!(!self.settings.mask_directories.is_empty(), "No mask directories given (use -m or smix.toml)");
+        if let (Some(grid), Some(repack)) = (self.settings.sprite_grid, self.settings.sprite_repack) {
+            ensure!(repack.frame_count() >= grid.frame_count(), "{grid} sprite frames don't fit in a {repack} grid");
+        }
+
+        if self.settings.writes_stdout() {
+            ensure!(self.settings.mask_directories.len() == 1, "Writing to stdout needs exactly one mask directory");
+            ensure!(self.settings.scale.len() <= 1, "Writing to stdout needs at most one scale");
+            ensure!(self.settings.extra_formats.is_empty(), "Writing to stdout needs a single format");
+            ensure!(!self.settings.incremental && !self.settings.sprite_frames, "--incremental and --sprite-frames need an output directory");
+            ensure!(self.settings.summary.is_empty(), "--manifest needs an output directory");
+            ensure!(self.settings.run_id.is_none(), "--run-id needs an output directory");
+            ensure!(self.settings.lods.is_none(), "Writing to stdout needs a single size, not --lods");
+        }
+        if let Some(layout) = &self.settings.output_layout {
+            ensure!(!Path::new(layout).has_root(), "Output layout {layout:?} must be relative to the output directory");
+        }
+        ensure!(self.settings.lods.is_none_or(|lods| lods <= MAX_LODS), "At most {MAX_LODS} levels of detail");
+        ensure!(
+            self.settings.lods.is_none() || !self.settings.sprite_frames,
+            "--sprite-frames can't be combined with --lods",
+        );
+        self.output_name("mask", 1, 1, 1.0, self.settings.lods.map(|_| 0), self.settings.export.format)?;
+        if let Some(expr) = &self.settings.expr {
+            self.expr = Some(Expression::compile(expr)?);
+            self.report(Event::Expression(expr));
+        }
+
+        self.report(Event::Weights(self.weight()));
+        // An expression may not mix by the weights at all
+        if self.settings.expr.is_none()
+            && let Some(warning) = weight_warning(self.weight())
+        {
+            self.warn(warning)?;
+        }
+
+        if !self.settings.writes_stdout() || self.settings.scale.is_empty() {
+            self.settings.scale.push(1.0);
+        }
+        self.settings.scale.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        self.settings.scale.dedup();
+
+        if self.settings.writes_stdout() {
+            self.report(Event::Stdout);
+        } else {
+            if let Some(run_id) = &self.settings.run_id {
+                let run_id = self.run_directory(run_id)?;
+                self.settings.output.push(&run_id);
+                self.report(Event::RunId(&run_id));
+                self.summary.get_mut().unwrap().run_id = Some(run_id);
+            }
+            let created = !self.settings.output.exists();
+            if created {
+                std::fs::create_dir_all(&self.settings.output)?;
+            }
+            self.report(Event::OutputDirectory { path: &self.settings.output, created });
+        }
+
+        Ok(())
+    }
+
+    /// The run directory `run_id` names under `output`: itself, checked to
+    /// be a plain directory name, or for [`AUTO_RUN_ID`] the UTC time, with
+    /// a counter if a run of the same second exists.
+    fn run_directory(&self, run_id: &str) -> anyhow::Result<String> {
+        if run_id != AUTO_RUN_ID {
+            let mut components = Path::new(run_id).components();
+            ensure!(
+                matches!((components.next(), components.next()), (Some(std::path::Component::Normal(_)), None)),
+                "Run ID {run_id:?} must be a plain directory name",
+            );
+            return Ok(run_id.into());
+        }
+        let stamp = utc_date_time().replace(':', "");
+        let mut run_id = stamp.clone();
+        for n in 2.. {
+            if !self.settings.output.join(&run_id).exists() {
+                break;
+            }
+            run_id = format!("{stamp}-{n}");
+        }
+        Ok(run_id)
+    }
+
+    /// Load the plugin libraries and resolve the `post` steps.
+    pub fn load_plugins(&mut self) -> anyhow::Result<()> {
+        for path in &self.settings.plugins {
+            plugin::load(path, &mut self.registry)
+                .with_context(|| format!("Loading plugin {}", path.display()))?;
+        }
+        self.post = self.registry.resolve(&self.settings.post)?;
+        Ok(())
+    }
+
+    /// Load every mask set, failing on the first that can't be loaded unless
+    /// `skip_invalid` is set.
+    pub fn load_masks(&mut self) -> anyhow::Result<()> {
+        if let Some(warning) = self.check_memory() {
+            anyhow::bail!("{warning}");
+        }
+        for source in self.settings.mask_directories.clone() {
+            let Err(e) = self.load_source(&source) else { continue };
+            if !self.settings.skip_invalid {
+                return Err(e.context(format!("Invalid mask set {}", source.display())));
+            }
+            let error = format!("{e:#}");
+            self.report(Event::Warning(format!("Skipping invalid mask set {}: {error}", source.display())));
+            self.failures.push(LoadFailure { source, error });
+        }
+        self.sort_masks();
+        Ok(())
+    }
+
+    /// Load the mask sets that can be loaded and collect the errors of the
+    /// others in [`Runner::failures`].
+    pub fn load_masks_lenient(&mut self) {
+        if let Some(warning) = self.check_memory() {
+            self.report(Event::Warning(warning));
+        }
+        for source in self.settings.mask_directories.clone() {
+            if let Err(e) = self.load_source(&source) {
+                self.failures.push(LoadFailure { source, error: format!("{e:#}") });
+            }
+        }
+        self.sort_masks();
+    }
+
+    /// Load the mask set at `source` on its own and in full, the way
+    /// [`Runner::load_masks`] loads each set: through the channel map,
+    /// `missing_channel` fallback and PSD layers of `settings`, reporting its
+    /// warnings to `reporter`. For frontends that load sets one at a time.
+    /// Animated sets give their first frame; `settings.levels` is not applied.
+    pub fn load_mask_set(settings: &Settings, source: &Path, reporter: impl Reporter + 'static) -> anyhow::Result<Mask> {
+        let settings = Settings {
+            mask_directories: vec![source.to_path_buf()],
+            name_collision: NameCollision::Suffix,
+            incremental: false,
+            ..settings.clone()
+        };
+        let mut runner = Runner::new(settings, BTreeMap::new(), reporter);
+        runner.load_source(source)?;
+        let (_, mask) = runner.into_loaded().masks.into_iter().next().expect("the set was loaded");
+        Ok(mask)
+    }
+
+    /// Report `warning`, or fail with it when `strict`.
+    fn warn(&self, warning: String) -> anyhow::Result<()> {
+        ensure!(!self.settings.strict, "{warning} (--strict)");
+        self.report(Event::Warning(warning));
+        Ok(())
+    }
+
+    /// Compare the estimated memory of every local mask set against
+    /// `max_memory`, returning why it is exceeded. Sets whose size can't be
+    /// read from a header, like archives, animated sets and URLs, count as
+    /// empty and are reported.
+    fn check_memory(&self) -> Option<String> {
+        let limit = self.settings.max_memory?;
+        let mut estimate = 0;
+        let mut unknown = Vec::new();
+        for source in &self.settings.mask_directories {
+            match Mask::estimate_memory(source, &self.settings.map).filter(|_| !remote::is_url(source)) {
+                Some(size) => estimate += size,
+                None => unknown.push(source.display().to_string()),
+            }
+        }
+        if !unknown.is_empty() {
+            self.report(Event::Warning(format!(
+                "Can't estimate the memory of {}; --max-memory doesn't count them",
+                unknown.join(", "),
+            )));
+        }
+        (estimate > limit).then(|| format!(
+            "Loading the mask sets needs about {}, more than --max-memory {}",
+            memory::format_size(estimate),
+            memory::format_size(limit),
+        ))
+    }
+
+    /// Put the loaded sets in `sort` order; ties keep the order they were given in.
+    fn sort_masks(&mut self) {
+        match self.settings.sort {
+            MaskOrder::None => return,
+            MaskOrder::Name => self.paths.sort_keys(),
+            MaskOrder::Size => {
+                let pixels = |name: &String| {
+                    let (width, height) = match self.masks.get(name) {
+                        Some(_) if let Some(&size) = self.full_sizes.get(name) => size,
+                        Some(mask) => mask.dimensions(),
+                        None => self.animations[name].dimensions(),
+                    };
+                    width as u64 * height as u64
+                };
+                let pixels: HashMap<_, _> = self.paths.keys().map(|name| (name.clone(), pixels(name))).collect();
+                self.paths.sort_by_key(|name, _| pixels[name]);
+            }
+        }
+        self.masks.sort_by_cached_key(|name, _| self.paths.get_index_of(name));
+        self.animations.sort_by_cached_key(|name, _| self.paths.get_index_of(name));
+    }
+
+    /// Mask sets skipped by [`Runner::load_masks_lenient`] or `skip_invalid`.
+    pub fn failures(&self) -> &[LoadFailure] {
+        &self.failures
+    }
+
+    fn load_source(&mut self, source: &Path) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let url = source.to_str().filter(|_| remote::is_url(source));
+        let path = &match url {
+            Some(url) => remote::fetch(url, &self.settings.map)?,
+            None => source.to_path_buf(),
+        };
+        let name = format!("{}", path.display());
+        let name = url.map_or_else(|| name.trim_end_matches('/').rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or("result"), remote::name);
+        let archive = ArchivePath::parse(path);
+        let archive_name = archive.as_ref().map(ArchivePath::name);
+        let name = if path.is_file() { name.rsplit_once('.').map_or(name, |(stem, _)| stem) } else { name };
+        let name = archive_name.as_deref().unwrap_or(name);
+        let key = self.mask_key(source, name, path.is_file())?;
+        let name = key.as_str();
+        let animated = path.is_dir() && AnimatedMask::detect(path).is_some();
+        let proxied = archive.is_none() && !animated;
+        let hash = if self.settings.incremental {
+            Some(incremental::hash_mask_sources(path, &self.settings.map)?)
+        } else {
+            None
+        };
+        // Warnings about the set's files, kept with its proxy so a cache hit
+        // reports them too
+        let mut warnings = Vec::new();
+        let cached = self.thumbnails.as_ref().filter(|_| proxied).and_then(|cache| cache.get(path, &self.settings));
+        let hit = cached.is_some();
+        if let Some(proxy) = cached {
+            self.full_sizes.insert(name.into(), proxy.full_size);
+            self.masks.insert(name.into(), proxy.mask);
+            warnings = proxy.warnings;
+        } else if let Some(archive) = &archive {
+            self.masks.insert(name.into(), Mask::from_archive(archive, &self.settings.map)?);
+        } else if layers::is_psd(path) {
+            let [r, g, b] = &self.settings.psd_layers[..] else {
+                anyhow::bail!("--psd-layers needs exactly three names");
+            };
+            self.masks.insert(name.into(), Mask::from_psd(path, &[r, g, b])?);
+        } else if path.is_file() {
+            let mask = Mask::open_packed(path)
+                .with_context(|| format!("Loading packed mask {}", path.display()))?;
+            warnings = mask.color_space_warnings([Some(path.as_path()); 3]);
+            self.masks.insert(name.into(), mask);
+        } else if animated {
+            let anim = AnimatedMask::new(path)?;
+            self.report(Event::AnimatedMask { name, frames: anim.frame_count() });
+            if self.settings.expr.is_some() {
+                self.report(Event::Warning(format!("{name}: --expr is not applied to animated masks")));
+            }
+            self.animations.insert(name.into(), anim);
+        } else {
+            let mask = match &self.settings.missing_channel {
+                Some(fallback) => {
+                    let (mask, missing) = Mask::new_with_fallback(path, &self.settings.map, fallback)?;
+                    warnings.extend(missing.iter().map(|file| format!("{} is missing, using {fallback}", file.display())));
+                    mask
+                }
+                None => Mask::new_with_mapping(path, &self.settings.map)
+                    .with_context(|| format!("Loading mask {} (see --missing-channel)", path.display()))?,
+            };
+            let files = self.settings.map.paths(path);
+            let files = std::array::from_fn(|i| {
+                Some(files[i].as_path()).filter(|file| self.settings.map.constants[i].is_none() && file.is_file())
+            });
+            warnings.extend(mask.color_space_warnings(files));
+            self.masks.insert(name.into(), mask);
+        }
+        let uv_path = path.join(uv::FILE);
+        if path.is_dir() && !animated && archive.is_none() && uv_path.is_file() {
+            let islands = UvIslands::load(&uv_path)
+                .with_context(|| format!("Loading {}", uv_path.display()))?;
+            self.report(Event::UvIslands { name, islands: islands.island_count() });
+            self.uvs.insert(name.into(), Arc::new(islands));
+        }
+        for warning in &warnings {
+            self.report(Event::Warning(format!("{name}: {warning}")));
+        }
+        if let Some(hash) = hash {
+            self.sources.insert(name.into(), hash);
+        }
+        if proxied
+            && !hit
+            && let Some(cache) = &self.thumbnails
+            && let Some(mask) = self.masks.get_mut(name)
+            && let Cow::Owned(proxy) = mask.downscaled(cache.size())
+        {
+            let full_size = mask.dimensions();
+            let stored = cache.insert(path, &self.settings, &proxy, full_size, &warnings);
+            *mask = proxy;
+            self.full_sizes.insert(name.into(), full_size);
+            if let Err(e) = stored {
+                self.report(Event::Warning(format!("{name}: caching the preview failed: {e:#}")));
+            }
+        }
+        self.paths.insert(name.into(), path.clone());
+        self.load_times.insert(name.into(), start.elapsed());
+        Ok(())
+    }
+
+    /// Name of the mask set at `source`, which would be called `name`, according to `name_collision`.
+    fn mask_key(&self, source: &Path, name: &str, is_file: bool) -> anyhow::Result<String> {
+        let taken = |key: &str| self.paths.contains_key(key);
+        match self.settings.name_collision {
+            NameCollision::Error | NameCollision::Suffix if !taken(name) => Ok(name.into()),
+            NameCollision::Error => anyhow::bail!(
+                "Mask name {name:?} of {} is already used by {}; see --on-name-collision",
+                source.display(),
+                self.paths[name].display(),
+            ),
+            NameCollision::Suffix => Ok((2..)
+                .map(|i| format!("{name}_{i}"))
+                .find(|key| !taken(key))
+                .expect("some suffix is free")),
+            NameCollision::Path => {
+                let key = path_key(source, &self.settings.mask_directories, is_file)?;
+                anyhow::ensure!(!taken(&key), "Mask set {} is given twice", source.display());
+                Ok(key)
+            }
+        }
+    }
+
+    /// Hand over the loaded masks instead of generating them.
+    pub fn into_loaded(mut self) -> Loaded {
+        let masks = self.paths.keys().map(|name| {
+            let mask = match self.masks.swap_remove(name) {
+                Some(mask) => mask,
+                None => self.animations.swap_remove(name).expect("every path has a mask").into_first(),
+            };
+            (name.clone(), mask)
+        }).collect();
+        Loaded {
+            settings: self.settings,
+            masks,
+            paths: self.paths,
+            full_sizes: self.full_sizes,
+            load_times: self.load_times,
+            presets: self.presets,
+            registry: self.registry,
+            failures: self.failures,
+        }
+    }
+
+    pub fn generate(self) -> anyhow::Result<()> {
+        let output = &self.settings.output;
+        let manifest = self.settings.incremental.then(|| Mutex::new(Manifest::load(output)));
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(self.settings.jobs).build()?;
+        let weight = self.weight();
+        let options = self.export_options();
+        let manifest_ref = manifest.as_ref();
+        let formats = self.formats();
+        if options.trim.is_some() && !self.settings.writes_stdout() {
+            *self.trims.lock().unwrap() = TrimReport::load(output);
+        }
+        let result = pool.install(|| {
+            self.masks.par_iter().try_for_each(|(name, mask)| {
+                let options = ExportOptions { uv: self.uvs.get(name).cloned(), ..options.clone() };
+                self.generate_mask(
+                    name, self.sheet_dimensions(mask.dimensions())?, &formats, manifest_ref,
+                    || {
+                        let mask = self.leveled(mask, Mask::apply_levels);
+                        Ok(self.sprite_sheet(match &self.expr {
+                            Some(expr) => mask.generate_with(expr, &weight)?,
+                            None => mask.generate_cancellable(&weight, &self.cancel)?,
+                        }))
+                    },
+                    |img, path, nwidth, nheight, format| {
+                        let options = ExportOptions { format, ..options.clone() };
+                        if self.settings.writes_stdout() {
+                            return Ok(stdout().lock().write_all(&img.encode(nwidth, nheight, &options)?)?);
+                        }
+                        self.export_trimmed(img, path, nwidth, nheight, &options)?;
+                        self.export_sprite_frames(name, img, nwidth, nheight, &options)
+                    },
+                )
+            })?;
+            self.animations.par_iter().try_for_each(|(name, anim)| self.generate_mask(
+                name, anim.dimensions(), &[ImageFormat::Gif.into()], manifest_ref,
+                || Ok(self.leveled(anim, AnimatedMask::apply_levels).generate(&weight)),
+                |anim, path, nwidth, nheight, _| {
+                    if self.settings.writes_stdout() {
+                        return anim.write_gif(stdout().lock(), nwidth, nheight, options.filter);
+                    }
+                    anim.save_gif(path, nwidth, nheight, options.filter)
+                },
+            ))
+        });
+        if let Some(manifest) = manifest {
+            manifest.into_inner().unwrap().save(output)?;
+        }
+        if options.trim.is_some() && !self.settings.writes_stdout() {
+            self.trims.into_inner().unwrap().save(output)?;
+        }
+        // An interrupted run still lists what it wrote
+        if result.is_ok() || self.cancel.is_cancelled() {
+            let mut summary = self.summary.into_inner().unwrap();
+            for &format in &self.settings.summary {
+                summary.save(output, format)?;
+            }
+        }
+        if !self.failures.is_empty() {
+            self.reporter.report(Event::Skipped(&self.failures));
+        }
+        result
+    }
+
+    /// `mask` corrected by `levels`, copied only if there is a correction.
+    fn leveled<'a, T: Clone>(&self, mask: &'a T, apply: impl Fn(&mut T, &Levels)) -> Cow<'a, T> {
+        if self.settings.levels.is_identity() {
+            return Cow::Borrowed(mask);
+        }
+        let mut mask = mask.clone();
+        apply(&mut mask, &self.settings.levels);
+        Cow::Owned(mask)
+    }
+
+    /// `export.format` followed by the `extra_formats`, without repeats.
+    fn formats(&self) -> Vec<OutputFormat> {
+        let mut formats = vec![self.settings.export.format];
+        for format in &self.settings.extra_formats {
+            if !formats.contains(format) {
+                formats.push(*format);
+            }
+        }
+        formats
+    }
+
+    /// Export every scale of one mask set, and its levels of detail, in every
+    /// format, generating the mix lazily so that fully up-to-date sets are
+    /// never mixed at all.
+    fn generate_mask<T>(
+        &self,
+        name: &str,
+        (width, height): (u32, u32),
+        formats: &[OutputFormat],
+        manifest: Option<&Mutex<Manifest>>,
+        generate: impl Fn() -> anyhow::Result<T>,
+        export: impl Fn(&T, &Path, u32, u32, OutputFormat) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut img = None;
+        for (index, &scale) in self.settings.scale.iter().enumerate() {
+            if scale < 0.0 {
+                self.report(Event::NegativeScale { index, scale });
+                continue;
+            }
+            let (base_width, base_height) = ((width as f32 * scale) as u32, (height as f32 * scale) as u32);
+            let lods = match self.settings.lods {
+                Some(count) => (0..=count).map(Some).collect(),
+                None => vec![None],
+            };
+            for (lod, &format) in lods.into_iter().flat_map(|lod| formats.iter().map(move |format| (lod, format))) {
+                self.cancel.check()?;
+                let (nwidth, nheight) = match lod {
+                    // Halved from the base size, not from the previous level, down to 1x1
+                    Some(lod) => ((base_width >> lod).max(1), (base_height >> lod).max(1)),
+                    None => (base_width, base_height),
+                };
+                let output_name = self.output_name(name, nwidth, nheight, scale, lod, format)?;
+
+                let key = manifest.map(|_| self.input_hash(name, scale, lod, format)).transpose()?;
+                if let (Some(manifest), Some(key)) = (manifest, &key)
+                    && manifest.lock().unwrap().is_fresh(&self.settings.output, &output_name, key)
+                {
+                    self.report(Event::UpToDate { file: &output_name });
+                    self.record_output(name, &output_name, (nwidth, nheight), scale, format)?;
+                    continue;
+                }
+
+                let img = match &mut img {
+                    Some(img) => img,
+                    None => img.insert(generate()?),
+                };
+                let path = self.settings.output.join(&output_name);
+                // Mask names may contain directories with `NameCollision::Path`, or the `output_layout`
+                if let Some(dir) = path.parent().filter(|_| !self.settings.writes_stdout()) {
+                    std::fs::create_dir_all(dir)?;
+                }
+                export(img, &path, nwidth, nheight, format)?;
+                self.report(Event::Generated { file: &output_name });
+                self.record_output(name, &output_name, (nwidth, nheight), scale, format)?;
+
+                if let (Some(manifest), Some(key)) = (manifest, key) {
+                    manifest.lock().unwrap().record(output_name, key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// With `summary`, list the output `file` (relative to `output`) of the mask set `mask`.
+    fn record_output(&self, mask: &str, file: &str, (width, height): (u32, u32), scale: f32, format: OutputFormat) -> anyhow::Result<()> {
+        if self.settings.summary.is_empty() {
+            return Ok(());
+        }
+        let entry = SummaryEntry {
+            file: file.into(),
+            mask: mask.into(),
+            source: self.paths[mask].display().to_string(),
+            width,
+            height,
+            scale,
+            format: format.extension().into(),
+            weight: self.settings.weight,
+            sha256: summary::sha256_file(&self.settings.output.join(file))?,
+        };
+        self.summary.lock().unwrap().outputs.push(entry);
+        Ok(())
+    }
+
+    /// Path of an output relative to `output`, in its `output_layout` directory.
+    fn output_name(&self, mask: &str, width: u32, height: u32, scale: f32, lod: Option<u32>, format: OutputFormat) -> anyhow::Result<String> {
+        let fields = NameFields { mask, width, height, scale, weight: self.settings.weight, lod };
+        let name = naming::render(&self.settings.name_template, &fields, format)?;
+        let Some(layout) = &self.settings.output_layout else {
+            return Ok(name);
+        };
+        let dir = naming::expand(layout, &fields)?;
+        let dir = dir.trim_end_matches('/');
+        Ok(if dir.is_empty() { name } else { format!("{dir}/{name}") })
+    }
+
+    /// Output size of a mask, accounting for `sprite_repack`.
+    fn sheet_dimensions(&self, dimensions: (u32, u32)) -> anyhow::Result<(u32, u32)> {
+        match (self.settings.sprite_grid, self.settings.sprite_repack) {
+            (Some(grid), Some(repack)) => Ok(repack.sheet_size(grid.frame_size(dimensions)?)),
+            (Some(grid), None) => grid.frame_size(dimensions).map(|_| dimensions),
+            _ => Ok(dimensions),
+        }
+    }
+
+    fn sprite_sheet(&self, img: GeneratedImage) -> GeneratedImage {
+        match (self.settings.sprite_grid, self.settings.sprite_repack) {
+            (Some(grid), Some(repack)) => {
+                let frames = img.split(grid).expect("grid checked by sheet_dimensions");
+                GeneratedImage::pack(&frames, repack).expect("repack size checked by prepare")
+            }
+            _ => img,
+        }
+    }
+
+    /// With `sprite_frames`, write each frame of an exported sheet next to it.
+    fn export_sprite_frames(&self, name: &str, sheet: &GeneratedImage, nwidth: u32, nheight: u32, options: &ExportOptions) -> anyhow::Result<()> {
+        let Some(grid) = self.settings.sprite_grid.filter(|_| self.settings.sprite_frames) else {
+            return Ok(());
+        };
+        let grid = self.settings.sprite_repack.unwrap_or(grid);
+        // The UV mask covers the whole sheet
+        let options = &ExportOptions { uv: None, ..options.clone() };
+        let (fwidth, fheight) = (nwidth / grid.columns, nheight / grid.rows);
+        for (i, frame) in sheet.split(grid)?.iter().enumerate() {
+            let scale = fwidth as f32 * grid.columns as f32 / sheet.dimensions().0 as f32;
+            let frame_name = self.output_name(&format!("{name}_{i}"), fwidth, fheight, scale, None, options.format)?;
+            let frame_path = self.settings.output.join(&frame_name);
+            if let Some(dir) = frame_path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            self.export_trimmed(frame, &frame_path, fwidth, fheight, options)?;
+            self.report(Event::Generated { file: &frame_name });
+            self.record_output(name, &frame_name, (fwidth, fheight), scale, options.format)?;
+        }
+        Ok(())
+    }
+
+    /// Export `img`, recording its placement when trimming.
+    fn export_trimmed(&self, img: &GeneratedImage, path: &Path, nwidth: u32, nheight: u32, options: &ExportOptions) -> anyhow::Result<()> {
+        if let Some(placement) = img.export_with_placement(path, nwidth, nheight, options)? {
+            let name = path.strip_prefix(&self.settings.output).unwrap_or(path).to_string_lossy().into_owned();
+            self.trims.lock().unwrap().record(name, placement, (nwidth, nheight));
+        }
+        Ok(())
+    }
+
+    /// Hash of everything that determines one output file.
+    fn input_hash(&self, name: &str, scale: f32, lod: Option<u32>, format: OutputFormat) -> anyhow::Result<String> {
+        let triple = |[r, g, b]: [f32; 3]| format!("{r} {g} {b}");
+        let load = incremental::hash_load_settings(&self.settings)?;
+        let mut key = incremental::InputKey::new();
+        key.field("smix", env!("CARGO_PKG_VERSION"))
+            .bytes("sources", self.sources[name].as_bytes())
+            .bytes("load", load.as_bytes())
+            .field("weight", triple(self.weight()))
+            .field("gain", triple(self.settings.levels.gain))
+            .field("bias", triple(self.settings.levels.bias))
+            .field("expr", incremental::optional(self.settings.expr.as_deref()))
+            .field("scale", scale)
+            .field("lod", incremental::optional(lod))
+            .field("sprite_grid", incremental::optional(self.settings.sprite_grid))
+            .field("sprite_repack", incremental::optional(self.settings.sprite_repack))
+            .field("sprite_frames", self.settings.sprite_frames)
+            .export(&ExportOptions { format, ..self.export_options() });
+        Ok(key.finish())
+    }
+
+    /// The export options of every output; the UV islands are set per mask set by `generate`.
+    pub fn export_options(&self) -> ExportOptions {
+        ExportOptions {
+            cancel: Some(self.cancel.clone()),
+            post: self.post.clone(),
+            annotate: self.settings.annotate.then(|| self.caption()),
+            uv: None,
+            ..self.settings.export.clone()
+        }
+    }
+
+    /// `annotate` caption: the weights, the preset they match if any, and
+    /// today's date unless the output must be reproducible.
+    fn caption(&self) -> String {
+        let weight = self.settings.weight;
+        let mut caption = format!("{} {} {}", weight[0], weight[1], weight[2]);
+        if let Some((name, _)) = self.presets.iter().find(|(_, preset)| **preset == weight) {
+            caption.push(' ');
+            caption.push_str(name);
+        }
+        if !self.settings.export.deterministic {
+            caption.push(' ');
+            caption.push_str(&utc_date());
+        }
+        caption
+    }
+}
+
+/// The [`NameCollision::Path`] name of `source`: its path below the
+/// directories all `sources` share, so that absolute paths and paths out of
+/// `..` still name outputs inside the output directory; a file's extension
+/// is dropped. Fails if the remaining path leaves that directory.
+fn path_key(source: &Path, sources: &[PathBuf], is_file: bool) -> anyhow::Result<String> {
+    let parents = sources.iter().map(|source| source.parent().unwrap_or(Path::new("")));
+    let shared = parents.clone().next().map_or(0, |first| {
+        let mut shared = first.components().count();
+        for parent in parents {
+            shared = first.components().zip(parent.components()).take(shared).take_while(|(a, b)| a == b).count();
+        }
+        shared
+    });
+    let mut key = Vec::new();
+    for component in source.components().skip(shared) {
+        match component {
+            Component::Normal(part) => key.push(part.to_string_lossy()),
+            Component::ParentDir => anyhow::bail!(
+                "Mask set {} is outside the directory the mask sets share, so --on-name-collision path can't name its outputs",
+                source.display(),
+            ),
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+    if is_file && let Some(last) = key.last_mut() {
+        let stem = Path::new(last.as_ref()).file_stem().map(|stem| stem.to_string_lossy().into_owned());
+        if let Some(stem) = stem {
+            *last = stem.into();
+        }
+    }
+    ensure!(!key.is_empty(), "Mask set {} has no name", source.display());
+    Ok(key.join("/"))
+}
+
+/// Today as `YYYY-MM-DD` (UTC).
+fn utc_date() -> String {
+    civil_date(utc_seconds())
+}
+
+/// Now as `YYYY-MM-DDTHH:MM:SS` (UTC).
+fn utc_date_time() -> String {
+    let secs = utc_seconds();
+    let time = secs % 86400;
+    format!("{}T{:02}:{:02}:{:02}", civil_date(secs), time / 3600, time / 60 % 60, time % 60)
+}
+
+fn utc_seconds() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The date `secs` after the Unix epoch as `YYYY-MM-DD`.
+fn civil_date(secs: u64) -> String {
+    // Civil date from days since 1970-01-01, see https://howardhinnant.github.io/date_algorithms.html
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(source: &str, sources: &[&str]) -> anyhow::Result<String> {
+        let sources: Vec<PathBuf> = sources.iter().map(PathBuf::from).collect();
+        path_key(Path::new(source), &sources, false)
+    }
+
+    #[test]
+    fn path_key_is_relative_to_shared_directory() {
+        let sources = ["/tmp/rv/a/hero", "/tmp/rv/b/hero"];
+        assert_eq!(key(sources[0], &sources).unwrap(), "a/hero");
+        assert_eq!(key(sources[1], &sources).unwrap(), "b/hero");
+    }
+
+    #[test]
+    fn path_key_keeps_relative_paths() {
+        let sources = ["a/hero", "./b/hero/"];
+        assert_eq!(key(sources[0], &sources).unwrap(), "a/hero");
+        assert_eq!(key(sources[1], &sources).unwrap(), "b/hero");
+    }
+
+    #[test]
+    fn path_key_of_single_set_is_its_name() {
+        assert_eq!(key("../masks/hero", &["../masks/hero"]).unwrap(), "hero");
+    }
+
+    #[test]
+    fn path_key_drops_file_extension() {
+        let sources = [PathBuf::from("a/hero.png"), PathBuf::from("b/hero.png")];
+        assert_eq!(path_key(&sources[0], &sources, true).unwrap(), "a/hero");
+    }
+
+    #[test]
+    fn path_key_rejects_escaping_paths() {
+        let sources = ["masks/a/hero", "masks/../../b/hero"];
+        assert!(key(sources[1], &sources).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+//! `--max-memory`: estimate what loading the mask sets takes before loading
+//! them, instead of getting killed for running out of memory mid-batch.
+
+use anyhow::Context;
+
+/// Parse a byte count like `512M`, `8G` or `1.5GiB`; suffixes are binary.
+pub fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let number: f64 = number.parse().with_context(|| format!("Invalid size {s:?}"))?;
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => anyhow::bail!("Invalid size {s:?}, expected e.g. 512M or 8G"),
+    };
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+/// `bytes` in the largest binary unit that keeps it at least 1, e.g. `1.5 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,90 @@
+//! Mask sources given as `http(s)://` URLs: the channel files are fetched
+//! concurrently into a local cache directory, which is then loaded like any
+//! other mask directory. Cached files are revalidated with their ETag, so
+//! unchanged masks aren't downloaded again.
+
+use std::path::{Path, PathBuf};
+
+use smix::ChannelMap;
+
+/// Whether a mask source is a URL rather than a local path.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+/// Mask name of a URL: its last non-empty path segment.
+pub fn name(url: &str) -> &str {
+    url.trim_end_matches('/').rsplit('/').next().unwrap_or("result")
+}
+
+/// `<cache dir>/smix/remote/<hash of url>`
+#[cfg(feature = "remote")]
+fn cache_dir(url: &str) -> anyhow::Result<PathBuf> {
+    let base = dirs::cache_dir().ok_or_else(|| anyhow::anyhow!("No cache directory for remote masks"))?;
+    Ok(base.join("smix").join("remote").join(&crate::incremental::hash(&[url.as_bytes()])[..16]))
+}
+
+/// Download the channel files of the mask at `url` and return the local directory.
+#[cfg(feature = "remote")]
+pub fn fetch(url: &str, map: &ChannelMap) -> anyhow::Result<PathBuf> {
+    let dir = cache_dir(url)?;
+    std::fs::create_dir_all(&dir)?;
+    let base = if url.ends_with('/') { url.to_string() } else { format!("{url}/") };
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_global(Some(std::time::Duration::from_secs(60)))
+        .build()
+        .into();
+    std::thread::scope(|scope| {
+        let downloads: Vec<_> = map.files.iter().zip(&map.constants)
+            .filter(|(_, constant)| constant.is_none())
+            .map(|(file, _)| file)
+            .map(|file| {
+                let (agent, dir) = (&agent, &dir);
+                let url = format!("{base}{}", file.to_string_lossy().replace('\\', "/"));
+                scope.spawn(move || fetch_file(agent, &url, &dir.join(file)))
+            })
+            .collect();
+        downloads.into_iter().try_for_each(|download| download.join().expect("download thread panicked"))
+    })?;
+    Ok(dir)
+}
+
+#[cfg(feature = "remote")]
+fn fetch_file(agent: &ureq::Agent, url: &str, path: &Path) -> anyhow::Result<()> {
+    const MAX_SIZE: u64 = 256 * 1024 * 1024;
+    let etag_path = path.with_extension("etag");
+    let etag = path.exists().then(|| std::fs::read_to_string(&etag_path).ok()).flatten();
+    let mut request = agent.get(url);
+    if let Some(etag) = &etag {
+        request = request.header("If-None-Match", etag.trim());
+    }
+    let mut response = request.call()?;
+    match response.status().as_u16() {
+        304 => return Ok(()),
+        200 => {}
+        status => anyhow::bail!("Fetching {url} failed: HTTP {status}"),
+    }
+    let etag = response.headers().get("etag").and_then(|v| v.to_str().ok()).map(str::to_string);
+    let body = response.body_mut().with_config().limit(MAX_SIZE).read_to_vec()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // The old ETag goes first and the new one only follows a complete file,
+    // so an interrupted download is fetched again rather than revalidated
+    if let Err(e) = std::fs::remove_file(&etag_path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        return Err(e.into());
+    }
+    smix::write_atomic(path, &body)?;
+    if let Some(etag) = etag {
+        smix::write_atomic(etag_path, etag.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "remote"))]
+pub fn fetch(_url: &str, _map: &ChannelMap) -> anyhow::Result<PathBuf> {
+    anyhow::bail!("URL mask sources need smix built with the `remote` feature")
+}
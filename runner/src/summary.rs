@@ -0,0 +1,133 @@
+//! `manifest.json` / `manifest.csv`: every file of a batch with the
+//! parameters it was generated with and its checksum, for asset importers.
+
+use std::{fmt::Write as _, path::Path};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// File formats of the summary, each written as `manifest.<ext>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SummaryFormat {
+    Json,
+    Csv,
+}
+
+impl SummaryFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Json => "manifest.json",
+            Self::Csv => "manifest.csv",
+        }
+    }
+}
+
+/// One output file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SummaryEntry {
+    /// Path relative to the output directory
+    pub file: String,
+    /// Name of the mask set
+    pub mask: String,
+    /// Where the mask set was loaded from
+    pub source: String,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    /// Extension of the output format
+    pub format: String,
+    pub weight: [f32; 3],
+    /// SHA-256 of the file content, as printed by `sha256sum`
+    pub sha256: String,
+}
+
+/// Why an output failed [`Summary::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    Missing,
+    /// The file's content no longer matches its recorded hash
+    Changed,
+}
+
+/// Every output of a run, including those `--incremental` left in place.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Summary {
+    /// smix version that wrote the files
+    pub generator: String,
+    /// The `--run-id` directory the files were written into, relative to
+    /// the output directory given; not in the CSV
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    pub outputs: Vec<SummaryEntry>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Self { generator: format!("smix {}", env!("CARGO_PKG_VERSION")), run_id: None, outputs: Vec::new() }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid manifest {}: {e}", path.display()))
+    }
+
+    /// Write the summary into `dir` in `format`, sorted by file name.
+    pub fn save(&mut self, dir: &Path, format: SummaryFormat) -> anyhow::Result<()> {
+        self.outputs.sort_by(|a, b| a.file.cmp(&b.file));
+        let text = match format {
+            SummaryFormat::Json => serde_json::to_string_pretty(self)? + "\n",
+            SummaryFormat::Csv => self.to_csv(),
+        };
+        smix::write_atomic(dir.join(format.file_name()), text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Hash every listed output under `dir` again and return those that are
+    /// missing or differ from the manifest.
+    pub fn verify(&self, dir: &Path) -> Vec<(&SummaryEntry, Problem)> {
+        self.outputs.iter()
+            .filter_map(|entry| match sha256_file(&dir.join(&entry.file)) {
+                Ok(hash) if hash == entry.sha256 => None,
+                Ok(_) => Some((entry, Problem::Changed)),
+                Err(_) => Some((entry, Problem::Missing)),
+            })
+            .collect()
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("file,mask,source,width,height,scale,format,r,g,b,sha256\n");
+        for entry in &self.outputs {
+            let [r, g, b] = entry.weight;
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{r},{g},{b},{}",
+                csv_field(&entry.file),
+                csv_field(&entry.mask),
+                csv_field(&entry.source),
+                entry.width,
+                entry.height,
+                entry.scale,
+                entry.format,
+                entry.sha256,
+            );
+        }
+        csv
+    }
+}
+
+/// Quote `field` if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+/// SHA-256 of the file at `path`, in lowercase hex.
+pub fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,91 @@
+//! Downscaled proxies of mask sets for previews, cached on disk by the hash
+//! of their source files and load settings, so reopening a preview on a large project doesn't
+//! decode and downscale every full-size mask again. Each entry is a mask
+//! directory of 16-bit PNGs plus the size of the original and the warnings
+//! loading it gave.
+
+use std::path::{Path, PathBuf};
+
+use smix::Mask;
+
+use crate::{incremental, Settings};
+
+/// Longest side of a proxy in pixels, unless configured otherwise.
+pub const DEFAULT_SIZE: u32 = 1024;
+
+/// File in each entry holding the original size as `WIDTHxHEIGHT`.
+const SIZE_FILE: &str = "size";
+
+/// File in each entry holding the warnings of loading the original, one per line.
+const WARNINGS_FILE: &str = "warnings";
+
+/// A preview mask and the size of the full mask set it stands for.
+pub struct Proxy {
+    pub mask: Mask,
+    pub full_size: (u32, u32),
+    /// What loading the full mask set warned about
+    pub warnings: Vec<String>,
+}
+
+/// Proxies of one project's mask sets.
+#[derive(Clone, Debug)]
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    size: u32,
+}
+
+impl ThumbnailCache {
+    /// Proxies fitting in `size`x`size`, stored in `dir`.
+    pub fn new(dir: PathBuf, size: u32) -> Self {
+        Self { dir, size }
+    }
+
+    /// `<cache_root>/smix/thumbnails/<hash of project>`, so every project
+    /// directory gets its own cache that can be deleted on its own.
+    pub fn for_project(cache_root: &Path, project: &Path, size: u32) -> Self {
+        let project = std::path::absolute(project).unwrap_or_else(|_| project.to_path_buf());
+        let key = incremental::hash(&[project.as_os_str().as_encoded_bytes()]);
+        Self::new(cache_root.join("smix").join("thumbnails").join(&key[..16]), size)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Longest side of the proxies.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn entry(&self, path: &Path, settings: &Settings) -> anyhow::Result<PathBuf> {
+        let sources = incremental::hash_mask_sources(path, &settings.map)?;
+        let load = incremental::hash_load_settings(settings)?;
+        let key = incremental::hash(&[sources.as_bytes(), load.as_bytes(), &self.size.to_le_bytes()]);
+        Ok(self.dir.join(&key[..32]))
+    }
+
+    /// The cached proxy of the mask set at `path`, if its files and the way
+    /// `settings` load it are unchanged since it was stored.
+    pub fn get(&self, path: &Path, settings: &Settings) -> Option<Proxy> {
+        let entry = self.entry(path, settings).ok()?;
+        let size = std::fs::read_to_string(entry.join(SIZE_FILE)).ok()?;
+        let (width, height) = size.trim().split_once('x')?;
+        let full_size = (width.parse().ok()?, height.parse().ok()?);
+        let mask = Mask::new(&entry).ok()?;
+        let warnings = std::fs::read_to_string(entry.join(WARNINGS_FILE)).ok()?;
+        let warnings = warnings.lines().map(String::from).collect();
+        Some(Proxy { mask, full_size, warnings })
+    }
+
+    /// Store `proxy`, the mask set at `path` loaded with `settings` and
+    /// scaled down from `full_size`, along with the `warnings` loading it gave.
+    pub fn insert(&self, path: &Path, settings: &Settings, proxy: &Mask, full_size: (u32, u32), warnings: &[String]) -> anyhow::Result<()> {
+        let entry = self.entry(path, settings)?;
+        std::fs::create_dir_all(&entry)?;
+        proxy.save_dir(&entry)?;
+        smix::write_atomic(entry.join(WARNINGS_FILE), warnings.join("\n").as_bytes())?;
+        // Written last: an entry without it is incomplete and ignored
+        smix::write_atomic(entry.join(SIZE_FILE), format!("{}x{}", full_size.0, full_size.1).as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! `trim.json`: where each `--trim`med output sat in the untrimmed image,
+//! and where that crop sits in the written file after `--canvas`.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use smix::post::Placement;
+
+/// File name of the report in the output directory.
+pub const REPORT: &str = "trim.json";
+
+/// Crop of one output file, in output pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct TrimEntry {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Size before trimming
+    pub source_width: u32,
+    pub source_height: u32,
+    /// Top-left corner of the crop in the output file; negative where the
+    /// canvas cut it off
+    pub output_x: i64,
+    pub output_y: i64,
+    /// Size of the output file, the canvas size with `--canvas`
+    pub output_width: u32,
+    pub output_height: u32,
+}
+
+/// Output file name -> its crop.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(transparent)]
+pub struct TrimReport {
+    outputs: BTreeMap<String, TrimEntry>,
+}
+
+impl TrimReport {
+    /// Entries of outputs skipped by `--incremental` are kept from the last run.
+    pub fn load(dir: &Path) -> Self {
+        std::fs::read_to_string(dir.join(REPORT))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        smix::write_atomic(dir.join(REPORT), (serde_json::to_string_pretty(self)? + "\n").as_bytes())?;
+        Ok(())
+    }
+
+    /// Record where the crop of `name`, exported at `width`x`height`, was placed.
+    pub fn record(&mut self, name: String, placement: Placement, (width, height): (u32, u32)) {
+        let crop = placement.crop;
+        self.outputs.insert(name, TrimEntry {
+            x: crop.x,
+            y: crop.y,
+            width: crop.width,
+            height: crop.height,
+            source_width: width,
+            source_height: height,
+            output_x: placement.x,
+            output_y: placement.y,
+            output_width: placement.width,
+            output_height: placement.height,
+        });
+    }
+}
@@ -0,0 +1,84 @@
+//! `incremental` runs skip outputs whose inputs hash the same, and rebuild
+//! them when any input changes, the contents of a fallback image included.
+
+use std::{path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use image::{Rgba, RgbaImage};
+use smix::{Fallback, ResizeFilter};
+use smix_runner::{Event, Runner, Settings};
+
+/// A fresh directory under the system temp directory.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("smix-runner-incremental-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_channel(path: &Path, value: u8) {
+    RgbaImage::from_pixel(8, 8, Rgba([value, value, value, 255])).save(path).unwrap();
+}
+
+/// Run `settings` incrementally, returning whether the output was generated
+/// rather than found up to date.
+fn generates(settings: &Settings) -> bool {
+    let generated = Arc::new(Mutex::new(None));
+    let seen = Arc::clone(&generated);
+    let reporter = move |event: Event<'_>| match event {
+        Event::Generated { .. } => *seen.lock().unwrap() = Some(true),
+        Event::UpToDate { .. } => *seen.lock().unwrap() = Some(false),
+        _ => {}
+    };
+    let settings = Settings { incremental: true, ..settings.clone() };
+    let mut runner = Runner::new(settings, Default::default(), reporter);
+    runner.prepare().unwrap();
+    runner.load_masks().unwrap();
+    runner.generate().unwrap();
+    generated.lock().unwrap().expect("one output")
+}
+
+#[test]
+fn changed_export_options_rebuild() {
+    let root = temp_dir("options");
+    let set = root.join("hero");
+    std::fs::create_dir_all(&set).unwrap();
+    for channel in ["r", "g", "b"] {
+        write_channel(&set.join(format!("{channel}.png")), 128);
+    }
+    let mut settings = Settings {
+        weight: [1.0, 0.0, 0.0],
+        mask_directories: vec![set],
+        output: root.join("out"),
+        ..Settings::default()
+    };
+    assert!(generates(&settings));
+    assert!(!generates(&settings));
+    settings.export.filter = ResizeFilter::Mitchell;
+    assert!(generates(&settings));
+    settings.export.white_balance = Some(Default::default());
+    assert!(generates(&settings));
+    assert!(!generates(&settings));
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn changed_fallback_image_rebuilds() {
+    let root = temp_dir("fallback");
+    let set = root.join("hero");
+    std::fs::create_dir_all(&set).unwrap();
+    write_channel(&set.join("r.png"), 255);
+    let fallback = root.join("fallback.png");
+    write_channel(&fallback, 0);
+    let settings = Settings {
+        weight: [0.0, 1.0, 0.0],
+        mask_directories: vec![set],
+        output: root.join("out"),
+        missing_channel: Some(Fallback::Image(fallback.clone())),
+        ..Settings::default()
+    };
+    assert!(generates(&settings));
+    assert!(!generates(&settings));
+    write_channel(&fallback, 255);
+    assert!(generates(&settings));
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,142 @@
+//! Mask sets load the same way whether the whole batch is loaded, one set
+//! is loaded on its own, or a cached proxy stands in for it.
+
+use std::{path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use image::{Rgba, RgbaImage};
+use smix::Fallback;
+use smix_runner::{thumbnails::ThumbnailCache, Event, Runner, Settings};
+
+/// A fresh directory under the system temp directory.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("smix-runner-load-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_channel(path: &Path, value: u8) {
+    RgbaImage::from_pixel(8, 8, Rgba([value, value, value, 255])).save(path).unwrap();
+}
+
+/// A reporter keeping the warnings it was given.
+fn warnings() -> (Arc<Mutex<Vec<String>>>, impl Fn(Event<'_>) + Send + Sync + 'static) {
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let kept = Arc::clone(&warnings);
+    (warnings, move |event: Event<'_>| {
+        if let Event::Warning(warning) = event {
+            kept.lock().unwrap().push(warning);
+        }
+    })
+}
+
+#[test]
+fn single_set_uses_the_channel_map() {
+    let dir = temp_dir("map");
+    write_channel(&dir.join("body.png"), 255);
+    write_channel(&dir.join("trim.png"), 0);
+    let settings = Settings {
+        map: "r=body.png,g=trim.png".parse().unwrap(),
+        missing_channel: Some(Fallback::Black),
+        ..Settings::default()
+    };
+    let (warnings, reporter) = warnings();
+    let mask = Runner::load_mask_set(&settings, &dir, reporter).unwrap();
+    assert_eq!(mask.dimensions(), (8, 8));
+    assert_eq!(mask.generate(&[1.0, 0.0, 0.0]).get_rgba().get_pixel(0, 0).0, [255, 255, 255, 255]);
+    assert_eq!(mask.generate(&[0.0, 1.0, 0.0]).get_rgba().get_pixel(0, 0).0, [0, 0, 0, 255]);
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1, "{warnings:?}");
+    assert!(warnings[0].contains("b.png is missing, using black"), "{warnings:?}");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cached_proxies_repeat_the_load_warnings() {
+    let root = temp_dir("proxy");
+    let set = root.join("hero");
+    std::fs::create_dir_all(&set).unwrap();
+    write_channel(&set.join("r.png"), 255);
+    write_channel(&set.join("g.png"), 128);
+    let load = || {
+        let settings = Settings {
+            mask_directories: vec![set.clone()],
+            missing_channel: Some(Fallback::Black),
+            ..Settings::default()
+        };
+        let (warnings, reporter) = warnings();
+        let mut runner = Runner::new(settings, Default::default(), reporter)
+            .with_thumbnails(ThumbnailCache::new(root.join("cache"), 4));
+        runner.load_masks().unwrap();
+        let loaded = runner.into_loaded();
+        assert_eq!(loaded.full_sizes["hero"], (8, 8));
+        assert_eq!(loaded.masks["hero"].dimensions(), (4, 4));
+        warnings.lock().unwrap().clone()
+    };
+    let first = load();
+    assert_eq!(first.len(), 1, "{first:?}");
+    assert_eq!(load(), first);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn changing_the_fallback_misses_the_proxy_cache() {
+    let root = temp_dir("proxy-fallback");
+    let set = root.join("hero");
+    std::fs::create_dir_all(&set).unwrap();
+    write_channel(&set.join("r.png"), 255);
+    let white = root.join("white.png");
+    write_channel(&white, 255);
+    let load = |fallback: Fallback| {
+        let settings = Settings {
+            mask_directories: vec![set.clone()],
+            missing_channel: Some(fallback),
+            ..Settings::default()
+        };
+        let (warnings, reporter) = warnings();
+        let mut runner = Runner::new(settings, Default::default(), reporter)
+            .with_thumbnails(ThumbnailCache::new(root.join("cache"), 4));
+        runner.load_masks().unwrap();
+        let loaded = runner.into_loaded();
+        let green = loaded.masks["hero"].generate(&[0.0, 1.0, 0.0]).get_rgba().get_pixel(0, 0).0;
+        (green, warnings.lock().unwrap().clone())
+    };
+    let (green, black) = load(Fallback::Black);
+    assert_eq!(green, [0, 0, 0, 255]);
+    assert!(black.iter().all(|warning| warning.contains("using black")), "{black:?}");
+    let (green, image) = load(Fallback::Image(white.clone()));
+    assert_eq!(green, [255, 255, 255, 255]);
+    assert!(image.iter().all(|warning| warning.contains("white.png")), "{image:?}");
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn memory_check_reads_psd_headers_and_reports_the_rest() {
+    let root = temp_dir("memory");
+    let psd = root.join("hero.psd");
+    let mut header = b"8BPS\0\x01\0\0\0\0\0\0\0\x03".to_vec();
+    header.extend(100u32.to_be_bytes());
+    header.extend(200u32.to_be_bytes());
+    std::fs::write(&psd, header).unwrap();
+    let archive = root.join("villain.zip");
+    std::fs::write(&archive, b"not a zip").unwrap();
+
+    let settings = Settings {
+        mask_directories: vec![psd.clone()],
+        max_memory: Some(1000),
+        ..Settings::default()
+    };
+    let error = Runner::new(settings, Default::default(), |_: Event<'_>| {}).load_masks().unwrap_err();
+    assert!(error.to_string().contains("more than --max-memory"), "{error}");
+
+    let settings = Settings {
+        mask_directories: vec![archive.clone()],
+        max_memory: Some(1000),
+        ..Settings::default()
+    };
+    let (warnings, reporter) = warnings();
+    Runner::new(settings, Default::default(), reporter).load_masks_lenient();
+    let warnings = warnings.lock().unwrap();
+    assert!(warnings[0].starts_with(&format!("Can't estimate the memory of {}", archive.display())), "{warnings:?}");
+    std::fs::remove_dir_all(root).unwrap();
+}
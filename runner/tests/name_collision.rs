@@ -0,0 +1,78 @@
+//! Outputs of mask sets named by `NameCollision::Path` stay inside the
+//! output directory, however the sets were given.
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+use smix_runner::{NameCollision, Runner, Settings};
+
+/// A fresh directory under the system temp directory.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("smix-runner-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_mask_set(dir: &Path) {
+    std::fs::create_dir_all(dir).unwrap();
+    for channel in ["r", "g", "b"] {
+        RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255])).save(dir.join(format!("{channel}.png"))).unwrap();
+    }
+}
+
+/// Every file below `dir`, relative to it.
+fn files(dir: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(next) = pending.pop() {
+        for entry in std::fs::read_dir(next).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path.strip_prefix(dir).unwrap().to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn run(mask_directories: Vec<PathBuf>, output: PathBuf) -> anyhow::Result<()> {
+    let settings = Settings {
+        weight: [1.0, 0.0, 0.0],
+        mask_directories,
+        output,
+        name_collision: NameCollision::Path,
+        ..Settings::default()
+    };
+    let mut runner = Runner::new(settings, Default::default(), |_: smix_runner::Event<'_>| {});
+    runner.prepare()?;
+    runner.load_masks()?;
+    runner.generate()
+}
+
+#[test]
+fn absolute_sources_write_inside_output() {
+    let root = temp_dir("absolute");
+    write_mask_set(&root.join("a/hero"));
+    write_mask_set(&root.join("b/hero"));
+    let output = root.join("out");
+    run(vec![root.join("a/hero"), root.join("b/hero")], output.clone()).unwrap();
+    assert_eq!(files(&output), ["a/hero_4x4.png", "b/hero_4x4.png"]);
+    assert_eq!(files(&root.join("a")), ["hero/b.png", "hero/g.png", "hero/r.png"]);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn parent_relative_sources_write_inside_output() {
+    let root = temp_dir("parent");
+    write_mask_set(&root.join("a/hero"));
+    write_mask_set(&root.join("b/hero"));
+    let output = root.join("work/out");
+    let sources = vec![root.join("work/../a/hero"), root.join("work/../b/hero")];
+    run(sources, output.clone()).unwrap();
+    assert_eq!(files(&output), ["a/hero_4x4.png", "b/hero_4x4.png"]);
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,31 @@
+//! Times decoding the PNGs given as arguments with the `image` crate and
+//! with the backend the features select.
+//!
+//! `cargo run --release --example decode_bench --features fast-png -- files...`
+
+use std::time::{Duration, Instant};
+
+use smix::backend::{self, ImageBackend, ImageCrate};
+
+const ROUNDS: u32 = 10;
+
+fn time(backend: &dyn ImageBackend, files: &[Vec<u8>]) -> anyhow::Result<Duration> {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for bytes in files {
+            backend.decode(bytes, Some(image::ImageFormat::Png))?;
+        }
+    }
+    Ok(start.elapsed() / ROUNDS)
+}
+
+fn main() -> anyhow::Result<()> {
+    let files = std::env::args().skip(1).map(std::fs::read).collect::<Result<Vec<_>, _>>()?;
+    anyhow::ensure!(!files.is_empty(), "Usage: decode_bench <png>...");
+
+    let baseline = time(&ImageCrate, &files)?;
+    let current = time(backend::current(), &files)?;
+    println!("image:    {baseline:?} per round");
+    println!("{:<9} {current:?} per round ({:.2}x)", format!("{}:", backend::current().name()), baseline.as_secs_f64() / current.as_secs_f64());
+    Ok(())
+}
@@ -0,0 +1,60 @@
+//! Times resizing an image to 8k with `imageops::resize` and with
+//! `resample::resize` on one thread and on all of them, checking the results
+//! are identical. Uses a generated 4096x4096 image unless one is given.
+//!
+//! With the `fast_image_resize` feature `resample::resize` runs on that crate
+//! instead, and the largest difference from `imageops` is printed instead.
+//!
+//! `cargo run --release --example resize_bench [--features fast_image_resize] -- [image] [filter]`
+
+use std::time::{Duration, Instant};
+
+use image::{imageops::{self, FilterType}, RgbaImage};
+use smix::resample;
+
+const ROUNDS: u32 = 3;
+const TARGET: (u32, u32) = (7680, 4320);
+
+fn time(resize: impl Fn() -> RgbaImage) -> (Duration, RgbaImage) {
+    let start = Instant::now();
+    let mut out = resize();
+    for _ in 1..ROUNDS {
+        out = resize();
+    }
+    (start.elapsed() / ROUNDS, out)
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let img = match args.next() {
+        Some(path) => image::open(path)?.into_rgba8(),
+        None => RgbaImage::from_fn(4096, 4096, |x, y| image::Rgba([(x % 256) as u8, (y % 256) as u8, ((x ^ y) % 256) as u8, 255])),
+    };
+    let filter = match args.next().as_deref() {
+        None | Some("lanczos3") => FilterType::Lanczos3,
+        Some("nearest") => FilterType::Nearest,
+        Some("triangle") => FilterType::Triangle,
+        Some("catmullrom") => FilterType::CatmullRom,
+        Some("gaussian") => FilterType::Gaussian,
+        Some(other) => anyhow::bail!("Unknown filter {other}"),
+    };
+    let (width, height) = TARGET;
+
+    let (baseline, expected) = time(|| imageops::resize(&img, width, height, filter));
+    println!("imageops:           {baseline:?}");
+    let mut jobs = vec![1, rayon::current_num_threads()];
+    jobs.dedup();
+    for jobs in jobs {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+        let (elapsed, out) = pool.install(|| time(|| resample::resize(&img, width, height, filter)));
+        let speedup = baseline.as_secs_f64() / elapsed.as_secs_f64();
+        if cfg!(feature = "fast_image_resize") {
+            let difference = out.as_raw().iter().zip(expected.as_raw()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+            println!("fast_image_resize, {jobs:>2} jobs:  {elapsed:?} ({speedup:.2}x, differs by up to {difference})");
+        } else {
+            anyhow::ensure!(out == expected, "resample::resize differs from imageops::resize");
+            println!("resample, {jobs:>2} jobs:  {elapsed:?} ({speedup:.2}x)");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+//! Tonal and color adjustments of a whole mix, made on the full precision
+//! image before it's resized, so 16-bit and half-float exports get them too.
+
+use std::{borrow::Cow, fmt};
+
+use image::Rgba32FImage;
+
+use crate::{linear_to_srgb, srgb_to_linear, ExportOptions, GeneratedImage};
+
+/// Histogram bins of [`GeneratedImage::equalize`]; finer than 8 bits so
+/// 16-bit mixes keep their gradations.
+const BINS: usize = 4096;
+
+/// What [`GeneratedImage::equalize`] spreads over the full range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Equalize {
+    /// The luminance, scaling RGB along with it; keeps hues
+    #[default]
+    Luminance,
+    /// R, G and B each on their own; stronger, but shifts hues
+    PerChannel,
+}
+
+impl fmt::Display for Equalize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Luminance => "luminance",
+            Self::PerChannel => "per-channel",
+        })
+    }
+}
+
+/// Color temperature of the white point [`WhiteBalance`] leaves as it is.
+pub const NEUTRAL_TEMPERATURE: f32 = 6500.0;
+
+/// How far a tint of ±1 moves the green gain.
+const TINT_STRENGTH: f32 = 0.3;
+
+/// Warming or cooling of a mix as if it were lit by a light of another color
+/// temperature, plus a tint across the green-magenta axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WhiteBalance {
+    /// Kelvin of the light: below [`NEUTRAL_TEMPERATURE`] warms, above cools
+    pub temperature: f32,
+    /// -1~1: negative toward green, positive toward magenta
+    pub tint: f32,
+}
+
+impl Default for WhiteBalance {
+    fn default() -> Self {
+        Self { temperature: NEUTRAL_TEMPERATURE, tint: 0.0 }
+    }
+}
+
+impl WhiteBalance {
+    pub fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Linear light RGB gains, scaled to keep the luminance of white.
+    fn gains(&self) -> [f32; 3] {
+        let light = blackbody(self.temperature);
+        let neutral = blackbody(NEUTRAL_TEMPERATURE);
+        let mut gains = [0, 1, 2].map(|c| light[c] / neutral[c]);
+        gains[1] *= 1.0 - self.tint * TINT_STRENGTH;
+        let y = 0.2126 * gains[0] + 0.7152 * gains[1] + 0.0722 * gains[2];
+        gains.map(|gain| gain / y)
+    }
+}
+
+/// Linear light RGB of a black body at `kelvin`, after Tanner Helland's fit
+/// of the blackbody color table; good enough for 1000~40000 K.
+fn blackbody(kelvin: f32) -> [f32; 3] {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let red = if t <= 66.0 { 255.0 } else { 329.699 * (t - 60.0).powf(-0.133_204_76) };
+    let green = if t <= 66.0 { 99.470_8 * t.ln() - 161.119_57 } else { 288.122_16 * (t - 60.0).powf(-0.075_514_85) };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    // A floor keeps the gains finite where the fit reaches 0
+    [red, green, blue].map(|v| srgb_to_linear(v / 255.0).max(1e-3))
+}
+
+/// Rec. 709 luminance of sRGB-encoded components.
+fn luminance([r, g, b]: [f32; 3]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// `rgb` with its HSV saturation changed by `f`, keeping hue and value.
+fn map_saturation(rgb: &mut [f32], f: impl Fn(f32) -> f32) {
+    let max = rgb.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let min = rgb.iter().copied().fold(f32::INFINITY, f32::min);
+    if max <= 0.0 || max == min {
+        return;
+    }
+    let saturation = (max - min) / max;
+    let scale = f(saturation).clamp(0.0, 1.0) / saturation;
+    for c in rgb {
+        *c = max - (max - *c) * scale;
+    }
+}
+
+fn bin(v: f32) -> usize {
+    (v.clamp(0.0, 1.0) * (BINS - 1) as f32).round() as usize
+}
+
+/// The equalizing map of `values`: each bin to the share of values at or
+/// below it, stretched so the darkest present bin maps to 0. `None` if all
+/// values fall into one bin, which there's nothing to spread.
+fn equalizer(values: impl Iterator<Item = f32>) -> Option<Vec<f32>> {
+    let mut histogram = vec![0u64; BINS];
+    for v in values {
+        histogram[bin(v)] += 1;
+    }
+    let total: u64 = histogram.iter().sum();
+    let first = histogram.iter().copied().find(|&count| count > 0)?;
+    if first == total {
+        return None;
+    }
+    let mut seen = 0;
+    Some(histogram.iter().map(|&count| {
+        seen += count;
+        seen.saturating_sub(first) as f32 / (total - first) as f32
+    }).collect())
+}
+
+impl GeneratedImage {
+    /// This image with its histogram equalized by `mode`, spreading crowded
+    /// tones over the full range to rescue low-contrast mixes. Only visible
+    /// pixels count towards the histogram; alpha is kept.
+    pub fn equalize(&self, mode: Equalize) -> GeneratedImage {
+        let src = self.get_rgba32f();
+        let visible = || src.pixels().filter(|p| p[3] > 0.0);
+        let mut img: Rgba32FImage = src.clone();
+        match mode {
+            Equalize::Luminance => {
+                let Some(map) = equalizer(visible().map(|p| luminance([p[0], p[1], p[2]]))) else { return self.clone() };
+                for p in img.pixels_mut() {
+                    let y = luminance([p[0], p[1], p[2]]);
+                    let target = map[bin(y)];
+                    for c in &mut p.0[..3] {
+                        *c = if y > 0.0 { (*c * target / y).clamp(0.0, 1.0) } else { target };
+                    }
+                }
+            }
+            Equalize::PerChannel => {
+                for c in 0..3 {
+                    let Some(map) = equalizer(visible().map(|p| p[c])) else { continue };
+                    for p in img.pixels_mut() {
+                        p.0[c] = map[bin(p.0[c])];
+                    }
+                }
+            }
+        }
+        GeneratedImage { source_bit_depth: self.source_bit_depth, ..GeneratedImage::new(img) }
+    }
+
+    /// This image with the saturation of every pixel changed by `f`, from
+    /// and to HSV saturation in 0~1.
+    fn map_saturation(&self, f: impl Fn(f32) -> f32) -> GeneratedImage {
+        let mut img = self.get_rgba32f().clone();
+        for p in img.pixels_mut() {
+            map_saturation(&mut p.0[..3], &f);
+        }
+        GeneratedImage { source_bit_depth: self.source_bit_depth, ..GeneratedImage::new(img) }
+    }
+
+    /// This image with the saturation boosted by `amount` (-1~1), weighted
+    /// toward unsaturated pixels: the saturation of gray-ish pixels grows by
+    /// up to `amount` times itself, already saturated ones hardly change.
+    /// Negative amounts mute the same way.
+    pub fn vibrance(&self, amount: f32) -> GeneratedImage {
+        self.map_saturation(|s| s * (1.0 + amount * (1.0 - s)))
+    }
+
+    /// This image with no pixel more saturated than `max` (0~1), lowering the
+    /// saturation of those above it while keeping hue and value.
+    pub fn clamp_saturation(&self, max: f32) -> GeneratedImage {
+        self.map_saturation(|s| s.min(max))
+    }
+
+    /// This image white balanced by `balance`, in linear light; alpha is kept.
+    pub fn white_balance(&self, balance: WhiteBalance) -> GeneratedImage {
+        let gains = balance.gains();
+        let mut img = self.get_rgba32f().clone();
+        for p in img.pixels_mut() {
+            for (c, gain) in p.0[..3].iter_mut().zip(gains) {
+                *c = linear_to_srgb((srgb_to_linear(*c) * gain).clamp(0.0, 1.0));
+            }
+        }
+        GeneratedImage { source_bit_depth: self.source_bit_depth, ..GeneratedImage::new(img) }
+    }
+
+    /// This image with the adjustments of `options` made in order; borrowed
+    /// if there's nothing to do. The color space conversion waits until
+    /// after resizing, which works on sRGB.
+    pub(crate) fn adjusted(&self, options: &ExportOptions) -> Cow<'_, GeneratedImage> {
+        let mut img = Cow::Borrowed(self);
+        if let Some(mode) = options.equalize {
+            img = Cow::Owned(img.equalize(mode));
+        }
+        if let Some(balance) = options.white_balance.filter(|balance| !balance.is_neutral()) {
+            img = Cow::Owned(img.white_balance(balance));
+        }
+        if let Some(amount) = options.vibrance {
+            img = Cow::Owned(img.vibrance(amount));
+        }
+        if let Some(max) = options.max_saturation {
+            img = Cow::Owned(img.clamp_saturation(max));
+        }
+        img
+    }
+}
@@ -0,0 +1,172 @@
+//! Animated (multi-frame) masks: APNG, GIF and animated WebP.
+//!
+//! Every frame is mixed as its own [`Mask`] with the same weights, and the
+//! result is written back out as an animated GIF.
+
+use std::{fmt, fs::File, io::{BufReader, Write}, path::{Path, PathBuf}};
+
+use image::{
+    codecs::{gif::{GifDecoder, GifEncoder, Repeat}, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, Delay, Frame, Rgba32FImage,
+};
+
+use crate::{decode, write_atomic, GeneratedImage, Levels, Mask, ResizeFilter};
+
+/// Extensions probed for animated mask files, in order.
+pub const EXTENSIONS: [&str; 3] = ["gif", "webp", "png"];
+
+#[derive(Clone)]
+pub struct AnimatedMask {
+    frames: Vec<(Mask, Delay)>,
+}
+
+impl fmt::Debug for AnimatedMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnimatedMask")
+            .field("dimensions", &self.frames.first().map(|(mask, _)| mask.dimensions()))
+            .field("frames", &self.frames.len())
+            .finish()
+    }
+}
+
+impl AnimatedMask {
+    /// The `[r, g, b]` files of `dir` if they form an animated mask set,
+    /// i.e. `r.gif`/`r.webp` exist or `r.png` is an APNG.
+    pub fn detect<P: AsRef<Path>>(dir: P) -> Option<[PathBuf; 3]> {
+        let dir = dir.as_ref();
+        EXTENSIONS.iter().find_map(|ext| {
+            let files = ["r", "g", "b"].map(|c| dir.join(format!("{c}.{ext}")));
+            let animated = match *ext {
+                "png" => is_apng(&files[0]),
+                _ => files[0].is_file(),
+            };
+            animated.then_some(files)
+        })
+    }
+
+    pub fn new<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let Some([r, g, b]) = Self::detect(path) else {
+            anyhow::bail!("{} contains no animated masks", path.display());
+        };
+        let (r, g, b) = (read_frames(&r)?, read_frames(&g)?, read_frames(&b)?);
+        anyhow::ensure!(
+            r.len() == g.len() && r.len() == b.len(),
+            "Animated masks have different frame counts ({}, {}, {})", r.len(), g.len(), b.len()
+        );
+
+        let frames = r.into_iter().zip(g).zip(b)
+            .map(|(((r, delay), (g, _)), (b, _))| Ok((Mask::from_images([r, g, b])?, delay)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        anyhow::ensure!(!frames.is_empty(), "Animated masks have no frames");
+        Ok(Self { frames })
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames[0].0.dimensions()
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Keep only the first frame, e.g. for a still preview.
+    pub fn into_first(self) -> Mask {
+        self.frames.into_iter().next().map(|(mask, _)| mask).expect("at least one frame")
+    }
+
+    /// [`Mask::apply_levels`] on every frame.
+    pub fn apply_levels(&mut self, levels: &Levels) {
+        for (mask, _) in &mut self.frames {
+            mask.apply_levels(levels);
+        }
+    }
+
+    pub fn generate(&self, weight: &[f32; 3]) -> GeneratedAnimation {
+        GeneratedAnimation {
+            frames: self.frames.iter()
+                .map(|(mask, delay)| (mask.generate(weight), *delay))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GeneratedAnimation {
+    frames: Vec<(GeneratedImage, Delay)>,
+}
+
+impl fmt::Debug for GeneratedAnimation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeneratedAnimation")
+            .field("dimensions", &self.frames.first().map(|(image, _)| image.dimensions()))
+            .field("frames", &self.frames.len())
+            .finish()
+    }
+}
+
+impl GeneratedAnimation {
+    pub fn frames(&self) -> &[(GeneratedImage, Delay)] {
+        &self.frames
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames[0].0.dimensions()
+    }
+
+    /// Write a looping GIF, resizing each frame to `nwidth`x`nheight`. The
+    /// file is replaced atomically, see [`write_atomic`].
+    pub fn save_gif<P: AsRef<Path>>(&self, path: P, nwidth: u32, nheight: u32, filter: impl Into<ResizeFilter>) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        self.write_gif(&mut buf, nwidth, nheight, filter)?;
+        Ok(write_atomic(path, &buf)?)
+    }
+
+    /// Same as [`GeneratedAnimation::save_gif`], into any writer.
+    pub fn write_gif<W: Write>(&self, writer: W, nwidth: u32, nheight: u32, filter: impl Into<ResizeFilter>) -> anyhow::Result<()> {
+        let filter = filter.into();
+        let mut encoder = GifEncoder::new(writer);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(self.frames.iter().map(|(img, delay)| {
+            let buffer = img.resized(nwidth, nheight, filter).into_owned();
+            Frame::from_parts(buffer, 0, 0, *delay)
+        }))?;
+        Ok(())
+    }
+}
+
+fn is_apng(path: &Path) -> bool {
+    File::open(path)
+        .ok()
+        .and_then(|file| PngDecoder::new(BufReader::new(file)).ok())
+        .and_then(|decoder| decoder.is_apng().ok())
+        .unwrap_or(false)
+}
+
+fn read_frames(path: &Path) -> anyhow::Result<Vec<(Rgba32FImage, Delay)>> {
+    let reader = BufReader::new(File::open(path)?);
+    let frames = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gif") => {
+            let mut decoder = GifDecoder::new(reader)?;
+            decode::limit(&mut decoder)?;
+            decoder.into_frames()
+        }
+        Some("webp") => {
+            let mut decoder = WebPDecoder::new(reader)?;
+            decode::limit(&mut decoder)?;
+            decoder.into_frames()
+        }
+        _ => {
+            let mut decoder = PngDecoder::new(reader)?;
+            decode::limit(&mut decoder)?;
+            decoder.apng()?.into_frames()
+        }
+    };
+    let frames = frames.collect_frames()?;
+    Ok(frames.into_iter()
+        .map(|frame| {
+            let delay = frame.delay();
+            (image::DynamicImage::ImageRgba8(frame.into_buffer()).into_rgba32f(), delay)
+        })
+        .collect())
+}
@@ -0,0 +1,134 @@
+//! Masks read straight out of zip and tar archives, without extracting.
+//!
+//! An archive mask source is written `set.zip` or `set.zip#inner/prefix`; the
+//! channel files are looked up under the prefix inside the archive.
+
+use std::{fs::File, io::Read, path::{Path, PathBuf}};
+
+use image::{ImageFormat, Rgba32FImage};
+
+use crate::{channel_bits, decode, fill_constants, ChannelMap, Mask};
+
+/// Archive container formats, detected by extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    /// gzip-compressed tar, `.tar.gz` or `.tgz`
+    TarGz,
+}
+
+impl ArchiveKind {
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// An archive file plus the directory inside it that holds the channel files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchivePath {
+    pub archive: PathBuf,
+    pub kind: ArchiveKind,
+    /// Directory inside the archive, `/`-separated, empty for the root
+    pub prefix: String,
+}
+
+impl ArchivePath {
+    /// Parse `set.zip` or `set.zip#inner/prefix`; `None` if `path` isn't an archive.
+    pub fn parse(path: &Path) -> Option<Self> {
+        let text = path.to_str()?;
+        let (archive, prefix) = text.split_once('#').unwrap_or((text, ""));
+        let archive = PathBuf::from(archive);
+        let kind = ArchiveKind::detect(&archive)?;
+        Some(Self { archive, kind, prefix: prefix.trim_matches('/').to_string() })
+    }
+
+    /// Last component of the prefix, or the archive name without extensions.
+    pub fn name(&self) -> String {
+        if let Some(last) = self.prefix.rsplit('/').find(|part| !part.is_empty()) {
+            return last.to_string();
+        }
+        let file = self.archive.file_name().map_or("result".into(), |name| name.to_string_lossy());
+        file.split('.').next().unwrap_or("result").to_string()
+    }
+
+    fn entry_name(&self, file: &Path) -> String {
+        let file = file.to_string_lossy().replace('\\', "/");
+        if self.prefix.is_empty() { file } else { format!("{}/{file}", self.prefix) }
+    }
+}
+
+impl Mask {
+    /// Load `r.png`, `g.png`, `b.png` stored under `inner_prefix` in a zip archive.
+    pub fn from_zip<P: AsRef<Path>>(path: P, inner_prefix: &str) -> anyhow::Result<Self> {
+        let source = ArchivePath { archive: path.as_ref().into(), kind: ArchiveKind::Zip, prefix: inner_prefix.into() };
+        Self::from_archive(&source, &ChannelMap::default())
+    }
+
+    /// Load the channel files named by `map` from a zip or tar archive.
+    pub fn from_archive(source: &ArchivePath, map: &ChannelMap) -> anyhow::Result<Self> {
+        let names = map.files.clone().map(|file| source.entry_name(&file));
+        let mut data: [Option<Vec<u8>>; 3] = [None, None, None];
+        match source.kind {
+            ArchiveKind::Zip => {
+                let mut zip = zip::ZipArchive::new(File::open(&source.archive)?)?;
+                for (slot, name) in data.iter_mut().zip(&names) {
+                    if let Ok(mut entry) = zip.by_name(name) {
+                        let mut buf = Vec::with_capacity(entry.size() as usize);
+                        entry.read_to_end(&mut buf)?;
+                        *slot = Some(buf);
+                    }
+                }
+            }
+            ArchiveKind::Tar => read_tar(File::open(&source.archive)?, &names, &mut data)?,
+            ArchiveKind::TarGz => read_tar(flate2::read::GzDecoder::new(File::open(&source.archive)?), &names, &mut data)?,
+        }
+        let mut images = [None, None, None];
+        let mut bit_depth = 8;
+        for (((image, bytes), name), constant) in images.iter_mut().zip(data).zip(&names).zip(&map.constants) {
+            if constant.is_some() {
+                continue;
+            }
+            let Some(bytes) = bytes else {
+                anyhow::bail!("{} has no {name}", source.archive.display());
+            };
+            let (decoded, bits) = decode(&bytes, name)?;
+            bit_depth = bit_depth.max(bits);
+            *image = Some(decoded);
+        }
+        Ok(Self::from_images(fill_constants(images, map, &source.archive)?)?.with_bit_depth(bit_depth))
+    }
+}
+
+/// Collect the entries called `names` in one pass over the tar stream.
+fn read_tar<R: Read>(reader: R, names: &[String; 3], data: &mut [Option<Vec<u8>>; 3]) -> anyhow::Result<()> {
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().trim_start_matches("./").to_string();
+        if let Some(i) = names.iter().position(|name| *name == path) {
+            let mut buf = Vec::new();
+            entry.read_to_end(&mut buf)?;
+            data[i] = Some(buf);
+        }
+    }
+    Ok(())
+}
+
+fn decode(bytes: &[u8], name: &str) -> anyhow::Result<(Rgba32FImage, u8)> {
+    let image = match ImageFormat::from_path(name) {
+        Ok(format) => decode::load_from_memory_with_format(bytes, format)?,
+        Err(_) => decode::load_from_memory(bytes)?,
+    };
+    let bits = channel_bits(image.color());
+    Ok((image.into_rgba32f(), bits))
+}
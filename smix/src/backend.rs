@@ -0,0 +1,153 @@
+//! Image file I/O behind [`ImageBackend`], so another codec library can take
+//! over decoding and encoding from `image` on a given target. The backend is
+//! chosen at compile time by features; [`current`] returns it. Pixels are
+//! exchanged as `image` buffers either way.
+
+use std::{io::{BufRead, Cursor, Seek}, path::Path};
+
+use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage};
+
+use crate::decode;
+
+/// Decodes the files masks are loaded from and encodes plain outputs.
+/// Implementations apply [`decode::limits`] or equivalent checks.
+pub trait ImageBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Decode an image file in memory, in `format` or guessed from the content.
+    fn decode(&self, bytes: &[u8], format: Option<ImageFormat>) -> anyhow::Result<DynamicImage>;
+
+    /// Decode the file at `path`, in the format of its extension.
+    fn open(&self, path: &Path) -> anyhow::Result<DynamicImage> {
+        self.decode(&std::fs::read(path)?, ImageFormat::from_path(path).ok())
+    }
+
+    /// Encode 8-bit RGBA as `format`.
+    fn encode(&self, img: &RgbaImage, format: ImageFormat) -> anyhow::Result<Vec<u8>>;
+}
+
+/// The `image` crate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImageCrate;
+
+impl ImageCrate {
+    fn read<R: BufRead + Seek>(mut reader: ImageReader<R>) -> anyhow::Result<DynamicImage> {
+        reader.limits(decode::limits());
+        reader.decode().map_err(decode::map_error)
+    }
+}
+
+impl ImageBackend for ImageCrate {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn decode(&self, bytes: &[u8], format: Option<ImageFormat>) -> anyhow::Result<DynamicImage> {
+        let mut reader = ImageReader::new(Cursor::new(bytes));
+        match format {
+            Some(format) => reader.set_format(format),
+            None => reader = reader.with_guessed_format()?,
+        }
+        Self::read(reader)
+    }
+
+    fn open(&self, path: &Path) -> anyhow::Result<DynamicImage> {
+        Self::read(ImageReader::open(path)?)
+    }
+
+    fn encode(&self, img: &RgbaImage, format: ImageFormat) -> anyhow::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        img.write_to(&mut Cursor::new(&mut buf), format)?;
+        Ok(buf)
+    }
+}
+
+/// PNGs through the `png` crate directly, skipping checksum verification
+/// and metadata chunks; other formats through [`ImageCrate`]. Loading large
+/// mask sets is mostly PNG decoding. Needs the `fast-png` feature.
+///
+/// Without the CRC and Adler-32 checks a corrupt file is only rejected if
+/// it no longer inflates; flipped bits inside the image data decode to
+/// wrong pixels instead of an error. `zune-png` was measured too and was
+/// slower than `image`'s own decoder, checks or not.
+#[cfg(feature = "fast-png")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FastPng;
+
+#[cfg(feature = "fast-png")]
+impl FastPng {
+    fn read<R: BufRead + Seek>(reader: R) -> anyhow::Result<DynamicImage> {
+        use image::ImageBuffer;
+        use png::{BitDepth, ColorType, Transformations};
+
+        let mut decoder = png::Decoder::new_with_limits(reader, png::Limits { bytes: decode::MAX_ALLOC as usize });
+        decoder.ignore_checksums(true);
+        decoder.set_ignore_text_chunk(true);
+        decoder.set_ignore_iccp_chunk(true);
+        decoder.set_transformations(Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        let (width, height) = reader.info().size();
+        if width > decode::MAX_DIMENSION || height > decode::MAX_DIMENSION {
+            return Err(decode::DecodeError::TooLarge.into());
+        }
+        let size = reader.output_buffer_size().ok_or(decode::DecodeError::TooLarge)?;
+        let mut buf = vec![0; size];
+        reader.next_frame(&mut buf)?;
+        let (color, depth) = reader.output_color_type();
+        let image = if depth == BitDepth::Sixteen {
+            let buf: Vec<u16> = buf.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            match color {
+                ColorType::Grayscale => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLuma16),
+                ColorType::GrayscaleAlpha => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLumaA16),
+                ColorType::Rgb => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgb16),
+                ColorType::Rgba => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgba16),
+                ColorType::Indexed => None,
+            }
+        } else {
+            match color {
+                ColorType::Grayscale => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLuma8),
+                ColorType::GrayscaleAlpha => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8),
+                ColorType::Rgb => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgb8),
+                ColorType::Rgba => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgba8),
+                ColorType::Indexed => None,
+            }
+        };
+        image.ok_or_else(|| anyhow::anyhow!("Unexpected {color:?} PNG output at {depth:?}"))
+    }
+}
+
+#[cfg(feature = "fast-png")]
+impl ImageBackend for FastPng {
+    fn name(&self) -> &'static str {
+        "fast-png"
+    }
+
+    fn decode(&self, bytes: &[u8], format: Option<ImageFormat>) -> anyhow::Result<DynamicImage> {
+        let format = format.or_else(|| image::guess_format(bytes).ok());
+        if format == Some(ImageFormat::Png) {
+            Self::read(Cursor::new(bytes))
+        } else {
+            ImageCrate.decode(bytes, format)
+        }
+    }
+
+    fn open(&self, path: &Path) -> anyhow::Result<DynamicImage> {
+        if ImageFormat::from_path(path).ok() == Some(ImageFormat::Png) {
+            Self::read(std::io::BufReader::new(std::fs::File::open(path)?))
+        } else {
+            ImageCrate.open(path)
+        }
+    }
+
+    fn encode(&self, img: &RgbaImage, format: ImageFormat) -> anyhow::Result<Vec<u8>> {
+        ImageCrate.encode(img, format)
+    }
+}
+
+/// The backend the enabled features select.
+pub fn current() -> &'static dyn ImageBackend {
+    #[cfg(feature = "fast-png")]
+    return &FastPng;
+    #[cfg(not(feature = "fast-png"))]
+    &ImageCrate
+}
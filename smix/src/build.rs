@@ -0,0 +1,62 @@
+//! A pure, in-memory entry point for build scripts and asset bakers.
+//!
+//! Encoded channel images go in and the encoded result comes out; nothing
+//! touches the filesystem or prints. The rest of the library doesn't print
+//! either, so build scripts can call any of it and still keep their output clean.
+//!
+//! ```no_run
+//! // build.rs
+//! let channels = ["r.png", "g.png", "b.png"].map(|file| std::fs::read(file).unwrap());
+//! let options = smix::build::BuildOptions { weight: [1.0, 0.15, 0.04], ..Default::default() };
+//! let png = smix::build::generate_to(channels.each_ref().map(Vec::as_slice), &options).unwrap();
+//! let out = std::env::var("OUT_DIR").unwrap();
+//! std::fs::write(format!("{out}/card.png"), png).unwrap();
+//! ```
+
+use crate::{decode, ExportOptions, Mask};
+
+/// Everything [`generate_to`] needs besides the channel images.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildOptions {
+    /// RGB mix weights
+    pub weight: [f32; 3],
+    /// Output size relative to the masks
+    pub scale: f32,
+    /// Format, filter and post-processing of the output
+    pub export: ExportOptions,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self { weight: [1.0, 1.0, 1.0], scale: 1.0, export: ExportOptions::default() }
+    }
+}
+
+impl Mask {
+    /// Decode the R, G, B masks from encoded image files in memory, with the
+    /// format guessed from their content.
+    pub fn from_bytes(channels: [&[u8]; 3]) -> anyhow::Result<Self> {
+        let mut images = Vec::with_capacity(3);
+        for (bytes, channel) in channels.into_iter().zip(["R", "G", "B"]) {
+            let image = decode::load_from_memory(bytes)
+                .map_err(|e| e.context(format!("Decoding the {channel} mask")))?;
+            images.push(image.into_rgba32f());
+        }
+        Self::from_images(images.try_into().expect("three channels"))
+    }
+
+    /// [`Mask::open_packed`] from an encoded image in memory.
+    pub fn from_packed_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::from_packed(&decode::load_from_memory(bytes)?.into_rgba32f()))
+    }
+}
+
+/// Mix the encoded `channels` (R, G, B mask files) and encode the result.
+pub fn generate_to(channels: [&[u8]; 3], options: &BuildOptions) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(options.scale > 0.0, "Scale must be positive, got {}", options.scale);
+    let mask = Mask::from_bytes(channels)?;
+    let (width, height) = mask.dimensions();
+    let nwidth = ((width as f32 * options.scale) as u32).max(1);
+    let nheight = ((height as f32 * options.scale) as u32).max(1);
+    mask.generate(&options.weight).encode(nwidth, nheight, &options.export)
+}
@@ -0,0 +1,144 @@
+//! Canvas resizing: place an image on a larger or smaller transparent canvas
+//! without scaling it, e.g. padded to power-of-two dimensions.
+
+use std::{fmt, str::FromStr};
+
+use image::{imageops, ImageBuffer, Pixel};
+
+use crate::GeneratedImage;
+
+/// Where the image sits on a resized canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+const ANCHOR_NAMES: [(Anchor, &str); 9] = [
+    (Anchor::TopLeft, "top-left"),
+    (Anchor::Top, "top"),
+    (Anchor::TopRight, "top-right"),
+    (Anchor::Left, "left"),
+    (Anchor::Center, "center"),
+    (Anchor::Right, "right"),
+    (Anchor::BottomLeft, "bottom-left"),
+    (Anchor::Bottom, "bottom"),
+    (Anchor::BottomRight, "bottom-right"),
+];
+
+impl Anchor {
+    /// Column and row of the anchor in a 3x3 grid.
+    fn cell(self) -> (i64, i64) {
+        let index = self as i64;
+        (index % 3, index / 3)
+    }
+
+    /// Offset of a `size` image on a `canvas`, per axis; negative when the
+    /// canvas is smaller and the image gets cropped.
+    pub(crate) fn offset(self, (width, height): (u32, u32), (canvas_width, canvas_height): (u32, u32)) -> (i64, i64) {
+        let (h, v) = self.cell();
+        let dx = canvas_width as i64 - width as i64;
+        let dy = canvas_height as i64 - height as i64;
+        (dx * h / 2, dy * v / 2)
+    }
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = ANCHOR_NAMES.iter().find(|(anchor, _)| anchor == self).expect("every anchor is named");
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Anchor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        ANCHOR_NAMES.iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s))
+            .map(|(anchor, _)| *anchor)
+            .ok_or_else(|| {
+                let names: Vec<_> = ANCHOR_NAMES.iter().map(|(_, name)| *name).collect();
+                anyhow::anyhow!("Unknown anchor {s:?}, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// Target canvas of an export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Canvas {
+    /// Exactly `width`x`height`
+    Size(u32, u32),
+    /// Each side rounded up to the next power of two
+    PowerOfTwo,
+}
+
+impl Canvas {
+    /// Canvas size for an image of `size`.
+    pub fn size(self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            Canvas::Size(width, height) => (width, height),
+            Canvas::PowerOfTwo => (width.max(1).next_power_of_two(), height.max(1).next_power_of_two()),
+        }
+    }
+}
+
+impl fmt::Display for Canvas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Canvas::Size(width, height) => write!(f, "{width}x{height}"),
+            Canvas::PowerOfTwo => f.write_str("pot"),
+        }
+    }
+}
+
+impl FromStr for Canvas {
+    type Err = anyhow::Error;
+
+    /// Parse `WxH`, e.g. `512x256`, or `pot`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if s.eq_ignore_ascii_case("pot") {
+            return Ok(Canvas::PowerOfTwo);
+        }
+        let (width, height) = s.split_once(['x', 'X'])
+            .ok_or_else(|| anyhow::anyhow!("Expected a canvas size like 512x256, got {s:?}"))?;
+        let (width, height) = (width.trim().parse()?, height.trim().parse()?);
+        anyhow::ensure!(width > 0 && height > 0, "Canvas must not be empty");
+        Ok(Canvas::Size(width, height))
+    }
+}
+
+/// Place `img` on a transparent `width`x`height` canvas at `anchor`,
+/// cropping whatever doesn't fit.
+pub fn resize_canvas<P: Pixel>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    (width, height): (u32, u32),
+    anchor: Anchor,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut canvas = ImageBuffer::new(width, height);
+    let (x, y) = anchor.offset(img.dimensions(), (width, height));
+    imageops::replace(&mut canvas, img, x, y);
+    canvas
+}
+
+impl GeneratedImage {
+    /// This image on a transparent `width`x`height` canvas at `anchor`,
+    /// without scaling.
+    pub fn with_canvas(&self, width: u32, height: u32, anchor: Anchor) -> GeneratedImage {
+        GeneratedImage::new(resize_canvas(self.get_rgba32f(), (width, height), anchor))
+    }
+
+    /// This image padded to power-of-two dimensions, see [`Canvas::PowerOfTwo`].
+    pub fn padded_to_pot(&self, anchor: Anchor) -> GeneratedImage {
+        let (width, height) = Canvas::PowerOfTwo.size(self.dimensions());
+        self.with_canvas(width, height, anchor)
+    }
+}
@@ -0,0 +1,77 @@
+//! Color vision deficiency simulation, for checking colorways for accessibility.
+//!
+//! Uses the full-severity matrices of Machado, Oliveira and Fernandes (2009),
+//! applied in linear RGB.
+
+use std::fmt;
+
+use crate::{linear_to_srgb, srgb_to_linear, GeneratedImage};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorBlindness {
+    /// No working red cones
+    Protanopia,
+    /// No working green cones
+    Deuteranopia,
+    /// No working blue cones
+    Tritanopia,
+}
+
+impl ColorBlindness {
+    pub const ALL: [ColorBlindness; 3] = [
+        ColorBlindness::Protanopia,
+        ColorBlindness::Deuteranopia,
+        ColorBlindness::Tritanopia,
+    ];
+
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            ColorBlindness::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorBlindness::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorBlindness::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+
+    /// Simulate one sRGB-encoded color.
+    pub fn simulate_rgb(self, rgb: [f32; 3]) -> [f32; 3] {
+        let linear = rgb.map(srgb_to_linear);
+        self.matrix().map(|row| {
+            let v = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+            linear_to_srgb(v.clamp(0.0, 1.0))
+        })
+    }
+}
+
+impl fmt::Display for ColorBlindness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColorBlindness::Protanopia => "protanopia",
+            ColorBlindness::Deuteranopia => "deuteranopia",
+            ColorBlindness::Tritanopia => "tritanopia",
+        })
+    }
+}
+
+impl GeneratedImage {
+    /// How the image looks with the color vision deficiency `kind`; alpha is kept.
+    pub fn simulate(&self, kind: ColorBlindness) -> GeneratedImage {
+        let mut img = self.get_rgba32f().clone();
+        for pixel in img.pixels_mut() {
+            let [r, g, b] = kind.simulate_rgb([pixel[0], pixel[1], pixel[2]]);
+            pixel.0[..3].copy_from_slice(&[r, g, b]);
+        }
+        GeneratedImage::new(img)
+    }
+}
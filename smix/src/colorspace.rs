@@ -0,0 +1,191 @@
+//! Conversion of finished mixes from the sRGB working space to wide-gamut
+//! output spaces, and tagging of the encoded files so viewers know which.
+//!
+//! Mixing, adjustments, resizing and previews all happen in sRGB; exports
+//! are converted just before encoding. [`ColorSpace`] only re-expresses the
+//! same colors with other primaries and transfer curve, so an sRGB red comes
+//! out as a less than full red in Display P3.
+
+use std::fmt;
+
+use image::{DynamicImage, ImageFormat, Rgba32FImage, RgbaImage};
+
+use crate::{f32img_to_u8img, linear_to_srgb, srgb_to_linear, GeneratedImage, OutputFormat};
+
+type Matrix = [[f64; 3]; 3];
+
+/// CIE xy chromaticities of the red, green and blue primaries.
+type Primaries = [[f64; 2]; 3];
+
+const SRGB_PRIMARIES: Primaries = [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]];
+const DISPLAY_P3_PRIMARIES: Primaries = [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060]];
+const REC2020_PRIMARIES: Primaries = [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]];
+
+/// All three spaces share the D65 white point, so no chromatic adaptation
+/// is needed.
+const D65: [f64; 2] = [0.3127, 0.3290];
+
+/// Constants of the BT.709 / BT.2020 transfer curve.
+const BT709_ALPHA: f32 = 1.099_296_8;
+const BT709_BETA: f32 = 0.018_053_97;
+
+/// Color space of exported images.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// The working space, left as is and untagged
+    #[default]
+    Srgb,
+    /// DCI-P3 primaries with the D65 white point and the sRGB curve
+    DisplayP3,
+    /// ITU-R BT.2020 primaries and curve, for SDR wide-gamut pipelines
+    Rec2020,
+}
+
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Srgb => "sRGB",
+            Self::DisplayP3 => "Display P3",
+            Self::Rec2020 => "Rec.2020",
+        })
+    }
+}
+
+impl ColorSpace {
+    fn primaries(self) -> Primaries {
+        match self {
+            Self::Srgb => SRGB_PRIMARIES,
+            Self::DisplayP3 => DISPLAY_P3_PRIMARIES,
+            Self::Rec2020 => REC2020_PRIMARIES,
+        }
+    }
+
+    /// CIE xy chromaticities of the red, green and blue primaries and of the
+    /// white point, for file formats that store them.
+    pub fn chromaticities(self) -> [[f32; 2]; 4] {
+        let [r, g, b] = self.primaries();
+        [r, g, b, D65].map(|xy| xy.map(|v| v as f32))
+    }
+
+    /// The PNG cICP chunk's code points (ITU-T H.273): color primaries,
+    /// transfer characteristics, matrix coefficients (0 for RGB) and full
+    /// range. `None` for sRGB, which untagged files are taken for anyway.
+    pub fn cicp(self) -> Option<[u8; 4]> {
+        match self {
+            Self::Srgb => None,
+            Self::DisplayP3 => Some([12, 13, 0, 1]),
+            Self::Rec2020 => Some([9, 14, 0, 1]),
+        }
+    }
+
+    /// The matrix from linear light sRGB to linear light in this space.
+    fn srgb_matrix(self) -> Matrix {
+        multiply(&invert(&rgb_to_xyz(self.primaries())), &rgb_to_xyz(SRGB_PRIMARIES))
+    }
+
+    /// Encode a linear light component with this space's transfer curve.
+    fn encode(self, v: f32) -> f32 {
+        match self {
+            Self::Srgb | Self::DisplayP3 => linear_to_srgb(v),
+            Self::Rec2020 if v < BT709_BETA => 4.5 * v,
+            Self::Rec2020 => BT709_ALPHA * v.powf(0.45) - (BT709_ALPHA - 1.0),
+        }
+    }
+}
+
+/// Whether files in `format` are tagged with their color space; viewers
+/// take other files for sRGB.
+pub fn can_tag(format: OutputFormat) -> bool {
+    matches!(format, OutputFormat::Image(ImageFormat::Png) | OutputFormat::ExrF16 | OutputFormat::Ktx2 | OutputFormat::Ktx2F16)
+}
+
+/// The matrix from linear RGB with `primaries` to CIE XYZ, scaled so that
+/// RGB white is the D65 white at Y = 1.
+fn rgb_to_xyz(primaries: Primaries) -> Matrix {
+    let xyz = |[x, y]: [f64; 2]| [x / y, 1.0, (1.0 - x - y) / y];
+    let columns = primaries.map(xyz);
+    let m = [0, 1, 2].map(|row| columns.map(|column| column[row]));
+    let white = xyz(D65);
+    let inverse = invert(&m);
+    let scale = [0, 1, 2].map(|row| (0..3).map(|i| inverse[row][i] * white[i]).sum::<f64>());
+    m.map(|row| [0, 1, 2].map(|column| row[column] * scale[column]))
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| (0..3).map(|i| a[row][i] * b[i][column]).sum()))
+}
+
+/// Inverse of `m` by cofactors; the primaries matrices are never singular.
+fn invert(m: &Matrix) -> Matrix {
+    let cofactor = |row: usize, column: usize| {
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant: f64 = (0..3).map(|column| m[0][column] * cofactor(0, column)).sum();
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| cofactor(column, row) / determinant))
+}
+
+/// `png` with a cICP chunk of `code_points` right after its header, before
+/// any chunk it has to precede.
+pub(crate) fn tag_png(png: &[u8], code_points: [u8; 4]) -> anyhow::Result<Vec<u8>> {
+    // 8 bytes of signature, then IHDR: length, type, 13 bytes of data, CRC
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+    anyhow::ensure!(png.len() > IHDR_END && &png[12..16] == b"IHDR", "Not a PNG file");
+    let mut chunk = Vec::with_capacity(16);
+    chunk.extend_from_slice(&4u32.to_be_bytes());
+    chunk.extend_from_slice(b"cICP");
+    chunk.extend_from_slice(&code_points);
+    chunk.extend_from_slice(&crc32fast::hash(&chunk[4..]).to_be_bytes());
+    Ok([&png[..IHDR_END], &chunk, &png[IHDR_END..]].concat())
+}
+
+impl ColorSpace {
+    /// `img` moved from sRGB to linear light with this space's primaries,
+    /// alpha kept. Half-float exports store these values as is.
+    pub(crate) fn linearize(self, img: &mut Rgba32FImage) {
+        let matrix = self.srgb_matrix().map(|row| row.map(|v| v as f32));
+        for p in img.pixels_mut() {
+            let linear = [p[0], p[1], p[2]].map(srgb_to_linear);
+            if self == Self::Srgb {
+                p.0[..3].copy_from_slice(&linear);
+                continue;
+            }
+            for (c, row) in p.0[..3].iter_mut().zip(&matrix) {
+                *c = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+            }
+        }
+    }
+
+    /// `img` converted from sRGB into this space: linearized, moved onto
+    /// the new primaries and encoded with its curve; alpha is kept. sRGB
+    /// colors are inside both wider gamuts, so nothing clips.
+    pub(crate) fn convert(self, img: &mut Rgba32FImage) {
+        if self == Self::Srgb {
+            return;
+        }
+        self.linearize(img);
+        for p in img.pixels_mut() {
+            for c in &mut p.0[..3] {
+                *c = self.encode(c.clamp(0.0, 1.0));
+            }
+        }
+    }
+
+    /// [`ColorSpace::convert`] of an 8-bit image.
+    pub(crate) fn convert_rgba8(self, img: &RgbaImage) -> RgbaImage {
+        let mut converted = DynamicImage::ImageRgba8(img.clone()).into_rgba32f();
+        self.convert(&mut converted);
+        f32img_to_u8img(&converted)
+    }
+}
+
+impl GeneratedImage {
+    /// This image converted from sRGB into `space`: linearized, moved onto
+    /// the new primaries and encoded with its curve; alpha is kept.
+    pub fn convert_color_space(&self, space: ColorSpace) -> GeneratedImage {
+        let mut img = self.get_rgba32f().clone();
+        space.convert(&mut img);
+        GeneratedImage { source_bit_depth: self.source_bit_depth, ..GeneratedImage::new(img) }
+    }
+}
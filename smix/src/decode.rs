@@ -0,0 +1,83 @@
+//! Image decoding with limits, so untrusted files fail with a [`DecodeError`]
+//! instead of exhausting memory or panicking on absurd dimensions. Every
+//! mask loader decodes through here, with the [`backend`].
+
+use std::{fmt, path::Path};
+
+use image::{DynamicImage, ImageDecoder, ImageError, ImageFormat};
+
+use crate::backend;
+
+/// Largest accepted width and height
+pub const MAX_DIMENSION: u32 = 16384;
+/// Largest buffer a decoder may allocate, in bytes
+pub const MAX_ALLOC: u64 = 2 << 30;
+
+/// Why an image was rejected. Returned inside [`anyhow::Error`]; downcast to
+/// tell it apart from malformed files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Width or height is 0
+    Empty,
+    /// Over [`MAX_DIMENSION`] on a side or [`MAX_ALLOC`] bytes decoded
+    TooLarge,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("Image has no pixels"),
+            Self::TooLarge => write!(f, "Image is larger than {MAX_DIMENSION}x{MAX_DIMENSION} or needs over {} MiB to decode", MAX_ALLOC >> 20),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The limits every decoder runs with.
+pub fn limits() -> image::Limits {
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_ALLOC);
+    limits
+}
+
+/// Apply [`limits`] to a decoder that is used directly, e.g. for frames.
+pub fn limit<D: ImageDecoder>(decoder: &mut D) -> anyhow::Result<()> {
+    let (width, height) = decoder.dimensions();
+    if width == 0 || height == 0 {
+        return Err(DecodeError::Empty.into());
+    }
+    decoder.set_limits(limits()).map_err(map_error)
+}
+
+/// Decode the image at `path` with the [`backend`], in the format of its extension.
+pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<DynamicImage> {
+    non_empty(backend::current().open(path.as_ref())?)
+}
+
+/// Decode an image in memory, guessing its format from the content.
+pub fn load_from_memory(bytes: &[u8]) -> anyhow::Result<DynamicImage> {
+    non_empty(backend::current().decode(bytes, None)?)
+}
+
+/// Decode an image in memory known to be in `format`.
+pub fn load_from_memory_with_format(bytes: &[u8], format: ImageFormat) -> anyhow::Result<DynamicImage> {
+    non_empty(backend::current().decode(bytes, Some(format))?)
+}
+
+fn non_empty(image: DynamicImage) -> anyhow::Result<DynamicImage> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(DecodeError::Empty.into());
+    }
+    Ok(image)
+}
+
+/// [`DecodeError::TooLarge`] for limit errors, as is otherwise.
+pub(crate) fn map_error(error: ImageError) -> anyhow::Error {
+    match error {
+        ImageError::Limits(_) => DecodeError::TooLarge.into(),
+        error => error.into(),
+    }
+}
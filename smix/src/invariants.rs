@@ -0,0 +1,50 @@
+//! Invariants of [`Mask::generate`], asserted by the property tests and, with
+//! the `invariants` feature, by debug assertions after every mix.
+
+use crate::{GeneratedImage, Mask};
+
+/// Largest difference two float mixes may have and still count as equal.
+pub const EPSILON: f32 = 1e-5;
+
+/// Whether `weight` keeps every mix of 0~1 masks in 0~1: the magnitudes add
+/// up to at most 1, or some weight is negative and the mix is clamped.
+pub fn bounded(weight: &[f32; 3]) -> bool {
+    weight.iter().any(|&w| w < 0.0) || weight.iter().map(|w| w.abs()).sum::<f32>() <= 1.0 + EPSILON
+}
+
+/// Every component of the float mix is in 0~1.
+pub fn in_unit_range(image: &GeneratedImage) -> bool {
+    image.get_rgba32f().as_raw().iter().all(|c| (-EPSILON..=1.0 + EPSILON).contains(c))
+}
+
+/// The mix has the alpha of the R mask.
+pub fn alpha_preserved(mask: &Mask, image: &GeneratedImage) -> bool {
+    mask.images[0].pixels().zip(image.get_rgba32f().pixels()).all(|(m, p)| m.0[3] == p.0[3])
+}
+
+/// Mixing by `a + b` equals the sum of mixing by `a` and by `b`. Only holds
+/// for non-negative weights, since negative ones clamp.
+pub fn linear(mask: &Mask, a: &[f32; 3], b: &[f32; 3]) -> bool {
+    let sum = [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+    let (mix_a, mix_b, mix_sum) = (mask.generate(a), mask.generate(b), mask.generate(&sum));
+    mix_a.get_rgba32f().pixels()
+        .zip(mix_b.get_rgba32f().pixels())
+        .zip(mix_sum.get_rgba32f().pixels())
+        .all(|((pa, pb), ps)| (0..3).all(|i| (pa.0[i] + pb.0[i] - ps.0[i]).abs() <= EPSILON))
+}
+
+/// Reordering the channel masks and the weights the same way by
+/// `permutation` gives the same mix. Only holds when the masks share their
+/// alpha, since the mix takes it from the R mask.
+pub fn permutation_invariant(mask: &Mask, weight: &[f32; 3], permutation: [usize; 3]) -> bool {
+    let permuted = Mask {
+        images: permutation.map(|i| mask.images[i].clone()),
+        stats: permutation.map(|i| mask.stats[i]),
+        ..mask.clone()
+    };
+    let permuted_weight = permutation.map(|i| weight[i]);
+    let (expected, actual) = (mask.generate(weight), permuted.generate(&permuted_weight));
+    expected.get_rgba32f().as_raw().iter()
+        .zip(actual.get_rgba32f().as_raw())
+        .all(|(e, a)| (e - a).abs() <= EPSILON)
+}
@@ -0,0 +1,154 @@
+//! KTX2 containers of uncompressed RGBA textures, for engines that upload
+//! them as is. One mip level, no supercompression, and a basic data format
+//! descriptor telling the primaries and transfer curve.
+
+use image::RgbaImage;
+
+use crate::colorspace::ColorSpace;
+
+const IDENTIFIER: [u8; 12] = [0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+
+/// Identifier, header and index, followed by the index of the one level.
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_SIZE: usize = 24;
+
+const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+#[cfg(feature = "f16")]
+const VK_FORMAT_R16G16B16A16_SFLOAT: u32 = 97;
+
+// Values of the Khronos Data Format Specification
+const KHR_DF_MODEL_RGBSDA: u8 = 1;
+#[cfg(feature = "f16")]
+const KHR_DF_TRANSFER_LINEAR: u8 = 1;
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+const KHR_DF_TRANSFER_ITU: u8 = 3;
+const KHR_DF_SAMPLE_DATATYPE_LINEAR: u8 = 0x10;
+#[cfg(feature = "f16")]
+const KHR_DF_SAMPLE_DATATYPE_SIGNED: u8 = 0x40;
+#[cfg(feature = "f16")]
+const KHR_DF_SAMPLE_DATATYPE_FLOAT: u8 = 0x80;
+/// Channel ids of R, G, B and A
+const CHANNELS: [u8; 4] = [0, 1, 2, 15];
+
+/// How one texel is stored.
+struct Texel {
+    vk_format: u32,
+    /// Bytes per channel
+    channel_size: u8,
+    transfer: u8,
+    /// Channel type qualifiers of every sample
+    qualifiers: u8,
+    /// Values of 0 and 1, as the sample's bit pattern
+    lower: u32,
+    upper: u32,
+}
+
+/// `KHR_DF_PRIMARIES_*` of `space`.
+fn primaries(space: ColorSpace) -> u8 {
+    match space {
+        ColorSpace::Srgb => 1,
+        ColorSpace::Rec2020 => 4,
+        ColorSpace::DisplayP3 => 10,
+    }
+}
+
+/// Encode 8-bit RGBA encoded with the transfer curve of `space`; the sRGB
+/// curve of sRGB and Display P3 makes it an `_SRGB` format.
+pub fn encode_rgba8(img: &RgbaImage, space: ColorSpace) -> Vec<u8> {
+    let srgb = space != ColorSpace::Rec2020;
+    let texel = Texel {
+        vk_format: if srgb { VK_FORMAT_R8G8B8A8_SRGB } else { VK_FORMAT_R8G8B8A8_UNORM },
+        channel_size: 1,
+        transfer: if srgb { KHR_DF_TRANSFER_SRGB } else { KHR_DF_TRANSFER_ITU },
+        qualifiers: 0,
+        lower: 0,
+        upper: 255,
+    };
+    encode(&texel, img.dimensions(), space, img.as_raw())
+}
+
+/// Encode half-float RGBA samples in linear light, row by row, tagged with
+/// the linear transfer function like half-float EXR.
+#[cfg(feature = "f16")]
+pub fn encode_rgba16f((width, height): (u32, u32), samples: &[half::f16], space: ColorSpace) -> Vec<u8> {
+    let texel = Texel {
+        vk_format: VK_FORMAT_R16G16B16A16_SFLOAT,
+        channel_size: 2,
+        transfer: KHR_DF_TRANSFER_LINEAR,
+        qualifiers: KHR_DF_SAMPLE_DATATYPE_FLOAT | KHR_DF_SAMPLE_DATATYPE_SIGNED,
+        lower: (-1.0f32).to_bits(),
+        upper: 1.0f32.to_bits(),
+    };
+    let data: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+    encode(&texel, (width, height), space, &data)
+}
+
+/// Basic data format descriptor of `texel`, with its total size in front.
+fn data_format_descriptor(texel: &Texel, space: ColorSpace) -> Vec<u8> {
+    let block_size = 24 + 16 * CHANNELS.len() as u32;
+    let mut dfd = Vec::with_capacity(4 + block_size as usize);
+    dfd.extend((4 + block_size).to_le_bytes());
+    // Khronos vendor, basic descriptor type, version 1.3
+    dfd.extend(0u32.to_le_bytes());
+    dfd.extend((2 | (block_size << 16)).to_le_bytes());
+    // Straight alpha
+    dfd.extend([KHR_DF_MODEL_RGBSDA, primaries(space), texel.transfer, 0]);
+    // 1x1x1x1 texel blocks
+    dfd.extend([0; 4]);
+    dfd.extend([texel.channel_size * 4, 0, 0, 0, 0, 0, 0, 0]);
+    let bits = u32::from(texel.channel_size) * 8;
+    for (i, channel) in CHANNELS.into_iter().enumerate() {
+        let mut qualifiers = texel.qualifiers;
+        // Alpha never goes through the sRGB curve
+        if channel == 15 && texel.transfer == KHR_DF_TRANSFER_SRGB {
+            qualifiers |= KHR_DF_SAMPLE_DATATYPE_LINEAR;
+        }
+        let channel_type = u32::from(channel | qualifiers);
+        dfd.extend(((i as u32 * bits) | ((bits - 1) << 16) | (channel_type << 24)).to_le_bytes());
+        dfd.extend(0u32.to_le_bytes());
+        dfd.extend(texel.lower.to_le_bytes());
+        dfd.extend(texel.upper.to_le_bytes());
+    }
+    dfd
+}
+
+/// Key/value data naming the writer, padded to 4 bytes.
+fn key_value_data() -> Vec<u8> {
+    let entry = format!("KTXwriter\0smix {}\0", env!("CARGO_PKG_VERSION"));
+    let mut kvd = Vec::new();
+    kvd.extend((entry.len() as u32).to_le_bytes());
+    kvd.extend(entry.as_bytes());
+    kvd.resize(kvd.len().next_multiple_of(4), 0);
+    kvd
+}
+
+fn encode(texel: &Texel, (width, height): (u32, u32), space: ColorSpace, data: &[u8]) -> Vec<u8> {
+    let dfd = data_format_descriptor(texel, space);
+    let kvd = key_value_data();
+    let dfd_offset = HEADER_SIZE + LEVEL_INDEX_SIZE;
+    let kvd_offset = dfd_offset + dfd.len();
+    // Level data is aligned to the texel size and to 4 bytes
+    let texel_size = usize::from(texel.channel_size) * 4;
+    let level_offset = (kvd_offset + kvd.len()).next_multiple_of(texel_size.max(4));
+
+    let mut buf = Vec::with_capacity(level_offset + data.len());
+    buf.extend(IDENTIFIER);
+    // One 2D level of one layer and face
+    for field in [texel.vk_format, u32::from(texel.channel_size), width, height, 0, 0, 1, 1, 0] {
+        buf.extend(field.to_le_bytes());
+    }
+    for field in [dfd_offset, dfd.len(), kvd_offset, kvd.len()] {
+        buf.extend((field as u32).to_le_bytes());
+    }
+    // No supercompression global data
+    buf.extend([0; 16]);
+    for field in [level_offset, data.len(), data.len()] {
+        buf.extend((field as u64).to_le_bytes());
+    }
+    buf.extend(dfd);
+    buf.extend(kvd);
+    buf.resize(level_offset, 0);
+    buf.extend_from_slice(data);
+    buf
+}
@@ -0,0 +1,58 @@
+//! Masks stored as named layers of one layered PSD file.
+
+use std::{io::Read, path::Path};
+
+use crate::Mask;
+
+/// Layer names used when none are given.
+pub const DEFAULT_LAYERS: [&str; 3] = ["r", "g", "b"];
+
+#[cfg(feature = "psd")]
+impl Mask {
+    /// Load the R, G, B masks from the layers of `path` named `layers`.
+    /// Layers are composited onto the full document canvas.
+    pub fn from_psd<P: AsRef<Path>>(path: P, layers: &[&str; 3]) -> anyhow::Result<Self> {
+        use image::{DynamicImage, RgbaImage};
+
+        let path = path.as_ref();
+        let psd = ::psd::Psd::from_bytes(&std::fs::read(path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid PSD {}: {e}", path.display()))?;
+        let (width, height) = (psd.width(), psd.height());
+        let mut images = Vec::with_capacity(3);
+        for name in layers {
+            let Some(layer) = psd.layer_by_name(name) else {
+                let names: Vec<_> = psd.layers().iter().map(|layer| layer.name()).collect();
+                anyhow::bail!("{} has no layer {name:?}; layers: {}", path.display(), names.join(", "));
+            };
+            let rgba = RgbaImage::from_raw(width, height, layer.rgba())
+                .ok_or_else(|| anyhow::anyhow!("Layer {name:?} doesn't match the {width}x{height} canvas"))?;
+            images.push(DynamicImage::ImageRgba8(rgba).into_rgba32f());
+        }
+        let images: [_; 3] = images.try_into().expect("three layers");
+        Self::from_images(images)
+    }
+}
+
+#[cfg(not(feature = "psd"))]
+impl Mask {
+    pub fn from_psd<P: AsRef<Path>>(_path: P, _layers: &[&str; 3]) -> anyhow::Result<Self> {
+        anyhow::bail!("PSD masks need smix built with the `psd` feature")
+    }
+}
+
+/// Whether `path` looks like a PSD file, by extension.
+pub fn is_psd(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("psd"))
+}
+
+/// Width and height of the PSD at `path`, read from its header.
+pub fn psd_dimensions(path: &Path) -> std::io::Result<(u32, u32)> {
+    // Signature, version, 6 reserved bytes and the channel count come first
+    let mut header = [0; 22];
+    std::fs::File::open(path)?.read_exact(&mut header)?;
+    if &header[..4] != b"8BPS" {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "not a PSD file"));
+    }
+    let field = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().expect("4 bytes"));
+    Ok((field(18), field(14)))
+}
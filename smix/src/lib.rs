@@ -1,107 +1,911 @@
-use std::path::Path;
-
-use image::{imageops, open, Rgba, Rgba32FImage, RgbaImage};
-
-/// RGBA color stored as `[R, G, B, A]` in **0.0~1.0**
-pub type Color = [f32; 4];
-
-pub fn apply_weight(weight: &[f32; 3], value: &[f32; 3]) -> f32 {
-    return weight[0]*value[0] + weight[1]*value[1] + weight[2]*value[2]
-}
-
-/// Mix a single RGBA pixel by 3-channel weight and 3 mask pixels.
-/// 
-/// Alpha channel is **preserved**; only RGB components are modified.
-/// For each channel `i in [0, 1, 2]`:
-/// 1. Extract channel values from the 3 masks into a temporary vector
-/// 2. Compute `pixel[i]` against `weight`
-/// 3. Store result back into `pixel[i]`
-/// 
-/// # Arguments
-/// * `pixel` - In-out RGBA pixel (alpha untouched)
-/// * `weight` - Per-channel weights `[Rw, Gw, Bw]` (sum != 0)
-/// * `mask` - Exactly 3 RGBA samples (alpha ignored) corresponding to R, G, B masks
-/// 
-/// # Exmaples
-/// ```
-/// use smix::mix_pixel;
-/// 
-/// let mut px = [0.0, 0.0, 0.0, 1.0];
-/// let w = [0.8, 0.15, 0.05];
-/// let m = [
-///     [1.0, 0.0, 0.0, 1.0], // red
-///     [0.0, 1.0, 0.0, 1.0], // red
-///     [0.0, 0.0, 1.0, 1.0], // red
-/// ];
-/// mix_pixel(&mut px, &w, &m);
-/// assert_eq!(px, [0.8, 0.15, 0.05, 1.0]);
-/// ```
-pub fn mix_pixel(pixel: &mut Color, weight: &[f32; 3], mask: &[Color; 3]) {
-    for i in 0..3 {
-        pixel[i] = apply_weight(weight, &[mask[0][i], mask[1][i], mask[2][i]]);
-    }
-}
+use std::{borrow::Cow, fmt, io::Cursor, path::{Path, PathBuf}, str::FromStr, sync::atomic::{AtomicU64, Ordering}};
+
+use image::{codecs::png, imageops, ImageBuffer, ImageEncoder, ImageFormat, Pixel, Rgba, Rgba32FImage, RgbaImage};
+use smix_core::quantize;
+
+use crate::cancel::{CancelToken, Cancelled};
+use crate::decode::{open, DecodeError};
+pub(crate) use smix_core::{linear_to_srgb, srgb_to_linear};
+
+pub mod adjust;
+pub mod animation;
+pub mod archive;
+pub mod backend;
+pub mod build;
+pub mod cancel;
+pub mod canvas;
+pub mod colorblind;
+pub mod colorspace;
+pub mod decode;
+pub mod font;
+pub mod invariants;
+pub mod ktx2;
+pub mod layers;
+pub mod lint;
+pub mod montage;
+pub mod naming;
+pub mod plugin;
+pub mod post;
+pub mod procedural;
+pub mod quantize;
+pub mod resample;
+pub mod script;
+pub mod sprite;
+pub mod stats;
+pub mod sweep;
+#[cfg(feature = "test-util")]
+pub mod testing;
+pub mod uv;
+
+pub use resample::ResizeFilter;
+pub use smix_core::{apply_weight, math, mix_pixel, Color, MixSemantics};
 
 pub fn f32img_to_u8img(src: &Rgba32FImage) -> RgbaImage {
     let (w, h) = src.dimensions();
     let mut dst = RgbaImage::new(w, h);
     for (x, y, p) in src.enumerate_pixels() {
         let [r, g, b, a] = p.0;
-        dst.put_pixel(x, y, Rgba([
-            (r.clamp(0.0, 1.0) * 255.0).round() as u8,
-            (g.clamp(0.0, 1.0) * 255.0).round() as u8,
-            (b.clamp(0.0, 1.0) * 255.0).round() as u8,
-            (a.clamp(0.0, 1.0) * 255.0).round() as u8
-        ]));
+        dst.put_pixel(x, y, Rgba([quantize(r), quantize(g), quantize(b), quantize(a)]));
     };
     dst
 }
 
+/// Write `contents` to a temporary file next to `path`, then rename it over
+/// `path`, so watchers and engines see either the old file or the complete
+/// new one, never a truncated image.
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> std::io::Result<()> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let path = path.as_ref();
+    let name = path.file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let tmp = path.with_file_name(format!(".{}.{}-{id}.tmp", name.to_string_lossy(), std::process::id()));
+    std::fs::write(&tmp, contents)
+        .and_then(|()| std::fs::rename(&tmp, path))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&tmp);
+        })
+}
+
+/// Encode `img` in the format of `path`'s extension and [`write_atomic`] it.
+fn save_atomic(img: &RgbaImage, path: &Path) -> anyhow::Result<()> {
+    let buf = backend::current().encode(img, ImageFormat::from_path(path)?)?;
+    Ok(write_atomic(path, &buf)?)
+}
+
+/// Resize a tileable texture without seams: the filter sees the opposite
+/// edge instead of a clamped border, see [`resample::resize_wrapped`].
+pub fn resize_tileable<S: resample::Sample>(
+    img: &ImageBuffer<Rgba<S>, Vec<S>>,
+    nwidth: u32,
+    nheight: u32,
+    filter: impl Into<ResizeFilter>,
+) -> ImageBuffer<Rgba<S>, Vec<S>>
+where
+    Rgba<S>: Pixel<Subpixel = S>,
+{
+    resample::resize_wrapped(img, nwidth, nheight, filter)
+}
+
+/// Which values resampling averages when an export is resized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResizeSpace {
+    /// The sRGB-encoded 8-bit values; fast, but downscaling darkens fine bright detail
+    Srgb,
+    /// Linear light: the full precision image is decoded from sRGB, resized
+    /// and encoded back
+    Linear,
+    /// Linear light when downscaling with a filter that blends pixels, sRGB otherwise
+    #[default]
+    Auto,
+}
+
+impl ResizeSpace {
+    /// Whether resizing `from` to `to` with `filter` happens in linear light.
+    pub fn is_linear(self, from: (u32, u32), to: (u32, u32), filter: ResizeFilter) -> bool {
+        match self {
+            Self::Srgb => false,
+            Self::Linear => true,
+            Self::Auto => filter != ResizeFilter::Nearest && (to.0 < from.0 || to.1 < from.1),
+        }
+    }
+}
+
+/// Which copy of a [`GeneratedImage`] resampling reads in the sRGB [`ResizeSpace`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResamplePrecision {
+    /// The 8-bit image, quantized before resizing
+    #[default]
+    U8,
+    /// The full precision image, quantized after resizing
+    F32,
+}
+
+/// Bits per channel of an export.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BitDepth {
+    /// 16 bits when the masks came from deeper than 8-bit images and the
+    /// format and options allow it, see [`GeneratedImage::exports_16bit`]
+    #[default]
+    Auto,
+    Eight,
+    /// PNG or TIFF, encoded from the float mix
+    Sixteen,
+}
+
+/// File format of an export: anything `image` can encode, JPEG XL or KTX2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    Image(ImageFormat),
+    /// Lossless JPEG XL, needs the `jxl` feature
+    Jxl,
+    /// Half-float OpenEXR, encoded from the float mix in linear light, needs
+    /// the `f16` feature
+    ExrF16,
+    /// Uncompressed 8-bit RGBA KTX2, see [`ktx2`]
+    Ktx2,
+    /// Uncompressed half-float RGBA KTX2, encoded from the float mix in
+    /// linear light, needs the `f16` feature
+    Ktx2F16,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Image(format) => format.extensions_str().first().copied().unwrap_or("png"),
+            Self::Jxl => "jxl",
+            Self::ExrF16 => "exr",
+            Self::Ktx2 | Self::Ktx2F16 => "ktx2",
+        }
+    }
+
+    /// Whether this is encoded from the float mix with half-float channels.
+    fn is_half_float(&self) -> bool {
+        matches!(self, Self::ExrF16 | Self::Ktx2F16)
+    }
+}
+
+impl From<ImageFormat> for OutputFormat {
+    fn from(format: ImageFormat) -> Self {
+        Self::Image(format)
+    }
+}
+
+#[cfg(feature = "optimize")]
+fn optimize_png(data: &[u8], level: u8) -> anyhow::Result<Vec<u8>> {
+    let mut options = oxipng::Options::from_preset(level.min(6));
+    if level > 6 {
+        options.deflate = oxipng::Deflaters::Zopfli { iterations: std::num::NonZeroU8::new(15).unwrap() };
+    }
+    Ok(oxipng::optimize_from_memory(data, &options)?)
+}
+
+#[cfg(not(feature = "optimize"))]
+fn optimize_png(_data: &[u8], _level: u8) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("PNG optimization needs smix built with the `optimize` feature")
+}
+
+#[cfg(feature = "jxl")]
+fn encode_jxl(img: &RgbaImage, options: &ExportOptions) -> anyhow::Result<Vec<u8>> {
+    use zune_core::{bit_depth::BitDepth, colorspace::ColorSpace, options::EncoderOptions};
+
+    let (width, height) = img.dimensions();
+    let mut encoder_options = EncoderOptions::new(width as usize, height as usize, ColorSpace::RGBA, BitDepth::Eight);
+    if let Some(speed) = options.speed {
+        // zune's effort grows with slowness, speed 1..=10 maps onto 127..=1
+        encoder_options = encoder_options.set_effort(127 - (speed.clamp(1, 10) - 1) * 14);
+    }
+    let mut buf = Vec::new();
+    zune_jpegxl::JxlSimpleEncoder::new(img.as_raw(), encoder_options)
+        .encode(&mut buf)
+        .map_err(|e| anyhow::anyhow!("JPEG XL encoding failed: {e:?}"))?;
+    Ok(buf)
+}
+
+#[cfg(not(feature = "jxl"))]
+fn encode_jxl(_img: &RgbaImage, _options: &ExportOptions) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("JPEG XL export needs smix built with the `jxl` feature")
+}
+
+/// Half-float RGBA samples of `img`, row by row.
+#[cfg(feature = "f16")]
+pub fn f32img_to_f16(img: &Rgba32FImage) -> Vec<half::f16> {
+    img.as_raw().iter().copied().map(half::f16::from_f32).collect()
+}
+
+/// Encode `img`, in linear light, as an OpenEXR file with half-float
+/// channels; the chromaticities of `space` are stored unless it's sRGB, the
+/// EXR default.
+#[cfg(feature = "f16")]
+fn encode_exr_f16(img: &Rgba32FImage, space: colorspace::ColorSpace) -> anyhow::Result<Vec<u8>> {
+    use exr::{meta::attribute::Chromaticities, prelude::{Encoding, Image, SpecificChannels, Vec2, WritableImage}};
+
+    let width = img.width() as usize;
+    let samples = f32img_to_f16(img);
+    let channels = SpecificChannels::rgba(|Vec2(x, y)| {
+        let i = (y * width + x) * 4;
+        (samples[i], samples[i + 1], samples[i + 2], samples[i + 3])
+    });
+    let mut image = Image::from_encoded_channels((width, img.height() as usize), Encoding::SMALL_LOSSLESS, channels);
+    if space != colorspace::ColorSpace::Srgb {
+        let [red, green, blue, white] = space.chromaticities().map(|[x, y]| Vec2(x, y));
+        image.attributes.chromaticities = Some(Chromaticities { red, green, blue, white });
+    }
+    let mut buf = Vec::new();
+    image
+        .write()
+        .to_buffered(Cursor::new(&mut buf))?;
+    Ok(buf)
+}
+
+#[cfg(not(feature = "f16"))]
+fn encode_exr_f16(_img: &Rgba32FImage, _space: colorspace::ColorSpace) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("Half-float EXR export needs smix built with the `f16` feature")
+}
+
+#[cfg(feature = "f16")]
+fn encode_ktx2_f16(img: &Rgba32FImage, space: colorspace::ColorSpace) -> anyhow::Result<Vec<u8>> {
+    Ok(ktx2::encode_rgba16f(img.dimensions(), &f32img_to_f16(img), space))
+}
+
+#[cfg(not(feature = "f16"))]
+fn encode_ktx2_f16(_img: &Rgba32FImage, _space: colorspace::ColorSpace) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("Half-float KTX2 export needs smix built with the `f16` feature")
+}
+
+/// Encode `img` as 16-bit RGBA in `format`.
+fn encode_rgba16(img: &Rgba32FImage, format: ImageFormat) -> anyhow::Result<Vec<u8>> {
+    let samples = img.as_raw().iter().map(|c| (c.clamp(0.0, 1.0) * 65535.0).round() as u16).collect();
+    let img = ImageBuffer::<Rgba<u16>, Vec<u16>>::from_raw(img.width(), img.height(), samples).expect("same size");
+    let mut buf = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buf), format)?;
+    Ok(buf)
+}
+
+/// Decode the image at `path` along with the bits per channel it was stored with.
+pub(crate) fn open_with_depth<P: AsRef<Path>>(path: P) -> anyhow::Result<(Rgba32FImage, u8)> {
+    let img = open(path)?;
+    let bits = channel_bits(img.color());
+    Ok((img.into_rgba32f(), bits))
+}
+
+pub(crate) fn channel_bits(color: image::ColorType) -> u8 {
+    (color.bits_per_pixel() / color.channel_count() as u16) as u8
+}
+
+/// Encode 8-bit RGBA in `options.format`.
+fn encode_rgba(img: &RgbaImage, options: &ExportOptions) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let mut buf = Vec::new();
+    match options.format {
+        OutputFormat::Image(ImageFormat::Png) if options.deterministic => {
+            png::PngEncoder::new_with_quality(&mut buf, png::CompressionType::Default, png::FilterType::Adaptive)
+                .write_image(img.as_raw(), width, height, image::ExtendedColorType::Rgba8)?;
+        }
+        #[cfg(feature = "avif")]
+        OutputFormat::Image(ImageFormat::Avif) => {
+            let (speed, quality) = (options.speed.unwrap_or(4), options.quality.unwrap_or(80));
+            image::codecs::avif::AvifEncoder::new_with_speed_quality(&mut buf, speed.clamp(1, 10), quality.clamp(1, 100))
+                // rav1e's tiling follows the thread count
+                .with_num_threads(options.deterministic.then_some(1))
+                .write_image(img.as_raw(), width, height, image::ExtendedColorType::Rgba8)?;
+        }
+        OutputFormat::Image(format) => buf = backend::current().encode(img, format)?,
+        OutputFormat::Jxl => return encode_jxl(img, options),
+        OutputFormat::Ktx2 => buf = ktx2::encode_rgba8(img, options.color_space),
+        OutputFormat::ExrF16 | OutputFormat::Ktx2F16 => anyhow::bail!("Half-float formats are encoded from the float image"),
+    }
+    Ok(buf)
+}
+
+/// Replacement for a channel image missing from a mask directory.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fallback {
+    /// Black RGB with the alpha of the first present channel, so the channel
+    /// contributes nothing to the mix
+    Black,
+    /// An image of the same size as the present channels
+    Image(PathBuf),
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Black => f.write_str("black"),
+            Self::Image(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for Fallback {
+    type Err = std::convert::Infallible;
+
+    /// `black`, or a path to an image
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if s.eq_ignore_ascii_case("black") { Self::Black } else { Self::Image(s.into()) })
+    }
+}
+
+/// Which file in a mask directory holds each of the R, G, B channel masks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMap {
+    pub files: [PathBuf; 3],
+    /// Channels filled with a flat color instead of loaded from `files`
+    pub constants: [Option<[f32; 3]>; 3],
+}
+
+impl Default for ChannelMap {
+    /// `r.png`, `g.png`, `b.png`
+    fn default() -> Self {
+        Self { files: ["r.png", "g.png", "b.png"].map(PathBuf::from), constants: [None; 3] }
+    }
+}
+
+impl fmt::Display for ChannelMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (channel, (file, constant))) in ["r", "g", "b"].iter().zip(self.files.iter().zip(&self.constants)).enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            match constant {
+                Some([r, g, b]) if r == g && g == b => write!(f, "{separator}{channel}={r}")?,
+                Some(color) => {
+                    let [r, g, b] = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                    write!(f, "{separator}{channel}=#{r:02x}{g:02x}{b:02x}")?
+                }
+                None => write!(f, "{separator}{channel}={}", file.display())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChannelMap {
+    /// Extensions tried, in order, when a mapped `.png` file doesn't exist
+    pub const ALTERNATE_EXTENSIONS: [&str; 1] = ["tga"];
+
+    /// The channel files inside `dir`. A missing `r.png` resolves to `r.tga`
+    /// if that exists, so legacy TGA mask sets load unchanged.
+    pub fn paths(&self, dir: &Path) -> [PathBuf; 3] {
+        self.files.clone().map(|file| {
+            let path = dir.join(file);
+            if path.exists() || path.extension().is_none_or(|ext| ext != "png") {
+                return path;
+            }
+            Self::ALTERNATE_EXTENSIONS.iter()
+                .map(|ext| path.with_extension(ext))
+                .find(|alternate| alternate.exists())
+                .unwrap_or(path)
+        })
+    }
+}
+
+impl FromStr for ChannelMap {
+    type Err = anyhow::Error;
+
+    /// `r=body.png,g=trim.png,b=accent.png`; channels left out keep their
+    /// default file. A `#rrggbb` color or a 0~1 value instead of a file fills
+    /// the channel flat, e.g. `b=#808080` or `b=0.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::default();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (channel, file) = entry.split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected CHANNEL=FILE, got `{entry}`"))?;
+            let index = match channel.trim() {
+                "r" => 0,
+                "g" => 1,
+                "b" => 2,
+                other => anyhow::bail!("Unknown channel `{other}`, expected r, g or b"),
+            };
+            let file = file.trim();
+            if let Some(hex) = file.strip_prefix('#') {
+                map.constants[index] = Some(parse_hex_color(hex)?);
+            } else if let Ok(value) = file.parse::<f32>() {
+                anyhow::ensure!((0.0..=1.0).contains(&value), "Constant channel value {value} must be in [0, 1]");
+                map.constants[index] = Some([value; 3]);
+            } else {
+                map.files[index] = file.into();
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// `rrggbb` as 0~1 components.
+fn parse_hex_color(hex: &str) -> anyhow::Result<[f32; 3]> {
+    anyhow::ensure!(hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()), "Expected a #rrggbb color, got `#{hex}`");
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).expect("hex digits") as f32 / 255.0;
+    Ok([component(0), component(2), component(4)])
+}
+
+/// A copy of `img` with its RGB set to `color`, keeping the alpha.
+fn flat_like(img: &Rgba32FImage, color: [f32; 3]) -> Rgba32FImage {
+    let mut flat = img.clone();
+    flat.pixels_mut().for_each(|p| p.0[..3].copy_from_slice(&color));
+    flat
+}
+
+/// Fill the channels `map` has constants for, which are left out of `images`,
+/// with the alpha of the first loaded channel.
+pub(crate) fn fill_constants(mut images: [Option<Rgba32FImage>; 3], map: &ChannelMap, source: &Path) -> anyhow::Result<[Rgba32FImage; 3]> {
+    let Some(present) = images.iter().flatten().next().cloned() else {
+        anyhow::bail!("No channel images in {}; at least one channel must not be constant", source.display());
+    };
+    for (image, constant) in images.iter_mut().zip(&map.constants) {
+        if let Some(color) = constant {
+            *image = Some(flat_like(&present, *color));
+        }
+    }
+    Ok(images.map(|image| image.expect("loaded or constant")))
+}
+
+/// Per-channel correction of the R, G, B masks before mixing,
+/// `value * gain + bias` clamped to 0~1, e.g. for masks authored too dark.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Levels {
+    pub gain: [f32; 3],
+    pub bias: [f32; 3],
+}
+
+impl Default for Levels {
+    /// Gain 1 and bias 0: masks are used as they are
+    fn default() -> Self {
+        Self { gain: [1.0; 3], bias: [0.0; 3] }
+    }
+}
+
+impl Levels {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Parse `r=1.2,b=0.9`; channels left out are `default`.
+    pub fn parse_channels(s: &str, default: f32) -> anyhow::Result<[f32; 3]> {
+        let mut values = [default; 3];
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (channel, value) = entry.split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected CHANNEL=VALUE, got `{entry}`"))?;
+            let index = match channel.trim() {
+                "r" => 0,
+                "g" => 1,
+                "b" => 2,
+                other => anyhow::bail!("Unknown channel `{other}`, expected r, g or b"),
+            };
+            values[index] = value.trim().parse()
+                .map_err(|_| anyhow::anyhow!("Invalid number `{}` for channel {channel}", value.trim()))?;
+        }
+        Ok(values)
+    }
+}
+
+/// Three same-sized channel masks, mixed by [`Mask::generate`].
+///
+/// A `Mask` is immutable after loading and is `Send + Sync`, so one mask can
+/// be shared between threads behind an [`Arc`](std::sync::Arc) and generated
+/// from concurrently:
+///
+/// ```
+/// use std::{sync::Arc, thread};
+/// use image::{Rgba, Rgba32FImage};
+/// use smix::Mask;
+///
+/// let channel = |c: usize| Rgba32FImage::from_fn(8, 8, |_, _| {
+///     let mut p = Rgba([0.0, 0.0, 0.0, 1.0]);
+///     p.0[c] = 1.0;
+///     p
+/// });
+/// let mask = Arc::new(Mask::from_images([channel(0), channel(1), channel(2)]).unwrap());
+///
+/// let workers: Vec<_> = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.2, 0.4, 0.6]]
+///     .into_iter()
+///     .map(|weight| {
+///         let mask = Arc::clone(&mask);
+///         thread::spawn(move || mask.generate(&weight))
+///     })
+///     .collect();
+/// for worker in workers {
+///     assert_eq!(worker.join().unwrap().dimensions(), (8, 8));
+/// }
+/// ```
+#[derive(Clone, Default)]
 pub struct Mask {
     images: [Rgba32FImage; 3],
     width: u32,
     height: u32,
+    bit_depth: u8,
+    /// Of `images`, refreshed whenever they change
+    stats: [stats::MaskStats; 3],
+}
+
+impl fmt::Debug for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mask")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+// Shared masks rely on this; fail the build if a field ever breaks it
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Mask>();
+    assert_send_sync::<GeneratedImage>();
+};
+
+impl TryFrom<[Rgba32FImage; 3]> for Mask {
+    type Error = anyhow::Error;
+
+    fn try_from(images: [Rgba32FImage; 3]) -> anyhow::Result<Self> {
+        Self::from_images(images)
+    }
 }
 
 impl Mask {
     pub fn new<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Self::new_with_mapping(path, &ChannelMap::default())
+    }
+
+    /// Load the channel masks from the files named by `map`.
+    pub fn new_with_mapping<P: AsRef<Path>>(path: P, map: &ChannelMap) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut images = [None, None, None];
+        let mut bit_depth = 8;
+        for ((image, file_path), constant) in images.iter_mut().zip(map.paths(path)).zip(&map.constants) {
+            if constant.is_none() {
+                let (img, bits) = open_with_depth(file_path)?;
+                bit_depth = bit_depth.max(bits);
+                *image = Some(img);
+            }
+        }
+        Ok(Self::from_images(fill_constants(images, map, path)?)?.with_bit_depth(bit_depth))
+    }
+
+    /// Like [`Mask::new_with_mapping`], but channel images that don't exist are
+    /// replaced by `fallback`. Returns the mask and the files that were replaced.
+    pub fn new_with_fallback<'a, P: AsRef<Path>>(
+        path: P,
+        map: &'a ChannelMap,
+        fallback: &Fallback,
+    ) -> anyhow::Result<(Self, Vec<&'a Path>)> {
         let path = path.as_ref();
-        let images = [
-            open(path.join("r.png"))?.into_rgba32f(),
-            open(path.join("g.png"))?.into_rgba32f(),
-            open(path.join("b.png"))?.into_rgba32f()
-        ];
+        let mut images = [None, None, None];
+        let mut missing = Vec::new();
+        let mut bit_depth = 8;
+        for (((image, file), file_path), constant) in images.iter_mut().zip(&map.files).zip(map.paths(path)).zip(&map.constants) {
+            if constant.is_some() {
+                continue;
+            }
+            if file_path.exists() {
+                let (img, bits) = open_with_depth(file_path)?;
+                bit_depth = bit_depth.max(bits);
+                *image = Some(img);
+            } else {
+                missing.push(file.as_path());
+            }
+        }
+        let substitute = match fallback {
+            Fallback::Black => {
+                let Some(present) = images.iter().flatten().next() else {
+                    anyhow::bail!("No channel images in {}", path.display());
+                };
+                flat_like(present, [0.0; 3])
+            }
+            Fallback::Image(file) => open(file)?.into_rgba32f(),
+        };
+        for (image, constant) in images.iter_mut().zip(&map.constants) {
+            if image.is_none() && constant.is_none() {
+                *image = Some(substitute.clone());
+            }
+        }
+        let images = fill_constants(images, map, path)?;
+        Ok((Self::from_images(images)?.with_bit_depth(bit_depth), missing))
+    }
+
+    /// Unpack a single image whose R, G and B channels hold the three masks
+    /// as grayscale; alpha is shared by all of them.
+    pub fn from_packed(img: &Rgba32FImage) -> Self {
+        let unpack = |c: usize| Rgba32FImage::from_fn(img.width(), img.height(), |x, y| {
+            let p = img.get_pixel(x, y).0;
+            Rgba([p[c], p[c], p[c], p[3]])
+        });
+        let (width, height) = img.dimensions();
+        Self::with_images([unpack(0), unpack(1), unpack(2)], width, height, 8)
+    }
+
+    /// [`Mask::from_packed`] from an image file.
+    pub fn open_packed<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let (img, bits) = open_with_depth(path)?;
+        Ok(Self::from_packed(&img).with_bit_depth(bits))
+    }
+
+    /// Replace the mask of `channel` (0 = R, 1 = G, 2 = B) with a flat
+    /// `color`, keeping its alpha; for sets with only two real masks.
+    pub fn with_constant_channel(mut self, channel: usize, color: [f32; 3]) -> Self {
+        self.images[channel] = flat_like(&self.images[channel], color);
+        self.stats[channel] = stats::MaskStats::of(&self.images[channel]);
+        self
+    }
+
+    /// The mask scaled down to fit in `max_side`x`max_side`, keeping its
+    /// aspect ratio; as is if it already fits.
+    pub fn downscaled(&self, max_side: u32) -> Cow<'_, Self> {
+        let longest = self.width.max(self.height);
+        if longest <= max_side {
+            return Cow::Borrowed(self);
+        }
+        let fit = |side: u32| ((side as u64 * max_side as u64 / longest as u64) as u32).max(1);
+        let (width, height) = (fit(self.width), fit(self.height));
+        let images = self.images.each_ref().map(|img| resample::resize(img, width, height, ResizeFilter::Triangle));
+        Cow::Owned(Self::with_images(images, width, height, self.bit_depth))
+    }
+
+    /// Write the channel masks as 16-bit `r.png`, `g.png` and `b.png` into
+    /// `dir`, a mask directory [`Mask::new`] loads back.
+    pub fn save_dir<P: AsRef<Path>>(&self, dir: P) -> anyhow::Result<()> {
+        for (image, path) in self.images.iter().zip(ChannelMap::default().paths(dir.as_ref())) {
+            write_atomic(path, &encode_rgba16(image, ImageFormat::Png)?)?;
+        }
+        Ok(())
+    }
+
+    /// Correct each channel mask by `levels`.
+    pub fn apply_levels(&mut self, levels: &Levels) {
+        for ((image, gain), bias) in self.images.iter_mut().zip(levels.gain).zip(levels.bias) {
+            for p in image.pixels_mut() {
+                for c in &mut p.0[..3] {
+                    *c = (*c * gain + bias).clamp(0.0, 1.0);
+                }
+            }
+        }
+        self.stats = self.images.each_ref().map(stats::MaskStats::of);
+    }
+
+    /// Min, max, mean and coverage of the R, G and B masks, gathered when
+    /// the mask was built, so showing or normalizing them doesn't rescan
+    /// the pixels. Up to date with [`Mask::apply_levels`].
+    pub fn stats(&self) -> &[stats::MaskStats; 3] {
+        &self.stats
+    }
+
+    fn with_images(images: [Rgba32FImage; 3], width: u32, height: u32, bit_depth: u8) -> Self {
+        let stats = images.each_ref().map(stats::MaskStats::of);
+        Self { images, width, height, bit_depth, stats }
+    }
+
+    /// Build a mask from already decoded R, G, B images of the same size.
+    pub fn from_images(images: [Rgba32FImage; 3]) -> anyhow::Result<Self> {
         let dimensions = images[0].dimensions();
+        anyhow::ensure!(dimensions.0 > 0 && dimensions.1 > 0, DecodeError::Empty);
         if dimensions == images[1].dimensions() && dimensions == images[2].dimensions() {
             let (width, height) = dimensions;
-            return Ok(Self {
-                images,
-                width,
-                height
-            });
+            return Ok(Self::with_images(images, width, height, 8));
         }
         Err(anyhow::anyhow!("Masks have different demensions!"))
     }
 
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Bits per channel of the deepest image the mask was loaded from; 8 for
+    /// masks built from decoded images. Outputs default to 16-bit above 8,
+    /// see [`BitDepth::Auto`].
+    pub fn source_bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub(crate) fn with_bit_depth(mut self, bits: u8) -> Self {
+        self.bit_depth = bits;
+        self
+    }
+
+    /// Bytes a loaded `width`x`height` mask takes: three RGBA f32 images.
+    pub const fn memory_size(width: u32, height: u32) -> u64 {
+        width as u64 * height as u64 * 16 * 3
+    }
+
+    /// [`Mask::memory_size`] of the mask set at `path` (a directory, a PSD or
+    /// a packed image), read from the image header without decoding. `None`
+    /// if the dimensions can't be told this way, e.g. for archives, or a
+    /// header doesn't tell the whole size, as for animated sets.
+    pub fn estimate_memory(path: &Path, map: &ChannelMap) -> Option<u64> {
+        let image = if path.is_dir() {
+            if animation::AnimatedMask::detect(path).is_some() {
+                return None;
+            }
+            map.paths(path).into_iter().find(|path| path.is_file())?
+        } else {
+            path.to_path_buf()
+        };
+        let (width, height) = if layers::is_psd(&image) {
+            layers::psd_dimensions(&image).ok()?
+        } else {
+            image::image_dimensions(image).ok()?
+        };
+        Some(Self::memory_size(width, height))
+    }
+
+    /// Mix the channel masks by `weight`. Only reads `self`, so it is safe to
+    /// call from several threads at once.
+    ///
+    /// Negative weights subtract their mask; the mix is then clamped to
+    /// 0~1 right away, so resizing doesn't spread negative values around.
     pub fn generate(&self, weight: &[f32; 3]) -> GeneratedImage {
+        self.generate_cancellable(weight, &CancelToken::new()).expect("never cancelled")
+    }
+
+    /// [`Mask::generate`], checking `cancel` before every row.
+    pub fn generate_cancellable(&self, weight: &[f32; 3], cancel: &CancelToken) -> Result<GeneratedImage, Cancelled> {
         let mut image = Rgba32FImage::new(self.width, self.height);
-        for (x, y, p) in image.enumerate_pixels_mut() {
-            let alpha = self.images[0].get_pixel(x, y).0[3];
-            p.0[3] = if alpha == 0.0 { continue } else { alpha };
-            let mask = [
-                self.images[0].get_pixel(x, y).0,
-                self.images[1].get_pixel(x, y).0,
-                self.images[2].get_pixel(x, y).0,
-            ];
-            mix_pixel(&mut p.0, &weight, &mask);
+        let subtractive = weight.iter().any(|&w| w < 0.0);
+        for (_, row) in image.enumerate_rows_mut() {
+            cancel.check()?;
+            for (x, y, p) in row {
+                let alpha = self.images[0].get_pixel(x, y).0[3];
+                p.0[3] = if alpha == 0.0 { continue } else { alpha };
+                let mask = [
+                    self.images[0].get_pixel(x, y).0,
+                    self.images[1].get_pixel(x, y).0,
+                    self.images[2].get_pixel(x, y).0,
+                ];
+                mix_pixel(&mut p.0, weight, &mask);
+                if subtractive {
+                    for c in &mut p.0[..3] {
+                        *c = c.clamp(0.0, 1.0);
+                    }
+                }
+            }
+        }
+        let image = GeneratedImage { source_bit_depth: self.bit_depth, ..GeneratedImage::new(image) };
+        #[cfg(feature = "invariants")]
+        {
+            debug_assert!(invariants::alpha_preserved(self, &image), "mix changed the alpha");
+            debug_assert!(!invariants::bounded(weight) || invariants::in_unit_range(&image), "mix by {weight:?} left 0~1");
         }
-        GeneratedImage::new(image)
+        Ok(image)
     }
 }
 
+/// Settings shared by every export path of [`GeneratedImage`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+    /// Resize filter used when the output size differs from the source
+    pub filter: ResizeFilter,
+    /// Encoded file format
+    pub format: OutputFormat,
+    /// Lossy quality `1..=100` (AVIF); `None` picks the encoder default
+    pub quality: Option<u8>,
+    /// Encoder speed `1..=10`, slower compresses better (AVIF, JPEG XL)
+    pub speed: Option<u8>,
+    /// Pin encoder settings and never write metadata (timestamps, software tags),
+    /// so the same inputs always encode to byte-identical files. Encoders
+    /// whose output could depend on threading (AVIF) run on one thread, and
+    /// resizing stays on the exact resampler instead of fast_image_resize,
+    /// whose output depends on the CPU, see [`resample`].
+    pub deterministic: bool,
+    /// Resize with wrapped edges, see [`resize_tileable`]
+    pub tileable: bool,
+    /// Whether resizing happens in linear light
+    pub resize_space: ResizeSpace,
+    /// Whether sRGB resizing reads the 8-bit or the full precision image;
+    /// linear light always uses full precision
+    pub resample_precision: ResamplePrecision,
+    /// Histogram equalization before resizing, see [`GeneratedImage::equalize`]
+    pub equalize: Option<adjust::Equalize>,
+    /// Temperature and tint, after equalizing, see [`GeneratedImage::white_balance`]
+    pub white_balance: Option<adjust::WhiteBalance>,
+    /// Saturation boost of unsaturated pixels, see [`GeneratedImage::vibrance`]
+    pub vibrance: Option<f32>,
+    /// Saturation limit, after vibrance, see [`GeneratedImage::clamp_saturation`]
+    pub max_saturation: Option<f32>,
+    /// Space the mix is converted into after resizing and post-processing,
+    /// just before encoding, see [`GeneratedImage::convert_color_space`]; PNG,
+    /// EXR and KTX2 files are tagged with it
+    pub color_space: colorspace::ColorSpace,
+    /// Unsharp mask applied after resizing; skipped at the original size
+    pub sharpen: Option<post::Sharpen>,
+    /// Lossless re-compression of PNG output: oxipng preset `0..=6`, or `7`
+    /// for preset 6 with zopfli. Needs the `optimize` feature.
+    pub optimize: Option<u8>,
+    /// Reduce to a palette; PNG output is written as indexed color
+    pub palette: Option<quantize::Quantize>,
+    /// Custom steps run in order after sharpening, see [`post::Registry`]
+    pub post: Vec<post::PostStep>,
+    /// Caption burned into the bottom-left corner after resizing, see [`post::annotate`]
+    pub annotate: Option<String>,
+    /// Bleed color this many pixels into transparent areas, see [`post::pad_edges`]
+    pub padding: Option<u32>,
+    /// UV islands of the mask set; fills the gutter from the nearest island
+    /// instead (by `padding` pixels, or all of it), see [`uv::UvIslands::dilate`]
+    pub uv: Option<std::sync::Arc<uv::UvIslands>>,
+    /// Crop to the visible pixels plus this margin, see [`post::content_bounds`]
+    pub trim: Option<u32>,
+    /// Place the final image on this canvas without scaling, after trimming
+    pub canvas: Option<canvas::Canvas>,
+    /// Position of the image on [`ExportOptions::canvas`]
+    pub anchor: canvas::Anchor,
+    /// Abort the export, checked while resizing, sharpening and padding and
+    /// between the other stages
+    pub cancel: Option<CancelToken>,
+    /// Write through a temporary file renamed into place, see [`write_atomic`]
+    pub atomic: bool,
+    /// Bits per channel, for formats that have a choice
+    pub bit_depth: BitDepth,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            filter: ResizeFilter::Lanczos3,
+            format: OutputFormat::Image(ImageFormat::Png),
+            quality: None,
+            speed: None,
+            deterministic: false,
+            tileable: false,
+            resize_space: ResizeSpace::Auto,
+            resample_precision: ResamplePrecision::U8,
+            equalize: None,
+            white_balance: None,
+            vibrance: None,
+            max_saturation: None,
+            color_space: colorspace::ColorSpace::Srgb,
+            sharpen: None,
+            optimize: None,
+            palette: None,
+            post: Vec::new(),
+            annotate: None,
+            padding: None,
+            uv: None,
+            trim: None,
+            canvas: None,
+            anchor: canvas::Anchor::Center,
+            cancel: None,
+            atomic: true,
+            bit_depth: BitDepth::Auto,
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Whether every step of these options also works on the float image,
+    /// which 16-bit and half-float exports are encoded from.
+    fn float_encodable(&self) -> bool {
+        self.sharpen.is_none() && self.post.is_empty() && self.uv.is_none() && self.annotate.is_none()
+            && self.trim.is_none() && self.canvas.is_none() && self.padding.is_none() && self.palette.is_none()
+    }
+}
+
+/// A mixed image, kept both in full precision and as 8-bit RGBA.
+#[derive(Clone, Default)]
 pub struct GeneratedImage {
     img32f: Rgba32FImage,
     img: RgbaImage,
+    source_bit_depth: u8,
+}
+
+impl fmt::Debug for GeneratedImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (width, height) = self.dimensions();
+        f.debug_struct("GeneratedImage")
+            .field("width", &width)
+            .field("height", &height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl From<Rgba32FImage> for GeneratedImage {
+    fn from(img: Rgba32FImage) -> Self {
+        Self::new(img)
+    }
+}
+
+impl From<RgbaImage> for GeneratedImage {
+    fn from(img: RgbaImage) -> Self {
+        Self {
+            img32f: image::DynamicImage::ImageRgba8(img.clone()).into_rgba32f(),
+            img,
+            source_bit_depth: 8,
+        }
+    }
 }
 
 impl GeneratedImage {
@@ -109,6 +913,29 @@ impl GeneratedImage {
         Self {
             img: f32img_to_u8img(&img),
             img32f: img,
+            source_bit_depth: 8,
+        }
+    }
+
+    /// [`Mask::source_bit_depth`] of the mask this was mixed from.
+    pub fn source_bit_depth(&self) -> u8 {
+        self.source_bit_depth
+    }
+
+    /// Whether exporting with `options` writes 16 bits per channel: always
+    /// for [`BitDepth::Sixteen`], which fails if the format or options can't
+    /// have it, and for [`BitDepth::Auto`] when they can and the source
+    /// masks were deeper than 8 bits.
+    pub fn exports_16bit(&self, options: &ExportOptions) -> anyhow::Result<bool> {
+        let supported = matches!(options.format, OutputFormat::Image(ImageFormat::Png | ImageFormat::Tiff))
+            && options.float_encodable();
+        match options.bit_depth {
+            BitDepth::Eight => Ok(false),
+            BitDepth::Auto => Ok(supported && self.source_bit_depth > 8),
+            BitDepth::Sixteen => {
+                anyhow::ensure!(supported, "16-bit export needs PNG or TIFF output without 8-bit post-processing, trimming, canvas, padding or palettes");
+                Ok(true)
+            }
         }
     }
 
@@ -116,6 +943,13 @@ impl GeneratedImage {
         format!("{basename}_{width}x{height}.png")
     }
 
+    /// Same as [`GeneratedImage::export_name`], but with the extension of `format`.
+    /// Only depends on the output size, so it can be computed before generating.
+    pub fn export_name_with_format(basename: &str, width: u32, height: u32, format: impl Into<OutputFormat>) -> String {
+        let ext = format.into().extension();
+        format!("{basename}_{width}x{height}.{ext}")
+    }
+
     pub fn get_rgba(&self) -> &RgbaImage {
         &self.img
     }
@@ -132,17 +966,233 @@ impl GeneratedImage {
         &mut self.img32f
     }
 
+    /// The mix as half-float RGBA samples, row by row: half the size of the
+    /// float image with more than 8-bit precision.
+    #[cfg(feature = "f16")]
+    pub fn to_f16(&self) -> Vec<half::f16> {
+        f32img_to_f16(&self.img32f)
+    }
+
     pub fn dimensions(&self) -> (u32, u32) {
         self.img.dimensions()
     }
 
+    /// Save in the format of `path`'s extension; the file is replaced atomically,
+    /// see [`write_atomic`].
     pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
-        self.img.save(path)?;
-        Ok(())
+        save_atomic(&self.img, path.as_ref())
+    }
+
+    /// [`GeneratedImage::save`] at `nwidth`x`nheight`.
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, nwidth: u32, nheight: u32, filter: impl Into<ResizeFilter>) -> anyhow::Result<()> {
+        save_atomic(&resample::resize(&self.img, nwidth, nheight, filter), path.as_ref())
+    }
+
+    /// [`GeneratedImage::save_as`], resizing the full precision image and
+    /// quantizing afterwards, which keeps smooth gradients from banding.
+    pub fn save_as_from_f32<P: AsRef<Path>>(&self, path: P, nwidth: u32, nheight: u32, filter: impl Into<ResizeFilter>) -> anyhow::Result<()> {
+        save_atomic(&f32img_to_u8img(&resample::resize(&self.img32f, nwidth, nheight, filter)), path.as_ref())
+    }
+
+    /// Encode as `format` at `nwidth`x`nheight`, resizing only when the size differs.
+    pub fn save_with_format<P: AsRef<Path>>(
+        &self,
+        path: P,
+        nwidth: u32,
+        nheight: u32,
+        filter: impl Into<ResizeFilter>,
+        format: impl Into<OutputFormat>,
+    ) -> anyhow::Result<()> {
+        let options = ExportOptions { filter: filter.into(), format: format.into(), ..Default::default() };
+        self.export(path, nwidth, nheight, &options)
+    }
+
+    /// The 8-bit image at `nwidth`x`nheight`, borrowed when no resize is needed.
+    pub fn resized(&self, nwidth: u32, nheight: u32, filter: impl Into<ResizeFilter>) -> Cow<'_, RgbaImage> {
+        if (nwidth, nheight) == self.dimensions() {
+            Cow::Borrowed(&self.img)
+        } else {
+            Cow::Owned(resample::resize(&self.img, nwidth, nheight, filter))
+        }
+    }
+
+    /// The 8-bit image at `nwidth`x`nheight` as exported with `options`:
+    /// resized with its filter, wrapped edges and in its [`ResizeSpace`].
+    pub fn resized_for(&self, nwidth: u32, nheight: u32, options: &ExportOptions) -> Cow<'_, RgbaImage> {
+        self.resized_for_cancellable(nwidth, nheight, options, &CancelToken::new()).expect("never cancelled")
+    }
+
+    /// Like [`GeneratedImage::resized_for`], keeping float precision.
+    pub fn resized_f32(&self, nwidth: u32, nheight: u32, options: &ExportOptions) -> Cow<'_, Rgba32FImage> {
+        self.resized_f32_cancellable(nwidth, nheight, options, &CancelToken::new()).expect("never cancelled")
     }
 
-    pub fn save_as<P: AsRef<Path>>(&self, path: P, nwidth: u32, nheight: u32, filter: imageops::FilterType) -> anyhow::Result<()> {
-        imageops::resize(&self.img, nwidth, nheight, filter).save(path)?;
+    /// [`GeneratedImage::resized_for`], checking `cancel` while resizing.
+    fn resized_for_cancellable(&self, nwidth: u32, nheight: u32, options: &ExportOptions, cancel: &CancelToken) -> Result<Cow<'_, RgbaImage>, Cancelled> {
+        if (nwidth, nheight) == self.dimensions() {
+            return Ok(Cow::Borrowed(&self.img));
+        }
+        let linear = options.resize_space.is_linear(self.dimensions(), (nwidth, nheight), options.filter);
+        if !linear && options.resample_precision == ResamplePrecision::U8 {
+            let resized = resample::resize_cancellable(&self.img, nwidth, nheight, options.filter, options.tileable, options.deterministic, cancel)?;
+            return Ok(Cow::Owned(resized));
+        }
+        let resized = self.resized_f32_cancellable(nwidth, nheight, options, cancel)?;
+        Ok(Cow::Owned(f32img_to_u8img(&resized)))
+    }
+
+    /// [`GeneratedImage::resized_f32`], checking `cancel` while resizing.
+    fn resized_f32_cancellable(&self, nwidth: u32, nheight: u32, options: &ExportOptions, cancel: &CancelToken) -> Result<Cow<'_, Rgba32FImage>, Cancelled> {
+        if (nwidth, nheight) == self.dimensions() {
+            return Ok(Cow::Borrowed(&self.img32f));
+        }
+        let resize = |img: &Rgba32FImage| {
+            resample::resize_cancellable(img, nwidth, nheight, options.filter, options.tileable, options.deterministic, cancel)
+        };
+        if !options.resize_space.is_linear(self.dimensions(), (nwidth, nheight), options.filter) {
+            return Ok(Cow::Owned(resize(&self.img32f)?));
+        }
+        let mut linear = self.img32f.clone();
+        for p in linear.pixels_mut() {
+            for c in &mut p.0[..3] {
+                *c = srgb_to_linear(*c);
+            }
+        }
+        let mut resized = resize(&linear)?;
+        for p in resized.pixels_mut() {
+            for c in &mut p.0[..3] {
+                *c = linear_to_srgb(c.max(0.0));
+            }
+        }
+        Ok(Cow::Owned(resized))
+    }
+
+    /// Encode into an in-memory file at `nwidth`x`nheight`.
+    pub fn encode(&self, nwidth: u32, nheight: u32, options: &ExportOptions) -> anyhow::Result<Vec<u8>> {
+        Ok(self.encode_with_placement(nwidth, nheight, options)?.0)
+    }
+
+    /// [`GeneratedImage::encode`], also returning where the crop of
+    /// [`ExportOptions::trim`] ended up when trimming.
+    pub fn encode_with_placement(&self, nwidth: u32, nheight: u32, options: &ExportOptions) -> anyhow::Result<(Vec<u8>, Option<post::Placement>)> {
+        let (buf, placement) = self.adjusted(options).encode_adjusted(nwidth, nheight, options)?;
+        match options.color_space.cicp() {
+            Some(code_points) if options.format == OutputFormat::Image(ImageFormat::Png) => {
+                Ok((colorspace::tag_png(&buf, code_points)?, placement))
+            }
+            _ => Ok((buf, placement)),
+        }
+    }
+
+    /// [`GeneratedImage::encode_with_placement`] of an image the adjustments
+    /// of `options` were already made to, see [`adjust`].
+    fn encode_adjusted(&self, nwidth: u32, nheight: u32, options: &ExportOptions) -> anyhow::Result<(Vec<u8>, Option<post::Placement>)> {
+        // Resizing and the row-based steps check the token as they go, the
+        // rest only in between
+        let cancel = options.cancel.clone().unwrap_or_default();
+        let check = || cancel.check();
+        check()?;
+        let is_png = options.format == OutputFormat::Image(ImageFormat::Png);
+        if options.format.is_half_float() {
+            anyhow::ensure!(
+                options.float_encodable(),
+                "Half-float export doesn't support 8-bit post-processing, trimming, canvas, padding or palettes",
+            );
+            let mut img = self.resized_f32_cancellable(nwidth, nheight, options, &cancel)?.into_owned();
+            options.color_space.linearize(&mut img);
+            let buf = match options.format {
+                OutputFormat::Ktx2F16 => encode_ktx2_f16(&img, options.color_space)?,
+                _ => encode_exr_f16(&img, options.color_space)?,
+            };
+            return Ok((buf, None));
+        }
+        if let OutputFormat::Image(format) = options.format
+            && self.exports_16bit(options)?
+        {
+            let mut img = self.resized_f32_cancellable(nwidth, nheight, options, &cancel)?.into_owned();
+            options.color_space.convert(&mut img);
+            let buf = encode_rgba16(&img, format)?;
+            check()?;
+            let buf = match options.optimize {
+                Some(level) if is_png => optimize_png(&buf, level)?,
+                _ => buf,
+            };
+            return Ok((buf, None));
+        }
+        let resize = (nwidth, nheight) != self.dimensions();
+        let mut img = self.resized_for_cancellable(nwidth, nheight, options, &cancel)?;
+        if let Some(sharpen) = options.sharpen.filter(|_| resize) {
+            img = Cow::Owned(post::unsharp_mask_cancellable(&img, sharpen, &cancel)?);
+        }
+        for step in &options.post {
+            check()?;
+            step.step.apply(img.to_mut())
+                .map_err(|e| anyhow::anyhow!("Post-processing step {:?} failed: {e}", step.name))?;
+        }
+        check()?;
+        if let Some(uv) = &options.uv {
+            uv.resized(img.width(), img.height()).dilate_cancellable(img.to_mut(), options.padding.unwrap_or(u32::MAX), &cancel)?;
+        }
+        if let Some(caption) = &options.annotate {
+            post::annotate(img.to_mut(), caption);
+        }
+        let crop = options.trim.and_then(|margin| post::content_bounds(&img, margin));
+        if let Some(crop) = crop {
+            img = Cow::Owned(imageops::crop_imm(&*img, crop.x, crop.y, crop.width, crop.height).to_image());
+        }
+        // An image with nothing visible is kept whole
+        let crop = crop.unwrap_or(post::Crop { x: 0, y: 0, width: img.width(), height: img.height() });
+        let mut offset = (0, 0);
+        if let Some(canvas) = options.canvas {
+            let size = canvas.size(img.dimensions());
+            offset = options.anchor.offset(img.dimensions(), size);
+            img = Cow::Owned(canvas::resize_canvas(&img, size, options.anchor));
+        }
+        let placement = options.trim.map(|_| post::Placement {
+            crop,
+            x: offset.0,
+            y: offset.1,
+            width: img.width(),
+            height: img.height(),
+        });
+        if let Some(radius) = options.padding.filter(|_| options.uv.is_none()) {
+            post::pad_edges_cancellable(img.to_mut(), radius, &cancel)?;
+        }
+        if options.color_space != colorspace::ColorSpace::Srgb {
+            img = Cow::Owned(options.color_space.convert_rgba8(&img));
+        }
+        check()?;
+        let buf = match options.palette {
+            Some(palette) if is_png => {
+                let mut buf = Vec::new();
+                quantize::quantize(&img, palette).write_png(&mut buf)?;
+                buf
+            }
+            Some(palette) => encode_rgba(&quantize::quantize(&img, palette).to_rgba(), options)?,
+            None => encode_rgba(&img, options)?,
+        };
+        check()?;
+        let buf = match options.optimize {
+            Some(level) if is_png => optimize_png(&buf, level)?,
+            _ => buf,
+        };
+        Ok((buf, placement))
+    }
+
+    pub fn export<P: AsRef<Path>>(&self, path: P, nwidth: u32, nheight: u32, options: &ExportOptions) -> anyhow::Result<()> {
+        self.export_with_placement(path, nwidth, nheight, options)?;
         Ok(())
     }
+
+    /// [`GeneratedImage::export`], returning where the crop of
+    /// [`ExportOptions::trim`] ended up when trimming.
+    pub fn export_with_placement<P: AsRef<Path>>(&self, path: P, nwidth: u32, nheight: u32, options: &ExportOptions) -> anyhow::Result<Option<post::Placement>> {
+        let (buf, placement) = self.encode_with_placement(nwidth, nheight, options)?;
+        if options.atomic {
+            write_atomic(path, &buf)?;
+        } else {
+            std::fs::write(path, buf)?;
+        }
+        Ok(placement)
+    }
 }
\ No newline at end of file
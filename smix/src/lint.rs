@@ -0,0 +1,126 @@
+//! Heuristic checks that channel masks are sRGB-encoded, as the mix assumes.
+//! Masks rendered or saved in linear light are the most common cause of
+//! mixes that look washed out, so loading warns when the color chunks of a
+//! PNG, the file format or the histogram of the soft edges suggest them.
+//!
+//! None of this is conclusive: a mask can be tagged wrongly, and a mask with
+//! mostly faint soft areas looks like a linear one to the histogram check.
+
+use std::path::Path;
+
+use crate::Mask;
+
+/// Median of the partially covered values below which a mask looks linear.
+/// Soft sRGB edges and gradients sit around 0.5; the same edges stored in
+/// linear light crowd into the shadows, around 0.2.
+const LINEAR_MEDIAN: f32 = 0.25;
+
+/// Fraction of the covered pixels that must be partial for the histogram to
+/// be telling; hard-edged masks have almost none.
+const MIN_PARTIAL: f64 = 0.01;
+
+/// cICP transfer characteristics (ITU-T H.273) for linear light and sRGB.
+const CICP_LINEAR: u8 = 8;
+const CICP_SRGB: u8 = 13;
+
+/// How a file's pixel values relate to light.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Srgb,
+    Linear,
+}
+
+/// What the file at `path` says about its encoding, and why: the sRGB, cICP
+/// and gAMA chunks of a PNG, or the convention of float formats like EXR.
+/// `None` if it says nothing or can't be read.
+pub fn file_encoding(path: &Path) -> Option<(Encoding, &'static str)> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "exr" | "hdr" => Some((Encoding::Linear, "float images are linear by convention")),
+        "png" => png_encoding(path),
+        _ => None,
+    }
+}
+
+fn png_encoding(path: &Path) -> Option<(Encoding, &'static str)> {
+    let file = std::io::BufReader::new(std::fs::File::open(path).ok()?);
+    let reader = ::png::Decoder::new(file).read_info().ok()?;
+    let info = reader.info();
+    // The sRGB and cICP chunks override gAMA, see the PNG specification
+    if info.srgb.is_some() {
+        return Some((Encoding::Srgb, "it has an sRGB chunk"));
+    }
+    match info.coding_independent_code_points.map(|cicp| cicp.transfer_function) {
+        Some(CICP_LINEAR) => return Some((Encoding::Linear, "its cICP chunk says linear transfer")),
+        Some(CICP_SRGB) => return Some((Encoding::Srgb, "its cICP chunk says sRGB transfer")),
+        _ => {}
+    }
+    let gamma = info.gama_chunk?.into_value();
+    if (gamma - 1.0).abs() < 0.05 {
+        Some((Encoding::Linear, "its gAMA chunk says gamma 1.0"))
+    } else if (gamma - 1.0 / 2.2).abs() < 0.02 {
+        Some((Encoding::Srgb, "its gAMA chunk says gamma 2.2"))
+    } else {
+        None
+    }
+}
+
+impl Mask {
+    /// Whether the partially covered values of each channel mask crowd into
+    /// the shadows the way soft edges stored in linear light do. `false` for
+    /// masks with too few partial pixels to tell.
+    pub fn looks_linear(&self) -> [bool; 3] {
+        self.images.each_ref().map(|image| {
+            let mut histogram = [0u64; 256];
+            let (mut covered, mut partial) = (0u64, 0u64);
+            for pixel in image.pixels() {
+                let [r, g, b, a] = pixel.0;
+                let strength = r.max(g).max(b).clamp(0.0, 1.0);
+                if a <= 0.0 || strength <= 0.0 {
+                    continue;
+                }
+                covered += 1;
+                let bin = (strength * 255.0).round() as usize;
+                if (1..255).contains(&bin) {
+                    partial += 1;
+                    histogram[bin] += 1;
+                }
+            }
+            if partial == 0 || (partial as f64) < covered as f64 * MIN_PARTIAL {
+                return false;
+            }
+            let mut seen = 0;
+            let median = histogram.iter().position(|&count| {
+                seen += count;
+                seen * 2 >= partial
+            });
+            median.is_some_and(|bin| (bin as f32 / 255.0) < LINEAR_MEDIAN)
+        })
+    }
+
+    /// Warnings about channel masks that look linear, given the files they
+    /// were loaded from in R, G, B order, `None` for constant channels.
+    /// Tagged files are trusted over the histogram, so an sRGB-tagged mask
+    /// with faint edges isn't reported.
+    pub fn color_space_warnings(&self, files: [Option<&Path>; 3]) -> Vec<String> {
+        let looks_linear = self.looks_linear();
+        let mut warnings = Vec::new();
+        for (file, looks_linear) in files.into_iter().zip(looks_linear) {
+            let Some(file) = file else { continue };
+            match file_encoding(file) {
+                Some((Encoding::Linear, why)) => warnings.push(format!(
+                    "{} looks linear ({why}), but masks are mixed as sRGB; the mix may look washed out",
+                    file.display(),
+                )),
+                Some((Encoding::Srgb, _)) => {}
+                None if looks_linear => warnings.push(format!(
+                    "{} looks linear (its soft edges are mostly dark), but masks are mixed as sRGB; the mix may look washed out",
+                    file.display(),
+                )),
+                None => {}
+            }
+        }
+        warnings.dedup();
+        warnings
+    }
+}
@@ -0,0 +1,67 @@
+//! Contact sheets: several generated images laid out on one sheet, each
+//! captioned with a label drawn by the embedded [`font`](crate::font).
+
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::{font, GeneratedImage, ResizeFilter};
+
+/// Gap around cells and labels, in pixels
+const PADDING: u32 = 4;
+
+/// Layout of a labeled contact sheet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Montage {
+    /// Cells per row
+    pub columns: u32,
+    /// Width every image is scaled to, keeping its aspect ratio
+    pub cell_width: u32,
+    /// Font pixel size of the labels
+    pub label_scale: u32,
+    pub background: Rgba<u8>,
+    pub label_color: Rgba<u8>,
+    pub filter: ResizeFilter,
+}
+
+impl Default for Montage {
+    fn default() -> Self {
+        Self {
+            columns: 4,
+            cell_width: 128,
+            label_scale: 1,
+            background: Rgba([32, 32, 32, 255]),
+            label_color: Rgba([230, 230, 230, 255]),
+            filter: ResizeFilter::Lanczos3,
+        }
+    }
+}
+
+impl Montage {
+    /// Lay `tiles` out row by row, each image above its label. Labels too wide
+    /// for a cell are cut off.
+    pub fn render(&self, tiles: &[(&GeneratedImage, &str)]) -> anyhow::Result<GeneratedImage> {
+        anyhow::ensure!(!tiles.is_empty(), "No images for the montage");
+        anyhow::ensure!(self.columns > 0 && self.cell_width > 0, "Montage cells must not be empty");
+        let scaled_height = |image: &GeneratedImage| {
+            let (width, height) = image.dimensions();
+            (self.cell_width as u64 * height as u64 / width.max(1) as u64).max(1) as u32
+        };
+        let image_height = tiles.iter().map(|(image, _)| scaled_height(image)).max().unwrap_or(1);
+        let label_height = font::GLYPH_HEIGHT * self.label_scale.max(1);
+        let (cell_width, cell_height) = (self.cell_width + PADDING, image_height + label_height + 2 * PADDING);
+        let rows = (tiles.len() as u32).div_ceil(self.columns);
+        let columns = self.columns.min(tiles.len() as u32);
+
+        let mut sheet = RgbaImage::from_pixel(columns * cell_width + PADDING, rows * cell_height + PADDING, self.background);
+        let max_chars = (self.cell_width + 1) / ((font::GLYPH_WIDTH + 1) * self.label_scale.max(1));
+        for (i, (image, label)) in tiles.iter().enumerate() {
+            let (col, row) = (i as u32 % self.columns, i as u32 / self.columns);
+            let (x, y) = ((col * cell_width + PADDING) as i64, (row * cell_height + PADDING) as i64);
+            let thumb = image.resized(self.cell_width, scaled_height(image), self.filter);
+            imageops::overlay(&mut sheet, thumb.as_ref(), x, y);
+            let label: String = label.chars().take(max_chars as usize).collect();
+            let label_y = y + (image_height + PADDING) as i64;
+            font::draw_text(&mut sheet, x, label_y, &label, self.label_color, self.label_scale);
+        }
+        Ok(sheet.into())
+    }
+}
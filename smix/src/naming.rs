@@ -0,0 +1,63 @@
+//! Output file naming templates, e.g. `{mask}_{width}x{height}`.
+//!
+//! Placeholders: `{mask}`, `{width}`, `{height}`, `{scale}`, `{r}`, `{g}`, `{b}`,
+//! and `{lod}` for the levels of an image pyramid. The extension of the
+//! output format is appended automatically, after `_lod<N>` for levels whose
+//! template has no `{lod}`. The same placeholders expand in output layouts
+//! like `{mask}/{scale}/`, see [`expand`].
+
+use crate::OutputFormat;
+
+/// Matches [`crate::GeneratedImage::export_name`].
+pub const DEFAULT_TEMPLATE: &str = "{mask}_{width}x{height}";
+
+/// Values available to a naming template.
+#[derive(Clone, Copy, Debug)]
+pub struct NameFields<'a> {
+    pub mask: &'a str,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    pub weight: [f32; 3],
+    /// Level of detail, 0 for the full size, if exporting a pyramid
+    pub lod: Option<u32>,
+}
+
+/// Expand `template` and append the extension of `format`.
+pub fn render(template: &str, fields: &NameFields, format: impl Into<OutputFormat>) -> anyhow::Result<String> {
+    let mut name = expand(template, fields)?;
+    if let Some(lod) = fields.lod.filter(|_| !template.contains("{lod}")) {
+        name = format!("{name}_lod{lod}");
+    }
+    let ext = format.into().extension();
+    Ok(format!("{name}.{ext}"))
+}
+
+/// Expand the placeholders of `template`, without an extension.
+pub fn expand(template: &str, fields: &NameFields) -> anyhow::Result<String> {
+    let mut name = String::with_capacity(template.len() + 16);
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .ok_or_else(|| anyhow::anyhow!("Unclosed `{{` in name template {template:?}"))?;
+        let key = &rest[start + 1..start + end];
+        match key {
+            "mask" => name.push_str(fields.mask),
+            "width" => name.push_str(&fields.width.to_string()),
+            "height" => name.push_str(&fields.height.to_string()),
+            "scale" => name.push_str(&fields.scale.to_string()),
+            "r" => name.push_str(&fields.weight[0].to_string()),
+            "g" => name.push_str(&fields.weight[1].to_string()),
+            "b" => name.push_str(&fields.weight[2].to_string()),
+            "lod" => match fields.lod {
+                Some(lod) => name.push_str(&lod.to_string()),
+                None => anyhow::bail!("Placeholder {{lod}} needs levels of detail to export"),
+            },
+            _ => anyhow::bail!("Unknown placeholder {{{key}}} in name template"),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+    Ok(name)
+}
@@ -0,0 +1,39 @@
+//! Post-processing steps loaded from dynamic libraries.
+//!
+//! A plugin is a `cdylib` built against the same smix version with the same
+//! compiler, exporting
+//!
+//! ```ignore
+//! #[unsafe(no_mangle)]
+//! pub fn smix_register(registry: &mut smix::post::Registry) {
+//!     registry.register("posterize", |img: &mut image::RgbaImage| { /* ... */ Ok(()) });
+//! }
+//! ```
+
+use std::path::Path;
+
+use crate::post::Registry;
+
+/// Symbol every plugin exports.
+pub const REGISTER_SYMBOL: &[u8] = b"smix_register";
+
+/// Load the plugin at `path` and let it add its steps to `registry`. The
+/// library stays loaded for the rest of the process, since its steps live
+/// in the registry.
+#[cfg(feature = "plugins")]
+pub fn load(path: &Path, registry: &mut Registry) -> anyhow::Result<()> {
+    // SAFETY: loading runs the library's initializers and the symbol type is
+    // only sound for plugins built as documented above; both are the user's call.
+    unsafe {
+        let library = libloading::Library::new(path)?;
+        let register = library.get::<fn(&mut Registry)>(REGISTER_SYMBOL)?;
+        register(registry);
+        std::mem::forget(library);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "plugins"))]
+pub fn load(_path: &Path, _registry: &mut Registry) -> anyhow::Result<()> {
+    anyhow::bail!("Plugins need smix built with the `plugins` feature")
+}
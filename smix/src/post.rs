@@ -0,0 +1,248 @@
+//! Post-processing passes applied to generated images.
+
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use image::{imageops, Pixel, Rgba, RgbaImage};
+
+use crate::{cancel::{CancelToken, Cancelled}, font};
+
+/// Unsharp mask settings: `value + amount * (value - blur(value, radius))`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sharpen {
+    /// Strength of the effect, `0.0` does nothing
+    pub amount: f32,
+    /// Gaussian blur sigma in output pixels
+    pub radius: f32,
+}
+
+impl Default for Sharpen {
+    fn default() -> Self {
+        Self { amount: 0.5, radius: 1.0 }
+    }
+}
+
+/// Sharpen the RGB channels of `img`; alpha is preserved.
+pub fn unsharp_mask(img: &RgbaImage, sharpen: Sharpen) -> RgbaImage {
+    unsharp_mask_cancellable(img, sharpen, &CancelToken::new()).expect("never cancelled")
+}
+
+/// [`unsharp_mask`], checking `cancel` after blurring and before every row.
+pub fn unsharp_mask_cancellable(img: &RgbaImage, sharpen: Sharpen, cancel: &CancelToken) -> Result<RgbaImage, Cancelled> {
+    let blurred = imageops::blur(img, sharpen.radius);
+    let mut out = img.clone();
+    for ((_, row), (_, blurred)) in out.enumerate_rows_mut().zip(blurred.enumerate_rows()) {
+        cancel.check()?;
+        for ((_, _, p), (_, _, b)) in row.zip(blurred) {
+            for i in 0..3 {
+                let value = p.0[i] as f32 + sharpen.amount * (p.0[i] as f32 - b.0[i] as f32);
+                p.0[i] = value.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Burn `caption` into the bottom-left corner of `img`, light text on a dark
+/// backing box. The font grows with the image: one font pixel per 256 pixels
+/// of width, up to 4.
+pub fn annotate(img: &mut RgbaImage, caption: &str) {
+    const MARGIN: u32 = 2;
+    let scale = (img.width() / 256).clamp(1, 4);
+    let (text_width, text_height) = font::text_size(caption, scale);
+    let (box_width, box_height) = (text_width + 2 * MARGIN * scale, text_height + 2 * MARGIN * scale);
+    let top = img.height().saturating_sub(box_height);
+    for y in top..img.height() {
+        for x in 0..box_width.min(img.width()) {
+            img.get_pixel_mut(x, y).blend(&Rgba([0, 0, 0, 160]));
+        }
+    }
+    let (x, y) = ((MARGIN * scale) as i64, (top + MARGIN * scale) as i64);
+    font::draw_text(img, x, y, caption, Rgba([255, 255, 255, 255]), scale);
+}
+
+/// A rectangle of an image, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where a trimmed export ended up: the `crop` of the resized image that
+/// was kept, and the position of its top-left corner in the written
+/// `width`x`height` file, which differs after [`ExportOptions::canvas`](crate::ExportOptions::canvas).
+/// Negative when the canvas cut part of the crop off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Placement {
+    pub crop: Crop,
+    pub x: i64,
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Bounding box of the non-transparent pixels of `img`, grown by `margin`
+/// and kept inside the image. `None` if nothing is visible.
+pub fn content_bounds(img: &RgbaImage, margin: u32) -> Option<Crop> {
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, p) in img.enumerate_pixels() {
+        if p[3] > 0 {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x);
+            bottom = bottom.max(y);
+        }
+    }
+    if left == u32::MAX {
+        return None;
+    }
+    let (x, y) = (left.saturating_sub(margin), top.saturating_sub(margin));
+    let right = right.saturating_add(margin).min(img.width() - 1);
+    let bottom = bottom.saturating_add(margin).min(img.height() - 1);
+    Some(Crop { x, y, width: right - x + 1, height: bottom - y + 1 })
+}
+
+/// Bleed the color of visible pixels up to `radius` pixels into fully
+/// transparent neighbors, so that mipmapping and filtering in-engine don't
+/// pull dark halos in from the transparent black. Alpha is unchanged.
+pub fn pad_edges(img: &mut RgbaImage, radius: u32) {
+    pad_edges_cancellable(img, radius, &CancelToken::new()).expect("never cancelled");
+}
+
+/// [`pad_edges`], checking `cancel` before every row of every pass.
+pub fn pad_edges_cancellable(img: &mut RgbaImage, radius: u32, cancel: &CancelToken) -> Result<(), Cancelled> {
+    let (width, height) = img.dimensions();
+    let mut filled: Vec<bool> = img.pixels().map(|p| p[3] > 0).collect();
+    for _ in 0..radius {
+        let mut grown = Vec::new();
+        for y in 0..height {
+            cancel.check()?;
+            for x in 0..width {
+                if filled[(y * width + x) as usize] {
+                    continue;
+                }
+                let (mut sum, mut count) = ([0u32; 3], 0);
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        if filled[(ny * width + nx) as usize] {
+                            let p = img.get_pixel(nx, ny);
+                            for c in 0..3 {
+                                sum[c] += p[c] as u32;
+                            }
+                            count += 1;
+                        }
+                    }
+                }
+                if count > 0 {
+                    grown.push((x, y, sum.map(|v| ((v + count / 2) / count) as u8)));
+                }
+            }
+        }
+        if grown.is_empty() {
+            break;
+        }
+        for (x, y, [r, g, b]) in grown {
+            let p = img.get_pixel_mut(x, y);
+            p.0[..3].copy_from_slice(&[r, g, b]);
+            filled[(y * width + x) as usize] = true;
+        }
+    }
+    Ok(())
+}
+
+/// A custom post-processing step, applied to the 8-bit image after resizing.
+///
+/// Steps are registered by name in a [`Registry`] and referenced by that name
+/// from the command line, pipeline files and the preview.
+pub trait PostProcess: Send + Sync {
+    fn apply(&self, img: &mut RgbaImage) -> anyhow::Result<()>;
+}
+
+impl<F: Fn(&mut RgbaImage) -> anyhow::Result<()> + Send + Sync> PostProcess for F {
+    fn apply(&self, img: &mut RgbaImage) -> anyhow::Result<()> {
+        self(img)
+    }
+}
+
+/// A registered [`PostProcess`] with its name; compares and prints by name.
+#[derive(Clone)]
+pub struct PostStep {
+    pub name: String,
+    pub step: Arc<dyn PostProcess>,
+}
+
+impl fmt::Debug for PostStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PostStep({:?})", self.name)
+    }
+}
+
+impl PartialEq for PostStep {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// Post-processing steps by name.
+#[derive(Clone, Default)]
+pub struct Registry {
+    steps: BTreeMap<String, Arc<dyn PostProcess>>,
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.steps.keys()).finish()
+    }
+}
+
+impl Registry {
+    /// A registry with the built-in steps: `sharpen` (default [`Sharpen`]),
+    /// `grayscale` and `invert`.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        registry.register("sharpen", |img: &mut RgbaImage| {
+            *img = unsharp_mask(img, Sharpen::default());
+            Ok(())
+        });
+        registry.register("grayscale", |img: &mut RgbaImage| {
+            for p in img.pixels_mut() {
+                let luma = p.to_luma().0[0];
+                p.0[..3].fill(luma);
+            }
+            Ok(())
+        });
+        registry.register("invert", |img: &mut RgbaImage| {
+            for p in img.pixels_mut() {
+                p.0[..3].iter_mut().for_each(|v| *v = 255 - *v);
+            }
+            Ok(())
+        });
+        registry
+    }
+
+    /// Add or replace the step called `name`.
+    pub fn register(&mut self, name: impl Into<String>, step: impl PostProcess + 'static) {
+        self.steps.insert(name.into(), Arc::new(step));
+    }
+
+    pub fn get(&self, name: &str) -> Option<PostStep> {
+        self.steps.get(name).map(|step| PostStep { name: name.into(), step: Arc::clone(step) })
+    }
+
+    /// Look up every name, failing on the first unknown one.
+    pub fn resolve<S: AsRef<str>>(&self, names: &[S]) -> anyhow::Result<Vec<PostStep>> {
+        names.iter()
+            .map(|name| {
+                let name = name.as_ref();
+                self.get(name).ok_or_else(|| anyhow::anyhow!(
+                    "Unknown post-processing step {name:?}; available: {}", self.names().collect::<Vec<_>>().join(", ")
+                ))
+            })
+            .collect()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.steps.keys().map(String::as_str)
+    }
+}
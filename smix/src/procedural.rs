@@ -0,0 +1,123 @@
+//! Synthesized masks: gradients, noise, checkers and shapes at any size, so
+//! tests and quick experiments don't need authored PNGs.
+//!
+//! Positions are in 0~1 image coordinates, `[0, 0]` being the top left
+//! corner; lengths are fractions of the shorter side.
+
+use image::{Rgba, Rgba32FImage};
+
+use crate::Mask;
+
+/// A grayscale pattern filling one channel mask.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    /// The same value everywhere
+    Solid(f32),
+    /// 0 at `from`, rising to 1 at `to` and constant beyond
+    LinearGradient { from: [f32; 2], to: [f32; 2] },
+    /// 1 at `center`, falling to 0 at `radius`
+    RadialGradient { center: [f32; 2], radius: f32 },
+    /// Perlin noise with `scale` lattice cells across the shorter side
+    Noise { scale: f32, seed: u32 },
+    /// Alternating 0 and 1 squares of `size` pixels, starting with 1
+    Checker { size: u32 },
+    /// 1 inside the circle, 0 outside
+    Circle { center: [f32; 2], radius: f32 },
+    /// 1 inside the rectangle from `min` to `max`, 0 outside
+    Rect { min: [f32; 2], max: [f32; 2] },
+}
+
+impl Pattern {
+    /// Value of pixel `(x, y)` of a `width`x`height` image, sampled at the
+    /// pixel center.
+    pub fn sample(&self, x: u32, y: u32, width: u32, height: u32) -> f32 {
+        let (u, v) = ((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32);
+        let short = width.min(height).max(1) as f32;
+        // Distance in fractions of the shorter side
+        let distance = |[cx, cy]: [f32; 2]| {
+            let (dx, dy) = ((u - cx) * width as f32 / short, (v - cy) * height as f32 / short);
+            (dx * dx + dy * dy).sqrt()
+        };
+        let value = match *self {
+            Self::Solid(value) => value,
+            Self::LinearGradient { from, to } => {
+                let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+                let length = dx * dx + dy * dy;
+                if length == 0.0 {
+                    1.0
+                } else {
+                    ((u - from[0]) * dx + (v - from[1]) * dy) / length
+                }
+            }
+            Self::RadialGradient { center, radius } => 1.0 - distance(center) / radius,
+            Self::Noise { scale, seed } => {
+                let cell = short / scale.max(f32::EPSILON);
+                perlin((x as f32 + 0.5) / cell, (y as f32 + 0.5) / cell, seed) * 0.5 + 0.5
+            }
+            Self::Checker { size } => {
+                let size = size.max(1);
+                if (x / size + y / size).is_multiple_of(2) { 1.0 } else { 0.0 }
+            }
+            Self::Circle { center, radius } => if distance(center) <= radius { 1.0 } else { 0.0 },
+            Self::Rect { min, max } => {
+                let inside = (min[0]..=max[0]).contains(&u) && (min[1]..=max[1]).contains(&v);
+                if inside { 1.0 } else { 0.0 }
+            }
+        };
+        value.clamp(0.0, 1.0)
+    }
+
+    /// The pattern as an opaque grayscale image.
+    pub fn render(&self, width: u32, height: u32) -> Rgba32FImage {
+        Rgba32FImage::from_fn(width, height, |x, y| {
+            let value = self.sample(x, y, width, height);
+            Rgba([value, value, value, 1.0])
+        })
+    }
+}
+
+impl Mask {
+    /// A `width`x`height` mask whose R, G and B masks are `patterns`.
+    ///
+    /// ```
+    /// use smix::{procedural::Pattern, Mask};
+    ///
+    /// let mask = Mask::procedural(32, 16, &[
+    ///     Pattern::LinearGradient { from: [0.0, 0.0], to: [1.0, 0.0] },
+    ///     Pattern::Circle { center: [0.5, 0.5], radius: 0.25 },
+    ///     Pattern::Noise { scale: 4.0, seed: 7 },
+    /// ]);
+    /// assert_eq!(mask.generate(&[1.0, 0.0, 0.0]).dimensions(), (32, 16));
+    /// ```
+    pub fn procedural(width: u32, height: u32, patterns: &[Pattern; 3]) -> Self {
+        Self::from_images(patterns.map(|pattern| pattern.render(width, height)))
+            .expect("rendered at the same size")
+    }
+}
+
+/// 2D Perlin noise, roughly in -1~1, with lattice points at integers.
+fn perlin(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (ix, iy) = (x0 as i32, y0 as i32);
+    let corner = |dx: i32, dy: i32| {
+        let [gx, gy] = gradient(ix.wrapping_add(dx), iy.wrapping_add(dy), seed);
+        gx * (fx - dx as f32) + gy * (fy - dy as f32)
+    };
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let (u, v) = (fade(fx), fade(fy));
+    let value = lerp(lerp(corner(0, 0), corner(1, 0), u), lerp(corner(0, 1), corner(1, 1), u), v);
+    // The extremes of 2D Perlin noise are ±√0.5
+    value * std::f32::consts::SQRT_2
+}
+
+/// Pseudo-random unit gradient of lattice point `(x, y)`.
+fn gradient(x: i32, y: i32, seed: u32) -> [f32; 2] {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841) ^ seed.wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    let angle = h as f32 / u32::MAX as f32 * std::f32::consts::TAU;
+    [angle.cos(), angle.sin()]
+}
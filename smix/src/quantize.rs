@@ -0,0 +1,94 @@
+//! Color quantization to a small palette, for indexed (palette) PNG export.
+
+use std::{fmt, io::Write};
+
+use color_quant::NeuQuant;
+use image::{imageops, RgbaImage};
+
+/// Palette export settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quantize {
+    /// Palette size, `2..=256`
+    pub colors: u16,
+    /// Floyd-Steinberg error diffusion instead of nearest color
+    pub dither: bool,
+}
+
+impl Default for Quantize {
+    /// A full 256 color palette without dithering
+    fn default() -> Self {
+        Self { colors: 256, dither: false }
+    }
+}
+
+/// An image stored as palette indices.
+#[derive(Clone)]
+pub struct IndexedImage {
+    width: u32,
+    height: u32,
+    /// RGBA palette entries
+    palette: Vec<[u8; 4]>,
+    indices: Vec<u8>,
+}
+
+impl fmt::Debug for IndexedImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IndexedImage")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("colors", &self.palette.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reduce `img` to at most `options.colors` colors (alpha included) with NeuQuant.
+pub fn quantize(img: &RgbaImage, options: Quantize) -> IndexedImage {
+    let colors = options.colors.clamp(2, 256) as usize;
+    // Sample every pixel for small images, every 10th for large ones
+    let samplefac = if img.len() < 4 * 512 * 512 { 1 } else { 10 };
+    let quant = NeuQuant::new(samplefac, colors, img.as_raw());
+    let palette = quant.color_map_rgba()
+        .chunks_exact(4)
+        .map(|c| [c[0], c[1], c[2], c[3]])
+        .collect();
+
+    let indices = if options.dither {
+        let mut dithered = img.clone();
+        imageops::dither(&mut dithered, &quant);
+        imageops::index_colors(&dithered, &quant).into_raw()
+    } else {
+        imageops::index_colors(img, &quant).into_raw()
+    };
+
+    let (width, height) = img.dimensions();
+    IndexedImage { width, height, palette, indices }
+}
+
+impl IndexedImage {
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn palette(&self) -> &[[u8; 4]] {
+        &self.palette
+    }
+
+    /// Expand back to RGBA, e.g. for formats without palette support.
+    pub fn to_rgba(&self) -> RgbaImage {
+        let pixels = self.indices.iter()
+            .flat_map(|&i| self.palette[i as usize])
+            .collect();
+        RgbaImage::from_raw(self.width, self.height, pixels).expect("one index per pixel")
+    }
+
+    /// Write an 8-bit indexed PNG with a `tRNS` chunk for alpha.
+    pub fn write_png<W: Write>(&self, writer: W) -> anyhow::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(self.palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect::<Vec<_>>());
+        encoder.set_trns(self.palette.iter().map(|c| c[3]).collect::<Vec<_>>());
+        encoder.write_header()?.write_image_data(&self.indices)?;
+        Ok(())
+    }
+}
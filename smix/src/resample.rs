@@ -0,0 +1,348 @@
+//! Multi-threaded resizing. [`resize`] gives the same pixels as
+//! [`imageops::resize`], whose separable filters run on one thread and read
+//! the source column by column; here each pass is split into strips of rows
+//! that rayon's current pool resizes in parallel, so batch generation's
+//! `jobs` limits it too, and 8k exports no longer wait on a single core.
+//!
+//! The output is bit-exact whatever the number of threads or the machine:
+//! strips only split the work, every pixel still adds up its taps in one
+//! fixed order. Asset caches hash outputs, so keep it that way;
+//! `tests/resample.rs` checks it. Exports ask for it through
+//! [`ExportOptions::deterministic`](crate::ExportOptions::deterministic),
+//! which is the exact mode once the `fast_image_resize` feature is on.
+//! Besides the `imageops` filters it has [`ResizeFilter::Box`],
+//! [`ResizeFilter::Hamming`] and [`ResizeFilter::Mitchell`].
+//!
+//! With the `fast_image_resize` feature, inexact resizes other than wrapped
+//! ones go through that crate's SIMD convolutions instead, on the same pool.
+//! They are still independent of the thread count, but its 8-bit path clamps
+//! between the two passes, so the ringing of `CatmullRom` and `Lanczos3`
+//! comes out differently at hard edges, and the other filters stay within a
+//! unit of `imageops`. It also picks CPU extensions at runtime, so the bytes
+//! can differ from one machine to the next; [`resize`], [`resize_wrapped`]
+//! and deterministic exports never take it. It can only be cancelled before
+//! it starts.
+
+use image::{imageops::{self, FilterType}, ImageBuffer, Pixel, Primitive, Rgba};
+use rayon::prelude::*;
+
+use crate::cancel::{CancelToken, Cancelled};
+
+/// Rows of a strip; fewer make the split overhead show on small images.
+const STRIP_ROWS: usize = 16;
+
+/// The filter resampling weighs source pixels with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResizeFilter {
+    /// The nearest pixel, as `imageops` picks it
+    Nearest,
+    /// Average of the source pixels a destination pixel covers
+    Box,
+    /// Linear interpolation
+    Triangle,
+    /// Windowed sinc with a short support; sharper than `Triangle`, softer than `Lanczos3`
+    Hamming,
+    /// Cubic with B = 0, C = 0.5
+    CatmullRom,
+    /// Cubic with B = C = 1/3; less ringing than `CatmullRom`, slightly softer
+    Mitchell,
+    /// Gaussian with a standard deviation of 0.5
+    Gaussian,
+    /// Lanczos with window 3
+    #[default]
+    Lanczos3,
+}
+
+impl From<FilterType> for ResizeFilter {
+    fn from(filter: FilterType) -> Self {
+        match filter {
+            FilterType::Nearest => Self::Nearest,
+            FilterType::Triangle => Self::Triangle,
+            FilterType::CatmullRom => Self::CatmullRom,
+            FilterType::Gaussian => Self::Gaussian,
+            FilterType::Lanczos3 => Self::Lanczos3,
+        }
+    }
+}
+
+/// A component type [`resize`] reads and writes.
+pub trait Sample: Primitive + Send + Sync + 'static {
+    fn to_f32(self) -> f32;
+    /// Clamped to the type's range and rounded, as `imageops` does.
+    fn from_f32(v: f32) -> Self;
+    /// `img` resized by fast_image_resize.
+    #[cfg(feature = "fast_image_resize")]
+    #[doc(hidden)]
+    fn simd_resize(img: &ImageBuffer<Rgba<Self>, Vec<Self>>, nwidth: u32, nheight: u32, filter: ResizeFilter) -> ImageBuffer<Rgba<Self>, Vec<Self>>
+    where
+        Rgba<Self>: Pixel<Subpixel = Self>;
+}
+
+impl Sample for u8 {
+    fn to_f32(self) -> f32 {
+        f32::from(self)
+    }
+
+    fn from_f32(v: f32) -> Self {
+        v.clamp(0.0, 255.0).round() as u8
+    }
+
+    #[cfg(feature = "fast_image_resize")]
+    fn simd_resize(img: &ImageBuffer<Rgba<Self>, Vec<Self>>, nwidth: u32, nheight: u32, filter: ResizeFilter) -> ImageBuffer<Rgba<Self>, Vec<Self>> {
+        let mut out = ImageBuffer::new(nwidth, nheight);
+        simd::resize(img, &mut out, filter);
+        out
+    }
+}
+
+impl Sample for u16 {
+    fn to_f32(self) -> f32 {
+        f32::from(self)
+    }
+
+    fn from_f32(v: f32) -> Self {
+        v.clamp(0.0, 65535.0).round() as u16
+    }
+
+    #[cfg(feature = "fast_image_resize")]
+    fn simd_resize(img: &ImageBuffer<Rgba<Self>, Vec<Self>>, nwidth: u32, nheight: u32, filter: ResizeFilter) -> ImageBuffer<Rgba<Self>, Vec<Self>> {
+        let mut out = ImageBuffer::new(nwidth, nheight);
+        simd::resize(img, &mut out, filter);
+        out
+    }
+}
+
+impl Sample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(v: f32) -> Self {
+        v.clamp(0.0, 1.0)
+    }
+
+    #[cfg(feature = "fast_image_resize")]
+    fn simd_resize(img: &ImageBuffer<Rgba<Self>, Vec<Self>>, nwidth: u32, nheight: u32, filter: ResizeFilter) -> ImageBuffer<Rgba<Self>, Vec<Self>> {
+        let mut out = image::Rgba32FImage::new(nwidth, nheight);
+        simd::resize(img, &mut out, filter);
+        // Float convolutions aren't clamped, ringing would leave the unit range
+        for v in out.iter_mut() {
+            *v = v.clamp(0.0, 1.0);
+        }
+        out
+    }
+}
+
+#[cfg(feature = "fast_image_resize")]
+mod simd {
+    use fast_image_resize::{FilterType, IntoImageView, IntoImageViewMut, ResizeAlg, ResizeOptions, Resizer};
+
+    use super::ResizeFilter;
+
+    /// `src` resized into `dst` with straight alpha, as the rest of smix keeps it.
+    pub(super) fn resize(src: &impl IntoImageView, dst: &mut impl IntoImageViewMut, filter: ResizeFilter) {
+        let algorithm = match filter {
+            ResizeFilter::Nearest => ResizeAlg::Nearest,
+            ResizeFilter::Box => ResizeAlg::Convolution(FilterType::Box),
+            ResizeFilter::Triangle => ResizeAlg::Convolution(FilterType::Bilinear),
+            ResizeFilter::Hamming => ResizeAlg::Convolution(FilterType::Hamming),
+            ResizeFilter::CatmullRom => ResizeAlg::Convolution(FilterType::CatmullRom),
+            ResizeFilter::Mitchell => ResizeAlg::Convolution(FilterType::Mitchell),
+            ResizeFilter::Gaussian => ResizeAlg::Convolution(FilterType::Gaussian),
+            ResizeFilter::Lanczos3 => ResizeAlg::Convolution(FilterType::Lanczos3),
+        };
+        let options = ResizeOptions::new().resize_alg(algorithm).use_alpha(false);
+        Resizer::new().resize(src, dst, &options).expect("RGBA buffers of the right size");
+    }
+}
+
+/// The kernel and its support. Filters `imageops` has too use its kernels,
+/// so the results match.
+fn kernel(filter: ResizeFilter) -> (fn(f32) -> f32, f32) {
+    fn sinc(t: f32) -> f32 {
+        let a = t * std::f32::consts::PI;
+        if t == 0.0 { 1.0 } else { a.sin() / a }
+    }
+    fn lanczos3(x: f32) -> f32 {
+        if x.abs() < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 }
+    }
+    fn hamming(x: f32) -> f32 {
+        if x.abs() >= 1.0 { 0.0 } else { sinc(x) * (0.54 + 0.46 * (x * std::f32::consts::PI).cos()) }
+    }
+    fn catmull_rom(x: f32) -> f32 {
+        bc_cubic(x, 0.0, 0.5)
+    }
+    fn mitchell(x: f32) -> f32 {
+        bc_cubic(x, 1.0 / 3.0, 1.0 / 3.0)
+    }
+    // The Mitchell-Netravali family of cubics
+    fn bc_cubic(x: f32, b: f32, c: f32) -> f32 {
+        let a = x.abs();
+        let k = if a < 1.0 {
+            (12.0 - 9.0 * b - 6.0 * c) * a.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * a.powi(2) + (6.0 - 2.0 * b)
+        } else if a < 2.0 {
+            (-b - 6.0 * c) * a.powi(3) + (6.0 * b + 30.0 * c) * a.powi(2) + (-12.0 * b - 48.0 * c) * a + (8.0 * b + 24.0 * c)
+        } else {
+            0.0
+        };
+        k / 6.0
+    }
+    fn gaussian(x: f32) -> f32 {
+        let r: f32 = 0.5;
+        ((2.0 * std::f32::consts::PI).sqrt() * r).recip() * (-x.powi(2) / (2.0 * r.powi(2))).exp()
+    }
+    fn triangle(x: f32) -> f32 {
+        if x.abs() < 1.0 { 1.0 - x.abs() } else { 0.0 }
+    }
+    match filter {
+        ResizeFilter::Nearest => (|_| 1.0, 0.0),
+        ResizeFilter::Box => (|x| if (-0.5..0.5).contains(&x) { 1.0 } else { 0.0 }, 0.5),
+        ResizeFilter::Triangle => (triangle, 1.0),
+        ResizeFilter::Hamming => (hamming, 1.0),
+        ResizeFilter::CatmullRom => (catmull_rom, 2.0),
+        ResizeFilter::Mitchell => (mitchell, 2.0),
+        ResizeFilter::Gaussian => (gaussian, 3.0),
+        ResizeFilter::Lanczos3 => (lanczos3, 3.0),
+    }
+}
+
+/// Source pixels and their normalized weights for one destination pixel.
+struct Taps {
+    indices: Vec<usize>,
+    weights: Vec<f32>,
+}
+
+/// The taps of every destination pixel when resizing `size` pixels to `nsize`.
+/// Taps beyond the edges are clamped away, as `imageops` does, or with `wrap`
+/// read from the opposite edge.
+fn taps(size: u32, nsize: u32, filter: ResizeFilter, wrap: bool) -> Vec<Taps> {
+    let (kernel, support) = kernel(filter);
+    let ratio = size as f32 / nsize as f32;
+    let sratio = ratio.max(1.0);
+    let src_support = support * sratio;
+    let size = i64::from(size);
+    (0..nsize)
+        .map(|out| {
+            let center = (out as f32 + 0.5) * ratio;
+            let (left, right) = ((center - src_support).floor() as i64, (center + src_support).ceil() as i64);
+            let (left, right) = if wrap {
+                (left, right.max(left + 1))
+            } else {
+                let left = left.clamp(0, size - 1);
+                (left, right.clamp(left + 1, size))
+            };
+            let center = center - 0.5;
+            let mut weights: Vec<f32> = (left..right).map(|i| kernel((i as f32 - center) / sratio)).collect();
+            let sum: f32 = weights.iter().sum();
+            for w in &mut weights {
+                *w /= sum;
+            }
+            Taps { indices: (left..right).map(|i| i.rem_euclid(size) as usize).collect(), weights }
+        })
+        .collect()
+}
+
+/// `img` resized to `nwidth`x`nheight` with `filter`, like [`imageops::resize`]
+/// but on all threads of the current rayon pool. Always exact, with or
+/// without the `fast_image_resize` feature.
+pub fn resize<S: Sample>(
+    img: &ImageBuffer<Rgba<S>, Vec<S>>,
+    nwidth: u32,
+    nheight: u32,
+    filter: impl Into<ResizeFilter>,
+) -> ImageBuffer<Rgba<S>, Vec<S>>
+where
+    Rgba<S>: Pixel<Subpixel = S>,
+{
+    resize_cancellable(img, nwidth, nheight, filter, false, true, &CancelToken::new()).expect("never cancelled")
+}
+
+/// [`resize`] of a tileable image: the filter reads past each edge from the
+/// opposite one, within its support, so the result tiles without seams.
+pub fn resize_wrapped<S: Sample>(
+    img: &ImageBuffer<Rgba<S>, Vec<S>>,
+    nwidth: u32,
+    nheight: u32,
+    filter: impl Into<ResizeFilter>,
+) -> ImageBuffer<Rgba<S>, Vec<S>>
+where
+    Rgba<S>: Pixel<Subpixel = S>,
+{
+    resize_cancellable(img, nwidth, nheight, filter, true, true, &CancelToken::new()).expect("never cancelled")
+}
+
+/// [`resize`], or [`resize_wrapped`] if `tileable`, checking `cancel` before
+/// every strip of rows. Unless `exact`, resizes that aren't wrapped go
+/// through fast_image_resize when the feature is on.
+pub fn resize_cancellable<S: Sample>(
+    img: &ImageBuffer<Rgba<S>, Vec<S>>,
+    nwidth: u32,
+    nheight: u32,
+    filter: impl Into<ResizeFilter>,
+    tileable: bool,
+    exact: bool,
+    cancel: &CancelToken,
+) -> Result<ImageBuffer<Rgba<S>, Vec<S>>, Cancelled>
+where
+    Rgba<S>: Pixel<Subpixel = S>,
+{
+    let filter = filter.into();
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 || nwidth == 0 || nheight == 0 || (nwidth, nheight) == (width, height) {
+        // Blank or copied, the filter doesn't matter
+        return Ok(imageops::resize(img, nwidth, nheight, FilterType::Nearest));
+    }
+    #[cfg(feature = "fast_image_resize")]
+    if !tileable && !exact {
+        cancel.check()?;
+        return Ok(S::simd_resize(img, nwidth, nheight, filter));
+    }
+    // Without the feature every resize is exact
+    #[cfg(not(feature = "fast_image_resize"))]
+    let _ = exact;
+    let src = img.as_raw();
+    let row = width as usize * 4;
+
+    // Vertical pass into floats in the component's own scale
+    let mut tmp = vec![0f32; row * nheight as usize];
+    let vertical = taps(height, nheight, filter, tileable);
+    tmp.par_chunks_mut(row * STRIP_ROWS).enumerate().try_for_each(|(strip, rows)| {
+        cancel.check()?;
+        for (y, out) in rows.chunks_exact_mut(row).enumerate() {
+            let taps = &vertical[strip * STRIP_ROWS + y];
+            // Weight by weight over whole rows, which adds up every pixel in
+            // the same order as imageops but reads the source sequentially
+            for (&i, &w) in taps.indices.iter().zip(&taps.weights) {
+                let start = i * row;
+                for (t, &v) in out.iter_mut().zip(&src[start..start + row]) {
+                    *t += v.to_f32() * w;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    // Horizontal pass, clamping and rounding into the output type
+    let nrow = nwidth as usize * 4;
+    let mut out = vec![S::DEFAULT_MIN_VALUE; nrow * nheight as usize];
+    let horizontal = taps(width, nwidth, filter, tileable);
+    out.par_chunks_mut(nrow * STRIP_ROWS).zip(tmp.par_chunks(row * STRIP_ROWS)).try_for_each(|(rows, tmp_rows)| {
+        cancel.check()?;
+        for (out, tmp) in rows.chunks_exact_mut(nrow).zip(tmp_rows.chunks_exact(row)) {
+            for (pixel, taps) in out.chunks_exact_mut(4).zip(&horizontal) {
+                let mut t = [0f32; 4];
+                for (&i, &w) in taps.indices.iter().zip(&taps.weights) {
+                    let p = &tmp[i * 4..][..4];
+                    for c in 0..4 {
+                        t[c] += p[c] * w;
+                    }
+                }
+                for (o, t) in pixel.iter_mut().zip(t) {
+                    *o = S::from_f32(t);
+                }
+            }
+        }
+        Ok(())
+    })?;
+    Ok(ImageBuffer::from_raw(nwidth, nheight, out).expect("buffer fits the dimensions"))
+}
@@ -0,0 +1,116 @@
+//! Per-pixel mix expressions, e.g. `r*0.8 + g*max(0.1, b)`.
+//!
+//! An expression is a [rhai](https://rhai.rs) script evaluated once per RGB
+//! component of every opaque pixel, replacing the linear mix of
+//! [`Mask::generate`]. Variables:
+//!
+//! - `r`, `g`, `b`: the component of the red, green and blue mask
+//! - `wr`, `wg`, `wb`: the mix weights
+//!
+//! Besides rhai's built-ins, `min`, `max`, `clamp(x, lo, hi)`, `lerp(a, b, t)`
+//! and `smoothstep(lo, hi, x)` are available for floats.
+
+#[cfg(feature = "script")]
+use image::Rgba32FImage;
+
+use crate::{GeneratedImage, Mask};
+
+/// A compiled per-pixel expression.
+pub struct Expression {
+    source: String,
+    #[cfg(feature = "script")]
+    engine: rhai::Engine,
+    #[cfg(feature = "script")]
+    ast: rhai::AST,
+}
+
+impl std::fmt::Debug for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Expression({:?})", self.source)
+    }
+}
+
+impl Expression {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+#[cfg(feature = "script")]
+impl Expression {
+    pub fn compile(source: &str) -> anyhow::Result<Self> {
+        let mut engine = rhai::Engine::new();
+        engine.set_max_expr_depths(64, 32);
+        engine.register_fn("min", |a: f32, b: f32| a.min(b));
+        engine.register_fn("max", |a: f32, b: f32| a.max(b));
+        engine.register_fn("clamp", |x: f32, lo: f32, hi: f32| x.clamp(lo, hi));
+        engine.register_fn("lerp", |a: f32, b: f32, t: f32| a + (b - a) * t);
+        engine.register_fn("smoothstep", |lo: f32, hi: f32, x: f32| {
+            let t = ((x - lo) / (hi - lo)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        });
+        let ast = engine.compile_expression(source)
+            .map_err(|e| anyhow::anyhow!("Invalid expression {source:?}: {e}"))?;
+        let expression = Self { source: source.to_string(), engine, ast };
+        // Catch type errors up front rather than on the first pixel
+        expression.eval(&mut expression.scope(&[1.0; 3]), [0.5; 3])?;
+        Ok(expression)
+    }
+
+    fn scope(&self, weight: &[f32; 3]) -> rhai::Scope<'static> {
+        let mut scope = rhai::Scope::new();
+        for (name, value) in ["wr", "wg", "wb"].into_iter().zip(weight) {
+            scope.push_constant(name, *value);
+        }
+        for name in ["r", "g", "b"] {
+            scope.push(name, 0.0f32);
+        }
+        scope
+    }
+
+    fn eval(&self, scope: &mut rhai::Scope, [r, g, b]: [f32; 3]) -> anyhow::Result<f32> {
+        scope.set_value("r", r).set_value("g", g).set_value("b", b);
+        let value = self.engine.eval_ast_with_scope::<rhai::Dynamic>(scope, &self.ast)
+            .map_err(|e| anyhow::anyhow!("Evaluating {:?}: {e}", self.source))?;
+        match (value.as_float(), value.as_int()) {
+            (Ok(value), _) => Ok(value),
+            (_, Ok(value)) => Ok(value as f32),
+            _ => anyhow::bail!("Expression {:?} must give a number, not {}", self.source, value.type_name()),
+        }
+    }
+}
+
+#[cfg(not(feature = "script"))]
+impl Expression {
+    pub fn compile(_source: &str) -> anyhow::Result<Self> {
+        anyhow::bail!("Mix expressions need smix built with the `script` feature")
+    }
+}
+
+impl Mask {
+    /// Mix the channel masks by `expr` instead of the linear weighting of
+    /// [`Mask::generate`]; `weight` is available to it as `wr`, `wg`, `wb`.
+    #[cfg(feature = "script")]
+    pub fn generate_with(&self, expr: &Expression, weight: &[f32; 3]) -> anyhow::Result<GeneratedImage> {
+        let (width, height) = self.dimensions();
+        let mut image = Rgba32FImage::new(width, height);
+        let mut scope = expr.scope(weight);
+        for (x, y, p) in image.enumerate_pixels_mut() {
+            let mask = self.images.each_ref().map(|img| img.get_pixel(x, y).0);
+            let alpha = mask[0][3];
+            if alpha == 0.0 {
+                continue;
+            }
+            for (c, value) in p.0[..3].iter_mut().enumerate() {
+                *value = expr.eval(&mut scope, [mask[0][c], mask[1][c], mask[2][c]])?;
+            }
+            p.0[3] = alpha;
+        }
+        Ok(GeneratedImage::new(image))
+    }
+
+    #[cfg(not(feature = "script"))]
+    pub fn generate_with(&self, _expr: &Expression, _weight: &[f32; 3]) -> anyhow::Result<GeneratedImage> {
+        anyhow::bail!("Mix expressions need smix built with the `script` feature")
+    }
+}
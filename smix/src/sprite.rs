@@ -0,0 +1,95 @@
+//! Sprite sheets: split a generated sheet into frames and pack frames back
+//! into a sheet with a (possibly different) grid.
+
+use std::{fmt, str::FromStr};
+
+use image::{imageops, Rgba32FImage};
+
+use crate::GeneratedImage;
+
+/// Sheet layout of `columns` x `rows` equally sized frames, in row-major order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteGrid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Default for SpriteGrid {
+    /// A single frame
+    fn default() -> Self {
+        Self { columns: 1, rows: 1 }
+    }
+}
+
+impl SpriteGrid {
+    pub fn frame_count(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    /// Size of one frame of a `width`x`height` sheet.
+    pub fn frame_size(&self, (width, height): (u32, u32)) -> anyhow::Result<(u32, u32)> {
+        anyhow::ensure!(
+            width % self.columns == 0 && height % self.rows == 0,
+            "{width}x{height} sheet can't be split into a {self} grid"
+        );
+        Ok((width / self.columns, height / self.rows))
+    }
+
+    /// Size of a sheet holding frames of `frame_width`x`frame_height`.
+    pub fn sheet_size(&self, (frame_width, frame_height): (u32, u32)) -> (u32, u32) {
+        (frame_width * self.columns, frame_height * self.rows)
+    }
+}
+
+impl fmt::Display for SpriteGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.columns, self.rows)
+    }
+}
+
+impl FromStr for SpriteGrid {
+    type Err = anyhow::Error;
+
+    /// Parse `CxR`, e.g. `4x2`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (columns, rows) = s.split_once(['x', 'X'])
+            .ok_or_else(|| anyhow::anyhow!("Expected a grid like 4x2, got {s:?}"))?;
+        let grid = Self { columns: columns.trim().parse()?, rows: rows.trim().parse()? };
+        anyhow::ensure!(grid.frame_count() > 0, "Sprite grid must have at least one frame");
+        Ok(grid)
+    }
+}
+
+impl GeneratedImage {
+    /// Cut the image into `grid.frame_count()` frames, row by row.
+    pub fn split(&self, grid: SpriteGrid) -> anyhow::Result<Vec<GeneratedImage>> {
+        let (fw, fh) = grid.frame_size(self.dimensions())?;
+        Ok((0..grid.rows)
+            .flat_map(|row| (0..grid.columns).map(move |col| (col, row)))
+            .map(|(col, row)| {
+                let frame = imageops::crop_imm(&self.img32f, col * fw, row * fh, fw, fh).to_image();
+                GeneratedImage::new(frame)
+            })
+            .collect())
+    }
+
+    /// Lay `frames` out row by row on a `grid` sheet; unused cells stay transparent.
+    pub fn pack(frames: &[GeneratedImage], grid: SpriteGrid) -> anyhow::Result<GeneratedImage> {
+        let Some(first) = frames.first() else {
+            anyhow::bail!("No frames to pack");
+        };
+        anyhow::ensure!(
+            frames.len() as u32 <= grid.frame_count(),
+            "{} frames don't fit in a {grid} grid", frames.len()
+        );
+        let (fw, fh) = first.dimensions();
+        let (width, height) = grid.sheet_size((fw, fh));
+        let mut sheet = Rgba32FImage::new(width, height);
+        for (i, frame) in frames.iter().enumerate() {
+            anyhow::ensure!(frame.dimensions() == (fw, fh), "Frames have different dimensions!");
+            let (col, row) = (i as u32 % grid.columns, i as u32 / grid.columns);
+            imageops::replace(&mut sheet, &frame.img32f, (col * fw) as i64, (row * fh) as i64);
+        }
+        Ok(GeneratedImage::new(sheet))
+    }
+}
@@ -0,0 +1,182 @@
+//! Color statistics of a mixed image per mask region.
+//!
+//! A pixel belongs to the region of a channel mask in proportion to that
+//! mask's strength there (its brightest component, times alpha), so soft
+//! mask edges count partially. [`Mask::fit_weights`] goes the other way,
+//! from target region colors to weights.
+//!
+//! [`MaskStats`] describe the channel masks themselves; every [`Mask`]
+//! keeps them up to date, see [`Mask::stats`].
+
+use image::Rgba32FImage;
+
+use crate::{Color, GeneratedImage, Levels, Mask};
+
+/// Statistics of one channel mask's strength, over its visible pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MaskStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Share of the whole image the mask covers, weighted by strength
+    pub coverage: f32,
+}
+
+impl MaskStats {
+    pub(crate) fn of(image: &Rgba32FImage) -> Self {
+        let (mut min, mut max, mut sum, mut covered, mut visible) = (f32::INFINITY, f32::NEG_INFINITY, 0f64, 0f64, 0u64);
+        for pixel in image.pixels() {
+            let [r, g, b, a] = pixel.0;
+            if a <= 0.0 {
+                continue;
+            }
+            let strength = r.max(g).max(b);
+            min = min.min(strength);
+            max = max.max(strength);
+            sum += f64::from(strength);
+            covered += region_weight(pixel.0);
+            visible += 1;
+        }
+        if visible == 0 {
+            return Self::default();
+        }
+        let pixels = f64::from(image.width()) * f64::from(image.height());
+        Self { min, max, mean: (sum / visible as f64) as f32, coverage: (covered / pixels) as f32 }
+    }
+}
+
+impl Levels {
+    /// Levels stretching each channel mask from its darkest to its
+    /// brightest value over the full 0~1 range, like auto exposure. Flat
+    /// masks are left as they are.
+    pub fn auto(stats: &[MaskStats; 3]) -> Self {
+        let mut levels = Self::default();
+        for (c, stats) in stats.iter().enumerate() {
+            if stats.max > stats.min {
+                levels.gain[c] = 1.0 / (stats.max - stats.min);
+                levels.bias[c] = -stats.min * levels.gain[c];
+            }
+        }
+        levels
+    }
+}
+
+/// Statistics of one channel mask's region.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RegionStats {
+    /// Area in pixels, weighted by mask strength
+    pub area: f64,
+    /// `area` as a fraction of the whole image
+    pub coverage: f64,
+    /// Weighted mean RGB of the mixed image, clamped to 0.0~1.0 as on export
+    pub mean: [f32; 3],
+    /// Weighted standard deviation of each RGB component
+    pub stddev: [f32; 3],
+}
+
+impl RegionStats {
+    /// `mean` as `#rrggbb`.
+    pub fn mean_hex(&self) -> String {
+        let [r, g, b] = self.mean.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+/// How much a pixel of a channel mask belongs to its region.
+fn region_weight([r, g, b, a]: Color) -> f64 {
+    f64::from(r.max(g).max(b) * a)
+}
+
+impl Mask {
+    /// Statistics of `image`, usually generated from this mask, over the
+    /// R, G and B mask regions.
+    pub fn region_stats(&self, image: &GeneratedImage) -> anyhow::Result<[RegionStats; 3]> {
+        anyhow::ensure!(
+            image.dimensions() == self.dimensions(),
+            "Image is {:?} but the mask is {:?}", image.dimensions(), self.dimensions(),
+        );
+        let img = image.get_rgba32f();
+        let mut sums = [[0f64; 7]; 3];
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let color = [pixel[0], pixel[1], pixel[2]].map(|v| f64::from(v.clamp(0.0, 1.0)));
+            for (sum, mask) in sums.iter_mut().zip(&self.images) {
+                let weight = region_weight(mask.get_pixel(x, y).0);
+                if weight <= 0.0 {
+                    continue;
+                }
+                sum[0] += weight;
+                for c in 0..3 {
+                    sum[1 + c] += weight * color[c];
+                    sum[4 + c] += weight * color[c] * color[c];
+                }
+            }
+        }
+        let pixels = f64::from(self.width) * f64::from(self.height);
+        Ok(sums.map(|sum| {
+            let area = sum[0];
+            if area == 0.0 {
+                return RegionStats::default();
+            }
+            let mean: [f64; 3] = std::array::from_fn(|c| sum[1 + c] / area);
+            RegionStats {
+                area,
+                coverage: area / pixels.max(1.0),
+                mean: mean.map(|m| m as f32),
+                stddev: std::array::from_fn(|c| (sum[4 + c] / area - mean[c] * mean[c]).max(0.0).sqrt() as f32),
+            }
+        }))
+    }
+
+    /// Weights whose mix brings the mean color of each region closest to
+    /// its target (least squares, before clamping). Regions without a
+    /// target are ignored; weights stay within 0.0~1.0.
+    pub fn fit_weights(&self, targets: &[Option<[f32; 3]>; 3]) -> [f32; 3] {
+        // means[k][j]: mean color of mask j over region k
+        let mut means = [[[0f64; 3]; 3]; 3];
+        let mut areas = [0f64; 3];
+        for (x, y) in (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y))) {
+            let pixels = self.images.each_ref().map(|img| img.get_pixel(x, y).0);
+            for (k, pixel) in pixels.iter().enumerate() {
+                let weight = region_weight(*pixel);
+                if weight <= 0.0 {
+                    continue;
+                }
+                areas[k] += weight;
+                for (j, other) in pixels.iter().enumerate() {
+                    for c in 0..3 {
+                        means[k][j][c] += weight * f64::from(other[c]);
+                    }
+                }
+            }
+        }
+
+        // Normal equations A w = v of the targeted regions' components
+        let mut a = [[0f64; 3]; 3];
+        let mut v = [0f64; 3];
+        for (k, target) in targets.iter().enumerate() {
+            let Some(target) = target.filter(|_| areas[k] > 0.0) else { continue };
+            for c in 0..3 {
+                let row: [f64; 3] = std::array::from_fn(|j| means[k][j][c] / areas[k]);
+                for i in 0..3 {
+                    v[i] += row[i] * f64::from(target[c]);
+                    for j in 0..3 {
+                        a[i][j] += row[i] * row[j];
+                    }
+                }
+            }
+        }
+
+        // Box-constrained minimum by cyclic coordinate descent
+        let mut w = [0.5f64; 3];
+        for _ in 0..200 {
+            for i in 0..3 {
+                if a[i][i] <= f64::EPSILON {
+                    continue;
+                }
+                let rest: f64 = (0..3).filter(|&j| j != i).map(|j| a[i][j] * w[j]).sum();
+                w[i] = ((v[i] - rest) / a[i][i]).clamp(0.0, 1.0);
+            }
+        }
+        w.map(|w| w as f32)
+    }
+}
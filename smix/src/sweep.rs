@@ -0,0 +1,66 @@
+//! Weight sweeps: sample the weight simplex at a fixed granularity and lay the
+//! results out on one contact sheet.
+
+use image::{imageops, RgbaImage};
+
+use crate::{GeneratedImage, ResizeFilter};
+
+/// Every weight `[r, g, b]` with components in multiples of `1 / steps`
+/// summing to 1. `steps` must be at least 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sweep {
+    pub steps: u32,
+}
+
+impl Sweep {
+    /// All sampled weights, by ascending red then ascending green.
+    pub fn weights(&self) -> Vec<[f32; 3]> {
+        let n = self.steps;
+        (0..=n)
+            .flat_map(|r| (0..=n - r).map(move |g| (r, g)))
+            .map(|(r, g)| [r, g, n - r - g].map(|v| v as f32 / n as f32))
+            .collect()
+    }
+
+    /// Grid cell `(column, row)` of the `i`th weight: rows by red, columns by
+    /// green, so the sheet forms a triangle.
+    fn cell(&self, i: usize) -> (u32, u32) {
+        let mut i = i as u32;
+        for r in 0..=self.steps {
+            let len = self.steps - r + 1;
+            if i < len {
+                return (i, r);
+            }
+            i -= len;
+        }
+        unreachable!("index outside the sweep")
+    }
+
+    /// Size of a contact sheet with `cell_width`x`cell_height` cells.
+    pub fn sheet_size(&self, (cell_width, cell_height): (u32, u32)) -> (u32, u32) {
+        ((self.steps + 1) * cell_width, (self.steps + 1) * cell_height)
+    }
+
+    /// Lay `images`, in [`Sweep::weights`] order, out on a sheet, each resized
+    /// to a cell. Cells outside the simplex stay transparent.
+    pub fn contact_sheet(
+        &self,
+        images: &[GeneratedImage],
+        cell: (u32, u32),
+        filter: impl Into<ResizeFilter>,
+    ) -> anyhow::Result<GeneratedImage> {
+        let filter = filter.into();
+        anyhow::ensure!(
+            images.len() == self.weights().len(),
+            "A {}-step sweep has {} images, got {}", self.steps, self.weights().len(), images.len()
+        );
+        let (width, height) = self.sheet_size(cell);
+        let mut sheet = RgbaImage::new(width, height);
+        for (i, image) in images.iter().enumerate() {
+            let (col, row) = self.cell(i);
+            let thumb = image.resized(cell.0, cell.1, filter);
+            imageops::replace(&mut sheet, thumb.as_ref(), (col * cell.0) as i64, (row * cell.1) as i64);
+        }
+        Ok(sheet.into())
+    }
+}
@@ -0,0 +1,109 @@
+//! Helpers for regression tests of mixing, here and downstream: tiny masks,
+//! generation in one call, and comparison against golden PNGs with a
+//! tolerance. Needs the `test-util` feature.
+//!
+//! ```
+//! use smix::testing;
+//!
+//! let mask = testing::tiny_mask(8, 8);
+//! let mixed = testing::mix(&mask, [0.5, 0.25, 0.25]);
+//! assert!(testing::compare(&mixed, &mixed, 0).matches());
+//! ```
+//!
+//! Golden files that don't exist yet are written by [`assert_golden`]; set
+//! `SMIX_UPDATE_GOLDEN=1` to rewrite existing ones after an intended change.
+
+use std::{fmt, path::Path};
+
+use image::RgbaImage;
+
+use crate::{procedural::Pattern, Mask};
+
+/// Environment variable that makes [`assert_golden`] rewrite golden files.
+pub const UPDATE_GOLDEN: &str = "SMIX_UPDATE_GOLDEN";
+
+/// A `width`x`height` mask with a distinct pattern per channel: a horizontal
+/// gradient, a centered circle and a 2-pixel checker.
+pub fn tiny_mask(width: u32, height: u32) -> Mask {
+    Mask::procedural(width, height, &[
+        Pattern::LinearGradient { from: [0.0, 0.0], to: [1.0, 0.0] },
+        Pattern::Circle { center: [0.5, 0.5], radius: 0.3 },
+        Pattern::Checker { size: 2 },
+    ])
+}
+
+/// Mix `mask` by `weight` into 8-bit RGBA.
+pub fn mix(mask: &Mask, weight: [f32; 3]) -> RgbaImage {
+    mask.generate(&weight).get_rgba().clone()
+}
+
+/// How far two images are apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Comparison {
+    /// Both images have the same dimensions; the counts are 0 otherwise
+    pub same_size: bool,
+    /// Largest difference of any channel of any pixel
+    pub max_difference: u8,
+    /// Pixels with a channel differing by more than the tolerance
+    pub differing_pixels: u64,
+}
+
+impl Comparison {
+    pub fn matches(&self) -> bool {
+        self.same_size && self.differing_pixels == 0
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.same_size {
+            return f.write_str("dimensions differ");
+        }
+        write!(f, "{} pixels differ, by up to {}", self.differing_pixels, self.max_difference)
+    }
+}
+
+/// Compare `actual` with `expected`, allowing every channel to be off by
+/// `tolerance`.
+pub fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> Comparison {
+    if actual.dimensions() != expected.dimensions() {
+        return Comparison { same_size: false, max_difference: 0, differing_pixels: 0 };
+    }
+    let mut comparison = Comparison { same_size: true, max_difference: 0, differing_pixels: 0 };
+    for (a, e) in actual.pixels().zip(expected.pixels()) {
+        let difference = a.0.iter().zip(e.0).map(|(a, e)| a.abs_diff(e)).max().unwrap_or(0);
+        comparison.max_difference = comparison.max_difference.max(difference);
+        if difference > tolerance {
+            comparison.differing_pixels += 1;
+        }
+    }
+    comparison
+}
+
+/// Compare `actual` with the PNG at `golden`, writing it instead if it
+/// doesn't exist or [`UPDATE_GOLDEN`] is set.
+pub fn check_golden<P: AsRef<Path>>(actual: &RgbaImage, golden: P, tolerance: u8) -> anyhow::Result<Comparison> {
+    let golden = golden.as_ref();
+    if !golden.exists() || std::env::var_os(UPDATE_GOLDEN).is_some_and(|value| value != "0") {
+        if let Some(dir) = golden.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        actual.save(golden)?;
+    }
+    let expected = image::open(golden)?.into_rgba8();
+    Ok(compare(actual, &expected, tolerance))
+}
+
+/// [`check_golden`], panicking with the difference if it doesn't match.
+#[track_caller]
+pub fn assert_golden<P: AsRef<Path>>(actual: &RgbaImage, golden: P, tolerance: u8) {
+    let golden = golden.as_ref();
+    match check_golden(actual, golden, tolerance) {
+        Ok(comparison) => assert!(
+            comparison.matches(),
+            "{} doesn't match: {comparison} (tolerance {tolerance}); set {UPDATE_GOLDEN}=1 to update it",
+            golden.display(),
+        ),
+        Err(e) => panic!("Checking {} failed: {e:#}", golden.display()),
+    }
+}
@@ -0,0 +1,162 @@
+//! UV island masks: keep edge dilation from bleeding across UV islands.
+//!
+//! A mask set may carry a `uv.png` in which the UV islands are drawn opaque
+//! and non-black on a black or transparent gutter. Each connected island gets
+//! its own label, and [`UvIslands::dilate`] fills the gutter from the nearest
+//! island only, never mixing the colors of two islands.
+
+use std::{collections::VecDeque, fmt, path::Path};
+
+use image::RgbaImage;
+
+use crate::cancel::{CancelToken, Cancelled};
+
+/// File name of the island mask in a mask directory.
+pub const FILE: &str = "uv.png";
+
+/// Island label of every pixel; `0` is gutter.
+#[derive(Clone, PartialEq, Eq)]
+pub struct UvIslands {
+    width: u32,
+    height: u32,
+    labels: Vec<u32>,
+}
+
+impl fmt::Debug for UvIslands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UvIslands")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("islands", &self.island_count())
+            .finish()
+    }
+}
+
+impl UvIslands {
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Ok(Self::from_image(&crate::decode::open(path)?.to_rgba8()))
+    }
+
+    /// Label the 4-connected islands of `img`'s visible, non-black pixels.
+    pub fn from_image(img: &RgbaImage) -> Self {
+        let (width, height) = img.dimensions();
+        let covered: Vec<bool> = img.pixels().map(|p| p[3] > 0 && (p[0] | p[1] | p[2]) > 0).collect();
+        let mut labels = vec![0; covered.len()];
+        let mut next = 0;
+        let mut queue = VecDeque::new();
+        for start in 0..covered.len() {
+            if !covered[start] || labels[start] != 0 {
+                continue;
+            }
+            next += 1;
+            labels[start] = next;
+            queue.push_back(start);
+            while let Some(i) = queue.pop_front() {
+                for n in neighbors(i, width, height, false) {
+                    if covered[n] && labels[n] == 0 {
+                        labels[n] = next;
+                        queue.push_back(n);
+                    }
+                }
+            }
+        }
+        Self { width, height, labels }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn island_count(&self) -> u32 {
+        self.labels.iter().copied().max().unwrap_or(0)
+    }
+
+    /// The same islands at another resolution, by nearest neighbor.
+    pub fn resized(&self, width: u32, height: u32) -> Self {
+        if (width, height) == self.dimensions() {
+            return self.clone();
+        }
+        // Sample at pixel centers
+        let source = |x: u32, size: u32, source_size: u32| {
+            (((x as f64 + 0.5) * source_size as f64 / size as f64) as u32).min(source_size - 1)
+        };
+        let labels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.label(source(x, width, self.width), source(y, height, self.height)))
+            .collect();
+        Self { width, height, labels }
+    }
+
+    fn label(&self, x: u32, y: u32) -> u32 {
+        self.labels[(y * self.width + x) as usize]
+    }
+
+    /// Grow every island's colors up to `radius` pixels into the gutter of
+    /// `img`, which must have the same size. A gutter pixel takes the average
+    /// of its already filled neighbors from a single island, the one with the
+    /// lowest label among those reaching it first. Alpha is unchanged.
+    pub fn dilate(&self, img: &mut RgbaImage, radius: u32) {
+        self.dilate_cancellable(img, radius, &CancelToken::new()).expect("never cancelled");
+    }
+
+    /// [`UvIslands::dilate`], checking `cancel` before every ring of pixels.
+    pub fn dilate_cancellable(&self, img: &mut RgbaImage, radius: u32, cancel: &CancelToken) -> Result<(), Cancelled> {
+        assert_eq!(img.dimensions(), self.dimensions(), "UV mask and image sizes differ");
+        let (width, height) = self.dimensions();
+        let mut owner = self.labels.clone();
+        let mut queued = vec![false; owner.len()];
+        let mut frontier: Vec<usize> = (0..owner.len())
+            .filter(|&i| owner[i] == 0 && neighbors(i, width, height, true).any(|n| owner[n] != 0))
+            .collect();
+        for &i in &frontier {
+            queued[i] = true;
+        }
+        for _ in 0..radius {
+            if frontier.is_empty() {
+                break;
+            }
+            cancel.check()?;
+            let raw: &mut [u8] = img;
+            let grown: Vec<_> = frontier.iter().map(|&i| {
+                let label = neighbors(i, width, height, true)
+                    .map(|n| owner[n])
+                    .filter(|&label| label != 0)
+                    .min()
+                    .expect("frontier pixels touch an island");
+                let (mut sum, mut count) = ([0u32; 3], 0);
+                for n in neighbors(i, width, height, true).filter(|&n| owner[n] == label) {
+                    for c in 0..3 {
+                        sum[c] += raw[n * 4 + c] as u32;
+                    }
+                    count += 1;
+                }
+                (i, label, sum.map(|v| ((v + count / 2) / count) as u8))
+            }).collect();
+            let mut next = Vec::new();
+            for (i, label, rgb) in grown {
+                raw[i * 4..i * 4 + 3].copy_from_slice(&rgb);
+                owner[i] = label;
+                for n in neighbors(i, width, height, true) {
+                    if owner[n] == 0 && !queued[n] {
+                        queued[n] = true;
+                        next.push(n);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        Ok(())
+    }
+}
+
+/// Indices of the 4- or 8-connected neighbors of pixel `i`.
+fn neighbors(i: usize, width: u32, height: u32, diagonal: bool) -> impl Iterator<Item = usize> {
+    let (w, h) = (width as i64, height as i64);
+    let (x, y) = (i as i64 % w, i as i64 / w);
+    [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)]
+        .into_iter()
+        .filter(move |(dx, dy)| diagonal || *dx == 0 || *dy == 0)
+        .map(move |(dx, dy)| (x + dx, y + dy))
+        .filter(move |&(nx, ny)| nx >= 0 && ny >= 0 && nx < w && ny < h)
+        .map(move |(nx, ny)| (ny * w + nx) as usize)
+}
@@ -0,0 +1,86 @@
+//! Tonal and color adjustments of whole mixes.
+
+use image::{Rgba, Rgba32FImage};
+use smix::{adjust::{Equalize, WhiteBalance, NEUTRAL_TEMPERATURE}, GeneratedImage};
+
+fn image(pixels: &[[f32; 4]]) -> GeneratedImage {
+    GeneratedImage::new(Rgba32FImage::from_fn(pixels.len() as u32, 1, |x, _| Rgba(pixels[x as usize])))
+}
+
+fn pixels(img: &GeneratedImage) -> Vec<[f32; 4]> {
+    img.get_rgba32f().pixels().map(|p| p.0).collect()
+}
+
+/// A dull gray ramp from 0.3 to 0.45.
+fn ramp() -> GeneratedImage {
+    image(&[0.3, 0.35, 0.4, 0.45].map(|v| [v, v, v, 1.0]))
+}
+
+#[test]
+fn equalize_spreads_over_the_full_range() {
+    for mode in [Equalize::Luminance, Equalize::PerChannel] {
+        let equalized = pixels(&ramp().equalize(mode));
+        let values: Vec<_> = equalized.iter().map(|p| p[0]).collect();
+        assert!(values[0].abs() < 1e-6, "{mode}: darkest is {}", values[0]);
+        assert!((values[3] - 1.0).abs() < 1e-6, "{mode}: brightest is {}", values[3]);
+        assert!(values.windows(2).all(|pair| pair[0] < pair[1]), "{mode}: order is kept, {values:?}");
+        assert!(equalized.iter().all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 1.0), "{mode}: grays stay gray");
+    }
+}
+
+#[test]
+fn equalize_ignores_transparent_pixels_and_flat_images() {
+    let img = image(&[[0.4, 0.4, 0.4, 1.0], [0.5, 0.5, 0.5, 1.0], [1.0, 1.0, 1.0, 0.0]]);
+    let equalized = pixels(&img.equalize(Equalize::PerChannel));
+    assert_eq!(equalized[0][0], 0.0);
+    assert_eq!(equalized[1][0], 1.0);
+    assert_eq!(equalized[2][3], 0.0, "alpha is kept");
+
+    let flat = image(&[[0.5, 0.25, 0.0, 1.0]; 3]);
+    assert_eq!(pixels(&flat.equalize(Equalize::Luminance)), pixels(&flat));
+}
+
+#[test]
+fn vibrance_boosts_dull_colors_more() {
+    // HSV saturation 0.2 and 0.8
+    let img = image(&[[0.5, 0.4, 0.4, 1.0], [1.0, 0.2, 0.2, 1.0]]);
+    let boosted = pixels(&img.vibrance(1.0));
+    let saturation = |p: [f32; 4]| (p[0] - p[1]) / p[0];
+    assert!((saturation(boosted[0]) - 0.36).abs() < 1e-5, "0.2 grows by 0.8 times itself, {:?}", boosted[0]);
+    assert!((saturation(boosted[1]) - 0.96).abs() < 1e-5, "0.8 grows by 0.2 times itself, {:?}", boosted[1]);
+    assert_eq!(boosted[0][0], 0.5, "value is kept");
+    assert_eq!(boosted[0][1], boosted[0][2], "hue is kept");
+    let muted = pixels(&img.vibrance(-1.0));
+    assert!((saturation(muted[0]) - 0.04).abs() < 1e-5, "{:?}", muted[0]);
+}
+
+#[test]
+fn max_saturation_only_lowers_saturated_pixels() {
+    let img = image(&[[0.5, 0.4, 0.4, 1.0], [1.0, 0.2, 0.2, 1.0]]);
+    let clamped = pixels(&img.clamp_saturation(0.5));
+    assert_eq!(clamped[0], [0.5, 0.4, 0.4, 1.0]);
+    assert!((clamped[1][1] - 0.5).abs() < 1e-6, "{:?}", clamped[1]);
+}
+
+#[test]
+fn neutral_white_balance_changes_nothing() {
+    let img = image(&[[0.5, 0.25, 0.0, 1.0], [1.0, 1.0, 1.0, 0.5], [0.1, 0.8, 0.3, 1.0]]);
+    let balanced = pixels(&img.white_balance(WhiteBalance { temperature: NEUTRAL_TEMPERATURE, tint: 0.0 }));
+    for (balanced, original) in balanced.iter().zip(pixels(&img)) {
+        for c in 0..4 {
+            assert!((balanced[c] - original[c]).abs() < 1e-5, "{balanced:?} != {original:?}");
+        }
+    }
+}
+
+#[test]
+fn white_balance_warms_and_cools() {
+    let gray = image(&[[0.5, 0.5, 0.5, 1.0]]);
+    let warm = pixels(&gray.white_balance(WhiteBalance { temperature: 3000.0, tint: 0.0 }))[0];
+    assert!(warm[0] > 0.5 && warm[2] < 0.5, "a warm light makes gray orange, {warm:?}");
+    let cool = pixels(&gray.white_balance(WhiteBalance { temperature: 10000.0, tint: 0.0 }))[0];
+    assert!(cool[0] < 0.5 && cool[2] > 0.5, "a cool light makes gray blue, {cool:?}");
+    let magenta = pixels(&gray.white_balance(WhiteBalance { temperature: NEUTRAL_TEMPERATURE, tint: 1.0 }))[0];
+    assert!(magenta[1] < magenta[0] && magenta[0] == magenta[2], "{magenta:?}");
+    assert_eq!(warm[3], 1.0, "alpha is kept");
+}
@@ -0,0 +1,58 @@
+//! The fast PNG backend decodes like the `image` crate, but doesn't verify
+//! checksums.
+#![cfg(feature = "fast-png")]
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat};
+use smix::backend::{FastPng, ImageBackend, ImageCrate};
+
+fn png(encoder: impl FnOnce(&mut png::Encoder<'_, &mut Vec<u8>>), data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut png = png::Encoder::new(&mut buf, width, height);
+    encoder(&mut png);
+    png.write_header().unwrap().write_image_data(data).unwrap();
+    buf
+}
+
+fn samples() -> Vec<Vec<u8>> {
+    let gradient = |len: usize| (0..len).map(|i| (i * 7 % 256) as u8).collect::<Vec<_>>();
+    let mut rgba16 = Vec::new();
+    DynamicImage::ImageRgba16(image::ImageBuffer::from_fn(5, 3, |x, y| image::Rgba([x as u16 * 9000, y as u16 * 20000, 123, 65535])))
+        .write_to(&mut Cursor::new(&mut rgba16), ImageFormat::Png)
+        .unwrap();
+    vec![
+        rgba16,
+        png(|png| png.set_color(png::ColorType::Grayscale), &gradient(15), 5, 3),
+        png(|png| png.set_color(png::ColorType::GrayscaleAlpha), &gradient(30), 5, 3),
+        png(|png| png.set_color(png::ColorType::Rgb), &gradient(45), 5, 3),
+        png(|png| {
+            png.set_color(png::ColorType::Indexed);
+            png.set_palette(vec![255, 0, 0, 0, 255, 0, 0, 0, 255]);
+            png.set_trns(vec![255, 128]);
+        }, &[0, 1, 2, 1, 0, 2, 2, 1, 0, 0, 1, 2, 0, 0, 1], 5, 3),
+        png(|png| {
+            png.set_color(png::ColorType::Grayscale);
+            png.set_depth(png::BitDepth::Four);
+        }, &[0x01, 0x23, 0x40, 0x56, 0x78, 0x90, 0xab, 0xcd, 0xe0], 5, 3),
+    ]
+}
+
+#[test]
+fn decodes_like_the_image_crate() {
+    for bytes in samples() {
+        let expected = ImageCrate.decode(&bytes, Some(ImageFormat::Png)).unwrap();
+        let actual = FastPng.decode(&bytes, Some(ImageFormat::Png)).unwrap();
+        assert_eq!(actual.to_rgba16(), expected.to_rgba16());
+    }
+}
+
+#[test]
+fn skips_checksums() {
+    let mut bytes = samples().swap_remove(3);
+    // The IDAT's CRC, right before the IEND chunk, so only the checksum is wrong
+    let at = bytes.len() - 12 - 1;
+    bytes[at] ^= 0xff;
+    assert!(ImageCrate.decode(&bytes, Some(ImageFormat::Png)).is_err());
+    assert!(FastPng.decode(&bytes, Some(ImageFormat::Png)).is_ok());
+}
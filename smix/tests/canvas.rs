@@ -0,0 +1,62 @@
+//! Canvas resizing places images at their anchor without scaling them.
+
+use image::{Rgba, RgbaImage};
+use smix::canvas::{self, Anchor, Canvas};
+
+/// A 2x2 opaque white image.
+fn square() -> RgbaImage {
+    RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 255]))
+}
+
+/// Top left corner of the opaque pixels of `img`.
+fn origin(img: &RgbaImage) -> (u32, u32) {
+    let (x, y, _) = img.enumerate_pixels().find(|(_, _, p)| p[3] > 0).expect("the image is on the canvas");
+    (x, y)
+}
+
+#[test]
+fn anchors_offset_the_image() {
+    let expected = [
+        (Anchor::TopLeft, (0, 0)),
+        (Anchor::Top, (2, 0)),
+        (Anchor::TopRight, (4, 0)),
+        (Anchor::Left, (0, 1)),
+        (Anchor::Center, (2, 1)),
+        (Anchor::Right, (4, 1)),
+        (Anchor::BottomLeft, (0, 2)),
+        (Anchor::Bottom, (2, 2)),
+        (Anchor::BottomRight, (4, 2)),
+    ];
+    for (anchor, offset) in expected {
+        let img = canvas::resize_canvas(&square(), (6, 4), anchor);
+        assert_eq!(img.dimensions(), (6, 4));
+        assert_eq!(origin(&img), offset, "{anchor}");
+        assert_eq!(img.pixels().filter(|p| p[3] > 0).count(), 4, "{anchor} keeps the image unscaled");
+    }
+}
+
+#[test]
+fn smaller_canvas_crops() {
+    let img = RgbaImage::from_fn(4, 4, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+    let cropped = canvas::resize_canvas(&img, (2, 2), Anchor::Center);
+    assert_eq!(cropped.get_pixel(0, 0).0, [1, 1, 0, 255]);
+    let cropped = canvas::resize_canvas(&img, (2, 2), Anchor::BottomRight);
+    assert_eq!(cropped.get_pixel(0, 0).0, [2, 2, 0, 255]);
+}
+
+#[test]
+fn power_of_two_rounds_each_side_up() {
+    assert_eq!(Canvas::PowerOfTwo.size((100, 64)), (128, 64));
+    assert_eq!(Canvas::PowerOfTwo.size((0, 1)), (1, 1));
+    assert_eq!(Canvas::Size(3, 5).size((100, 64)), (3, 5));
+}
+
+#[test]
+fn canvases_and_anchors_parse() {
+    assert_eq!("512x256".parse::<Canvas>().unwrap(), Canvas::Size(512, 256));
+    assert_eq!("POT".parse::<Canvas>().unwrap(), Canvas::PowerOfTwo);
+    assert!("0x4".parse::<Canvas>().is_err());
+    assert!("512".parse::<Canvas>().is_err());
+    assert_eq!("Bottom-Left".parse::<Anchor>().unwrap(), Anchor::BottomLeft);
+    assert!("middle".parse::<Anchor>().is_err());
+}
@@ -0,0 +1,33 @@
+//! Exports in a wide-gamut color space are resized in sRGB and converted
+//! afterwards; half-float exports hold linear light.
+
+use image::{Rgba, Rgba32FImage};
+use smix::{colorspace::ColorSpace, ExportOptions, GeneratedImage, ResizeSpace};
+
+#[test]
+fn linear_resize_happens_before_the_conversion() {
+    let img = GeneratedImage::new(Rgba32FImage::from_fn(2, 1, |x, _| {
+        let v = x as f32;
+        Rgba([v, v, v, 1.0])
+    }));
+    let options = ExportOptions { resize_space: ResizeSpace::Linear, color_space: ColorSpace::Rec2020, ..ExportOptions::default() };
+    let png = img.encode(1, 1, &options).unwrap();
+    let pixel = image::load_from_memory(&png).unwrap().into_rgba8().get_pixel(0, 0).0;
+    // Half of white in linear light, encoded with the BT.2020 curve
+    let expected = (1.099_296_8 * 0.5f32.powf(0.45) - 0.099_296_8) * 255.0;
+    for c in &pixel[..3] {
+        assert!((f32::from(*c) - expected).abs() <= 1.0, "{pixel:?} instead of {expected}");
+    }
+}
+
+#[cfg(feature = "f16")]
+#[test]
+fn half_float_exr_holds_linear_light() {
+    let img = GeneratedImage::new(Rgba32FImage::from_pixel(1, 1, Rgba([0.5, 0.5, 0.5, 0.5])));
+    let options = ExportOptions { format: smix::OutputFormat::ExrF16, ..ExportOptions::default() };
+    let exr = img.encode(1, 1, &options).unwrap();
+    let pixel = image::load_from_memory(&exr).unwrap().into_rgba32f().get_pixel(0, 0).0;
+    let linear = ((0.5 + 0.055) / 1.055f32).powf(2.4);
+    assert!(pixel[..3].iter().all(|c| (c - linear).abs() < 1e-3), "{pixel:?} instead of {linear}");
+    assert_eq!(pixel[3], 0.5);
+}
@@ -0,0 +1,44 @@
+//! `deterministic` exports encode to the same bytes every time, whatever the
+//! number of threads, and resize with the exact resampler, which matches
+//! `imageops` on any machine.
+
+use image::{imageops::{self, FilterType}, ImageFormat, Rgba, Rgba32FImage};
+use smix::{ExportOptions, GeneratedImage, OutputFormat, ResizeFilter, ResizeSpace};
+
+/// Hard edges and gradients, so every filter has something to ring on.
+fn mix() -> GeneratedImage {
+    GeneratedImage::new(Rgba32FImage::from_fn(67, 45, |x, y| {
+        let edge = if (x / 8 + y / 8) % 2 == 0 { 1.0 } else { 0.0 };
+        Rgba([edge, x as f32 / 66.0, y as f32 / 44.0, 0.5 + edge / 2.0])
+    }))
+}
+
+fn export(format: OutputFormat, threads: usize) -> Vec<u8> {
+    let options = ExportOptions { format, filter: ResizeFilter::Lanczos3, deterministic: true, ..ExportOptions::default() };
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    pool.install(|| mix().encode(130, 37, &options)).unwrap()
+}
+
+#[test]
+fn exports_are_byte_identical() {
+    let mut formats = vec![OutputFormat::Image(ImageFormat::Png), OutputFormat::Ktx2];
+    if cfg!(feature = "f16") {
+        formats.extend([OutputFormat::ExrF16, OutputFormat::Ktx2F16]);
+    }
+    for format in formats {
+        let first = export(format, 1);
+        assert_eq!(first, export(format, 1), "{format:?} twice");
+        assert_eq!(first, export(format, 4), "{format:?} on more threads");
+    }
+}
+
+#[test]
+fn exports_resize_exactly() {
+    let img = mix();
+    for filter in [FilterType::Triangle, FilterType::CatmullRom, FilterType::Lanczos3] {
+        let options = ExportOptions { filter: filter.into(), resize_space: ResizeSpace::Srgb, deterministic: true, ..ExportOptions::default() };
+        let png = img.encode(130, 37, &options).unwrap();
+        let exported = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert!(exported == imageops::resize(img.get_rgba(), 130, 37, filter), "{filter:?}");
+    }
+}
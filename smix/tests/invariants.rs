@@ -0,0 +1,72 @@
+//! Property tests of the mixing invariants in `smix::invariants`, over random
+//! procedural masks and weights. Failing cases are shrunk and their seeds
+//! kept in `proptest-regressions/`.
+
+use proptest::prelude::*;
+use smix::{invariants, procedural::Pattern, Mask};
+
+const CASES: u32 = 64;
+
+const PERMUTATIONS: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+
+fn point() -> impl Strategy<Value = [f32; 2]> {
+    [0.0f32..1.0, 0.0f32..1.0]
+}
+
+fn pattern() -> impl Strategy<Value = Pattern> {
+    prop_oneof![
+        (0.0f32..=1.0).prop_map(Pattern::Solid),
+        (point(), point()).prop_map(|(from, to)| Pattern::LinearGradient { from, to }),
+        (point(), 0.1f32..1.1).prop_map(|(center, radius)| Pattern::RadialGradient { center, radius }),
+        (0.5f32..8.5, any::<u32>()).prop_map(|(scale, seed)| Pattern::Noise { scale, seed }),
+        (1u32..=4).prop_map(|size| Pattern::Checker { size }),
+        (point(), 0.0f32..1.0).prop_map(|(center, radius)| Pattern::Circle { center, radius }),
+        (point(), point()).prop_map(|(min, max)| Pattern::Rect { min, max }),
+    ]
+}
+
+fn mask() -> impl Strategy<Value = Mask> {
+    (1u32..=16, 1u32..=16, pattern(), pattern(), pattern())
+        .prop_map(|(width, height, r, g, b)| Mask::procedural(width, height, &[r, g, b]))
+}
+
+/// Non-negative weights adding up to at most 1.
+fn weight() -> impl Strategy<Value = [f32; 3]> {
+    [0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0].prop_map(|weight| {
+        let sum = weight.iter().sum::<f32>().max(1.0);
+        weight.map(|w| w / sum)
+    })
+}
+
+/// Weights in -1~1, at least one of them negative.
+fn signed_weight() -> impl Strategy<Value = [f32; 3]> {
+    ([-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0], 0usize..3).prop_map(|(mut weight, negative)| {
+        weight[negative] = -weight[negative].abs().max(f32::EPSILON);
+        weight
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn mix_stays_in_unit_range(mask in mask(), weight in prop_oneof![weight(), signed_weight()]) {
+        prop_assert!(invariants::bounded(&weight));
+        prop_assert!(invariants::in_unit_range(&mask.generate(&weight)));
+    }
+
+    #[test]
+    fn mix_preserves_alpha(mask in mask(), weight in signed_weight()) {
+        prop_assert!(invariants::alpha_preserved(&mask, &mask.generate(&weight)));
+    }
+
+    #[test]
+    fn mix_is_linear_in_weights(mask in mask(), a in weight(), b in weight()) {
+        prop_assert!(invariants::linear(&mask, &a, &b));
+    }
+
+    #[test]
+    fn mix_commutes_with_channel_permutations(mask in mask(), weight in weight(), permutation in prop::sample::select(&PERMUTATIONS[..])) {
+        prop_assert!(invariants::permutation_invariant(&mask, &weight, permutation));
+    }
+}
@@ -0,0 +1,89 @@
+//! KTX2 exports parse with the `ktx2` crate and hold the exported pixels.
+
+use image::{ImageFormat, Rgba, Rgba32FImage};
+use ktx2::{ChannelTypeQualifiers, ColorPrimaries, DfdBlockBasic, Format, TransferFunction};
+use smix::{colorspace::ColorSpace, ExportOptions, GeneratedImage, OutputFormat};
+
+fn gradient() -> GeneratedImage {
+    GeneratedImage::new(Rgba32FImage::from_fn(5, 3, |x, y| Rgba([x as f32 / 4.0, y as f32 / 2.0, 0.25, 1.0])))
+}
+
+fn encode(format: OutputFormat, color_space: ColorSpace) -> Vec<u8> {
+    let options = ExportOptions { format, color_space, ..ExportOptions::default() };
+    gradient().encode(5, 3, &options).unwrap()
+}
+
+/// The basic data format descriptor of `reader`.
+fn descriptor<'a>(reader: &'a ktx2::Reader<&[u8]>) -> DfdBlockBasic<'a> {
+    let block = reader.dfd_blocks().next().expect("a data format descriptor");
+    DfdBlockBasic::parse(block.data).unwrap()
+}
+
+#[test]
+fn rgba8_holds_the_png_pixels() {
+    let bytes = encode(OutputFormat::Ktx2, ColorSpace::Srgb);
+    let reader = ktx2::Reader::new(bytes.as_slice()).unwrap();
+    let header = reader.header();
+    assert_eq!(header.format, Some(Format::R8G8B8A8_SRGB));
+    assert_eq!((header.pixel_width, header.pixel_height, header.level_count), (5, 3, 1));
+    assert_eq!(header.type_size, 1);
+
+    let png = encode(OutputFormat::Image(ImageFormat::Png), ColorSpace::Srgb);
+    let expected = image::load_from_memory(&png).unwrap().into_rgba8();
+    let level = reader.levels().next().unwrap();
+    assert_eq!(level.data, expected.as_raw().as_slice());
+    assert_eq!(level.data.as_ptr() as usize % 4, bytes.as_ptr() as usize % 4);
+
+    let dfd = descriptor(&reader);
+    assert_eq!(dfd.header.color_primaries, Some(ColorPrimaries::BT709));
+    assert_eq!(dfd.header.transfer_function, Some(TransferFunction::SRGB));
+    let samples: Vec<_> = dfd.sample_information().collect();
+    assert_eq!(samples.iter().map(|s| (s.bit_offset, s.channel_type)).collect::<Vec<_>>(), [(0, 0), (8, 1), (16, 2), (24, 15)]);
+    assert_eq!(samples[3].channel_type_qualifiers, ChannelTypeQualifiers::LINEAR);
+    assert!(samples.iter().all(|s| s.bit_length.get() == 8 && s.upper == 255));
+
+    let writer = reader.key_value_data().find(|(key, _)| *key == "KTXwriter").unwrap().1;
+    assert!(writer.starts_with(b"smix "));
+}
+
+#[test]
+fn rec2020_is_tagged() {
+    let bytes = encode(OutputFormat::Ktx2, ColorSpace::Rec2020);
+    let reader = ktx2::Reader::new(bytes.as_slice()).unwrap();
+    assert_eq!(reader.header().format, Some(Format::R8G8B8A8_UNORM));
+    let dfd = descriptor(&reader);
+    assert_eq!(dfd.header.color_primaries, Some(ColorPrimaries::BT2020));
+    assert_eq!(dfd.header.transfer_function, Some(TransferFunction::ITU));
+}
+
+#[cfg(feature = "f16")]
+#[test]
+fn rgba16f_holds_the_float_mix() {
+    let bytes = encode(OutputFormat::Ktx2F16, ColorSpace::Srgb);
+    let reader = ktx2::Reader::new(bytes.as_slice()).unwrap();
+    let header = reader.header();
+    assert_eq!(header.format, Some(Format::R16G16B16A16_SFLOAT));
+    assert_eq!(header.type_size, 2);
+
+    let level = reader.levels().next().unwrap();
+    assert_eq!(level.data.len(), 5 * 3 * 4 * 2);
+    assert_eq!(level.data.as_ptr() as usize % 8, bytes.as_ptr() as usize % 8);
+    let sample = |i: usize| half::f16::from_le_bytes([level.data[i * 2], level.data[i * 2 + 1]]).to_f32();
+    // The last pixel of the first row: x = 4, y = 0
+    let i = 4 * 4;
+    // sRGB 0.25 in linear light, as half-float
+    let quarter = half::f16::from_f32(((0.25 + 0.055) / 1.055f32).powf(2.4)).to_f32();
+    assert_eq!([sample(i), sample(i + 1), sample(i + 2), sample(i + 3)], [1.0, 0.0, quarter, 1.0]);
+
+    let dfd = descriptor(&reader);
+    assert_eq!(dfd.header.color_primaries, Some(ColorPrimaries::BT709));
+    assert_eq!(dfd.header.transfer_function, Some(TransferFunction::Linear));
+    let samples: Vec<_> = dfd.sample_information().collect();
+    assert!(samples.iter().all(|s| s.bit_length.get() == 16
+        && s.channel_type_qualifiers == ChannelTypeQualifiers::FLOAT | ChannelTypeQualifiers::SIGNED
+        && s.upper == 1.0f32.to_bits()));
+
+    let bytes = encode(OutputFormat::Ktx2F16, ColorSpace::DisplayP3);
+    let reader = ktx2::Reader::new(bytes.as_slice()).unwrap();
+    assert_eq!(descriptor(&reader).header.color_primaries, Some(ColorPrimaries::DISPLAYP3));
+}
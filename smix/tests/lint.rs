@@ -0,0 +1,75 @@
+//! Masks that look linear by their color chunks, format or histogram are reported.
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, Rgba32FImage};
+use smix::{lint::{self, Encoding}, Mask};
+
+/// A mask whose three channels hold the soft ramp `value(i)` for `i` in 1~254.
+fn ramp(value: impl Fn(f32) -> f32) -> Mask {
+    let img = Rgba32FImage::from_fn(254, 1, |x, _| {
+        let v = value((x + 1) as f32 / 255.0);
+        Rgba([v, v, v, 1.0])
+    });
+    Mask::from_images([img.clone(), img.clone(), img]).unwrap()
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+/// A 1x1 gray PNG at `name` in a fresh directory, with the chunks `tag` sets.
+fn png(name: &str, tag: impl FnOnce(&mut png::Encoder<'_, std::fs::File>)) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("smix-lint-{}-{name}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let mut encoder = png::Encoder::new(std::fs::File::create(&path).unwrap(), 1, 1);
+    encoder.set_color(png::ColorType::Grayscale);
+    tag(&mut encoder);
+    encoder.write_header().unwrap().write_image_data(&[128]).unwrap();
+    path
+}
+
+#[test]
+fn color_chunks_tell_the_encoding() {
+    let linear = png("linear.png", |encoder| encoder.set_source_gamma(png::ScaledFloat::new(1.0)));
+    let gamma = png("gamma.png", |encoder| encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2)));
+    let srgb = png("srgb.png", |encoder| {
+        encoder.set_source_gamma(png::ScaledFloat::new(1.0));
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    });
+    let untagged = png("untagged.png", |_| {});
+    assert_eq!(lint::file_encoding(&linear).map(|(encoding, _)| encoding), Some(Encoding::Linear));
+    assert_eq!(lint::file_encoding(&gamma).map(|(encoding, _)| encoding), Some(Encoding::Srgb));
+    assert_eq!(lint::file_encoding(&srgb).map(|(encoding, _)| encoding), Some(Encoding::Srgb), "sRGB overrides gAMA");
+    assert_eq!(lint::file_encoding(&untagged), None);
+    assert_eq!(lint::file_encoding(Path::new("mask.EXR")).map(|(encoding, _)| encoding), Some(Encoding::Linear));
+    assert_eq!(lint::file_encoding(Path::new("mask.jpg")), None);
+    for path in [linear, gamma, srgb, untagged] {
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
+
+#[test]
+fn linear_soft_edges_look_linear() {
+    assert_eq!(ramp(srgb_to_linear).looks_linear(), [true; 3]);
+    assert_eq!(ramp(|v| v).looks_linear(), [false; 3]);
+    let hard = Mask::from_images([0.0, 1.0, 1.0].map(|v| Rgba32FImage::from_pixel(4, 4, Rgba([v, v, v, 1.0])))).unwrap();
+    assert_eq!(hard.looks_linear(), [false; 3], "hard edges have nothing to tell");
+}
+
+#[test]
+fn tagged_files_are_trusted_over_the_histogram() {
+    let mask = ramp(srgb_to_linear);
+    let srgb = png("tagged.png", |encoder| encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual));
+    let untagged = png("plain.png", |_| {});
+    assert!(mask.color_space_warnings([Some(&srgb), None, None]).is_empty());
+    let warnings = mask.color_space_warnings([Some(&untagged), Some(&untagged), None]);
+    assert_eq!(warnings.len(), 1, "repeated files are reported once, {warnings:?}");
+    assert!(warnings[0].contains("soft edges"), "{}", warnings[0]);
+    let warnings = ramp(|v| v).color_space_warnings([Some(Path::new("mask.exr")), None, None]);
+    assert_eq!(warnings.len(), 1, "{warnings:?}");
+    for path in [srgb, untagged] {
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
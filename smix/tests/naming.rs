@@ -0,0 +1,34 @@
+//! Output naming templates expand every placeholder and reject unknown ones.
+
+use image::ImageFormat;
+use smix::naming::{self, NameFields, DEFAULT_TEMPLATE};
+
+fn fields(lod: Option<u32>) -> NameFields<'static> {
+    NameFields { mask: "hero", width: 512, height: 256, scale: 0.5, weight: [1.0, 0.25, 0.0], lod }
+}
+
+#[test]
+fn placeholders_expand() {
+    let name = naming::expand("{mask}-{width}x{height}@{scale}_{r}_{g}_{b}", &fields(None)).unwrap();
+    assert_eq!(name, "hero-512x256@0.5_1_0.25_0");
+    assert_eq!(naming::expand("{mask}/{scale}/", &fields(None)).unwrap(), "hero/0.5/");
+}
+
+#[test]
+fn render_appends_the_extension() {
+    assert_eq!(naming::render(DEFAULT_TEMPLATE, &fields(None), ImageFormat::Png).unwrap(), "hero_512x256.png");
+}
+
+#[test]
+fn levels_without_lod_placeholder_get_a_suffix() {
+    assert_eq!(naming::render("{mask}", &fields(Some(2)), ImageFormat::Png).unwrap(), "hero_lod2.png");
+    assert_eq!(naming::render("{mask}-{lod}", &fields(Some(2)), ImageFormat::Png).unwrap(), "hero-2.png");
+}
+
+#[test]
+fn unknown_placeholders_are_errors() {
+    let error = naming::expand("{mask}_{size}", &fields(None)).unwrap_err();
+    assert!(error.to_string().contains("{size}"), "{error}");
+    assert!(naming::expand("{mask", &fields(None)).is_err());
+    assert!(naming::expand("{mask}_{lod}", &fields(None)).is_err(), "{{lod}} needs levels of detail");
+}
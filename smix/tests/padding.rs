@@ -0,0 +1,43 @@
+//! Edge padding bleeds visible colors into transparent neighbors, and only as far as asked.
+
+use image::{Rgba, RgbaImage};
+use smix::post;
+
+/// A 7x1 row with a single visible red pixel in the middle.
+fn dot() -> RgbaImage {
+    RgbaImage::from_fn(7, 1, |x, _| if x == 3 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 0, 0]) })
+}
+
+#[test]
+fn padding_stops_at_the_radius() {
+    let mut img = dot();
+    post::pad_edges(&mut img, 2);
+    let colors: Vec<_> = img.pixels().map(|p| p.0).collect();
+    assert_eq!(colors, [
+        [0, 0, 0, 0],
+        [255, 0, 0, 0],
+        [255, 0, 0, 0],
+        [255, 0, 0, 255],
+        [255, 0, 0, 0],
+        [255, 0, 0, 0],
+        [0, 0, 0, 0],
+    ]);
+}
+
+#[test]
+fn padding_averages_neighbors() {
+    let mut img = RgbaImage::from_fn(3, 1, |x, _| match x {
+        0 => Rgba([200, 0, 0, 255]),
+        2 => Rgba([0, 100, 0, 255]),
+        _ => Rgba([0, 0, 0, 0]),
+    });
+    post::pad_edges(&mut img, 1);
+    assert_eq!(img.get_pixel(1, 0).0, [100, 50, 0, 0]);
+}
+
+#[test]
+fn fully_transparent_images_are_unchanged() {
+    let mut img = RgbaImage::new(4, 4);
+    post::pad_edges(&mut img, 8);
+    assert_eq!(img, RgbaImage::new(4, 4));
+}
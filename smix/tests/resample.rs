@@ -0,0 +1,104 @@
+//! Bit-exactness of the threaded resizing in `smix::resample`: outputs are
+//! hashed by asset caches, so they must not depend on the number of threads,
+//! and must match `imageops::resize` for the filters it has.
+
+use image::{imageops::{self, FilterType}, ImageBuffer, Rgba, Rgba32FImage, RgbaImage};
+use smix::{resample, ResizeFilter};
+
+const FILTERS: [ResizeFilter; 8] = [
+    ResizeFilter::Nearest,
+    ResizeFilter::Box,
+    ResizeFilter::Triangle,
+    ResizeFilter::Hamming,
+    ResizeFilter::CatmullRom,
+    ResizeFilter::Mitchell,
+    ResizeFilter::Gaussian,
+    ResizeFilter::Lanczos3,
+];
+
+/// Odd sizes, so the last strip of rows is partial, up and down.
+const SIZES: [(u32, u32); 3] = [(130, 37), (23, 90), (67, 45)];
+
+fn source() -> RgbaImage {
+    RgbaImage::from_fn(67, 45, |x, y| Rgba([(x * 7 % 256) as u8, (y * 13 % 256) as u8, ((x ^ y) * 5 % 256) as u8, ((x + y) * 3 % 256) as u8]))
+}
+
+fn on_threads<T: Send>(threads: usize, f: impl FnOnce() -> T + Send) -> T {
+    rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap().install(f)
+}
+
+/// `img` resized to every size with every filter, on `threads` threads.
+fn resize_all<S: resample::Sample>(img: &ImageBuffer<Rgba<S>, Vec<S>>, threads: usize) -> Vec<ImageBuffer<Rgba<S>, Vec<S>>>
+where
+    Rgba<S>: image::Pixel<Subpixel = S>,
+{
+    on_threads(threads, || {
+        FILTERS.iter().flat_map(|&filter| SIZES.map(|(width, height)| resample::resize(img, width, height, filter))).collect()
+    })
+}
+
+#[test]
+fn threads_do_not_change_8_bit_output() {
+    let img = source();
+    assert!(resize_all(&img, 1) == resize_all(&img, 4));
+}
+
+#[test]
+fn threads_do_not_change_float_output() {
+    let img: Rgba32FImage = image::DynamicImage::from(source()).into_rgba32f();
+    let (serial, threaded) = (resize_all(&img, 1), resize_all(&img, 4));
+    // Compared bit for bit, so even -0.0 and 0.0 would differ
+    for (a, b) in serial.iter().zip(&threaded) {
+        assert!(a.as_raw().iter().zip(b.as_raw()).all(|(a, b)| a.to_bits() == b.to_bits()));
+    }
+}
+
+#[test]
+fn matches_imageops() {
+    let img = source();
+    for filter in [FilterType::Nearest, FilterType::Triangle, FilterType::CatmullRom, FilterType::Gaussian, FilterType::Lanczos3] {
+        for (width, height) in SIZES {
+            let expected = imageops::resize(&img, width, height, filter);
+            assert!(on_threads(4, || resample::resize(&img, width, height, filter)) == expected, "{filter:?} to {width}x{height}");
+        }
+    }
+}
+
+/// fast_image_resize, used by inexact resizes, only rounds differently with
+/// the filters that don't ring.
+#[cfg(feature = "fast_image_resize")]
+#[test]
+fn nearly_matches_imageops() {
+    let img = source();
+    let cancel = smix::cancel::CancelToken::new();
+    for filter in [FilterType::Nearest, FilterType::Triangle, FilterType::Gaussian] {
+        for (width, height) in SIZES {
+            let expected = imageops::resize(&img, width, height, filter);
+            let resized = on_threads(4, || resample::resize_cancellable(&img, width, height, filter, false, false, &cancel).unwrap());
+            assert!(resized.as_raw().iter().zip(expected.as_raw()).all(|(a, b)| a.abs_diff(*b) <= 1), "{filter:?} to {width}x{height}");
+        }
+    }
+}
+
+/// Wrapped edges read what a 3x3 tiling of the image has next to its middle
+/// tile, so resizing the tiling and keeping the middle gives the same pixels.
+/// Wrapped resizes always run on the strips, so compare against those.
+#[test]
+fn wrapped_edges_match_a_tiled_resize() {
+    let img = source();
+    let tiled = RgbaImage::from_fn(67 * 3, 45 * 3, |x, y| *img.get_pixel(x % 67, y % 45));
+    for filter in FILTERS {
+        let wrapped = resample::resize_wrapped(&img, 134, 15, filter);
+        let middle = imageops::crop_imm(&resample::resize(&tiled, 134 * 3, 15 * 3, filter), 134, 15, 134, 15).to_image();
+        let off = wrapped.as_raw().iter().zip(middle.as_raw()).filter(|(a, b)| a.abs_diff(**b) > 1).count();
+        assert_eq!(off, 0, "{filter:?}");
+    }
+}
+
+#[test]
+fn cancelled_resize_stops() {
+    let cancel = smix::cancel::CancelToken::new();
+    cancel.cancel();
+    let resized = resample::resize_cancellable(&source(), 130, 37, ResizeFilter::Lanczos3, false, false, &cancel);
+    assert_eq!(resized.err(), Some(smix::cancel::Cancelled));
+}
@@ -0,0 +1,42 @@
+//! Trimmed exports report where their crop ended up in the written file.
+
+use image::{Rgba, Rgba32FImage};
+use smix::{canvas::{Anchor, Canvas}, post::{Crop, Placement}, ExportOptions, GeneratedImage};
+
+/// An 8x8 image with only a 2x2 block at (5, 1) visible.
+fn block() -> GeneratedImage {
+    GeneratedImage::new(Rgba32FImage::from_fn(8, 8, |x, y| {
+        let visible = (5..7).contains(&x) && (1..3).contains(&y);
+        Rgba([1.0, 1.0, 1.0, if visible { 1.0 } else { 0.0 }])
+    }))
+}
+
+fn placement(options: &ExportOptions) -> Option<Placement> {
+    block().encode_with_placement(8, 8, options).unwrap().1
+}
+
+#[test]
+fn trimmed_crop_fills_the_output() {
+    let options = ExportOptions { trim: Some(0), ..ExportOptions::default() };
+    let crop = Crop { x: 5, y: 1, width: 2, height: 2 };
+    assert_eq!(placement(&options), Some(Placement { crop, x: 0, y: 0, width: 2, height: 2 }));
+}
+
+#[test]
+fn canvas_moves_the_crop() {
+    let options = ExportOptions {
+        trim: Some(0),
+        canvas: Some(Canvas::Size(6, 4)),
+        anchor: Anchor::BottomRight,
+        padding: Some(2),
+        ..ExportOptions::default()
+    };
+    let crop = Crop { x: 5, y: 1, width: 2, height: 2 };
+    assert_eq!(placement(&options), Some(Placement { crop, x: 4, y: 2, width: 6, height: 4 }));
+}
+
+#[test]
+fn untrimmed_exports_have_no_placement() {
+    let options = ExportOptions { canvas: Some(Canvas::PowerOfTwo), ..ExportOptions::default() };
+    assert_eq!(placement(&options), None);
+}